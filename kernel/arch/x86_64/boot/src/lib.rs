@@ -1,38 +1,90 @@
 #![no_std]
 
+mod relocate;
+
+pub use relocate::is_relocated;
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
 use kernel_boot_interface::{
+    cmdline::BootCmdline,
     framebuf,
     hhdm::{self, BootHhdm},
+    kernel::BootKernelAddr,
     memmap, BootInfo,
 };
 use lazy_static::lazy_static;
-use limine::{FramebufferRequest, HhdmRequest, MemmapRequest};
+use limine::{
+    FramebufferRequest, HhdmRequest, KernelAddressRequest, KernelFileRequest, MemmapRequest,
+    RsdpRequest,
+};
+use teensy_std::addr::{PhysAddr, VirtAddr};
 
 static MEMMAP_REQUEST: MemmapRequest = MemmapRequest::new(0);
 static FRAMEBUFFER_REQUEST: FramebufferRequest = FramebufferRequest::new(0);
 static HHDM_REQUEST: HhdmRequest = HhdmRequest::new(0);
+static KERNEL_ADDRESS_REQUEST: KernelAddressRequest = KernelAddressRequest::new(0);
+static KERNEL_FILE_REQUEST: KernelFileRequest = KernelFileRequest::new(0);
+static RSDP_REQUEST: RsdpRequest = RsdpRequest::new(0);
+
+/// Where the kernel is linked to start, as set in linker.ld.
+pub const KERNEL_LINK_BASE: VirtAddr = VirtAddr::new(0xffff_ffff_8000_0000);
+/// The HHDM maps at least this much, even past the end of the memory map.
+const HHDM_MIN_LEN: usize = 4 << 30;
+
+/// Set once the memory holding limine's responses may be handed out.
+static BOOTLOADER_RELEASED: AtomicBool = AtomicBool::new(false);
 
 lazy_static! {
     static ref BOOT_INFO: BootInfo = retrieve_boot_info();
 }
 
+/// Relocates the kernel if the bootloader did not, so it must be the first
+/// thing the kernel calls.
 pub fn arch_init() -> &'static BootInfo {
+    if !relocate::is_relocated() {
+        unsafe { relocate::relocate(get_kernel_addr().slide()) };
+    }
     return &BOOT_INFO;
 }
 
+/// Makes sure everything the kernel needs has been copied out of limine's
+/// responses, after which bootloader reclaimable memory is free to reuse.
+pub fn release_bootloader() -> &'static BootInfo {
+    let boot_info = arch_init();
+    BOOTLOADER_RELEASED.store(true, Ordering::SeqCst);
+    boot_info
+}
+
 fn retrieve_boot_info() -> BootInfo {
     let memmap = get_memmap();
-    let hhdm = get_hhdm();
+    let hhdm = get_hhdm(&memmap);
     let frame_buffer = get_framebuffer(&hhdm);
+    let kernel_addr = get_kernel_addr();
+    let cmdline = get_cmdline();
+    let rsdp = get_rsdp(&hhdm);
 
     BootInfo {
         memmap,
         frame_buffer,
         hhdm,
+        kernel_addr,
+        cmdline,
+        rsdp,
     }
 }
 
+/// Limine's responses live in bootloader reclaimable memory, so each read of
+/// one checks it was not handed out yet.
+fn assert_not_released() {
+    assert!(
+        !BOOTLOADER_RELEASED.load(Ordering::SeqCst),
+        "Limine responses read after bootloader memory was released"
+    );
+}
+
 fn get_memmap() -> memmap::Memmap {
+    assert_not_released();
     if let Some(memmap_response) = MEMMAP_REQUEST.get_response().get() {
         debug_assert!(memmap_response.entry_count <= memmap::MAX_MEM_REGIONS as u64);
         let mut memmap: memmap::Memmap = unsafe { core::mem::zeroed() };
@@ -51,10 +103,13 @@ fn get_memmap() -> memmap::Memmap {
 }
 
 fn get_framebuffer(hhdm: &BootHhdm) -> framebuf::BootFrameBuf {
+    assert_not_released();
     if let Some(framebuffer_response) = FRAMEBUFFER_REQUEST.get_response().get() {
         let framebuffer = &framebuffer_response.framebuffers()[0];
         framebuf::BootFrameBuf {
-            phys_address: framebuffer.address.as_ptr().unwrap() as usize - hhdm.base,
+            phys_address: hhdm
+                .virt_to_phys(VirtAddr::from_ptr(framebuffer.address.as_ptr().unwrap()))
+                .expect("The framebuffer is in the HHDM"),
             width: framebuffer.width,
             height: framebuffer.height,
             pitch: framebuffer.pitch,
@@ -65,30 +120,68 @@ fn get_framebuffer(hhdm: &BootHhdm) -> framebuf::BootFrameBuf {
     }
 }
 
-fn get_hhdm() -> hhdm::BootHhdm {
+/// Limine maps the first 4 GiB and every memory map entry in the HHDM.
+fn get_hhdm(memmap: &memmap::Memmap) -> hhdm::BootHhdm {
+    assert_not_released();
     let hhdm_response = HHDM_REQUEST
         .get_response()
         .get()
         .expect("No hhdm response from limine.");
     hhdm::BootHhdm {
         base: hhdm_response.offset as usize,
+        len: memmap
+            .iter()
+            .map(|entry| entry.end().as_usize())
+            .fold(HHDM_MIN_LEN, usize::max),
     }
 }
 
+fn get_kernel_addr() -> BootKernelAddr {
+    assert_not_released();
+    let kernel_address_response = KERNEL_ADDRESS_REQUEST
+        .get_response()
+        .get()
+        .expect("No kernel address response from limine.");
+    BootKernelAddr {
+        phys_base: PhysAddr::new(kernel_address_response.physical_base as usize),
+        virt_base: VirtAddr::new(kernel_address_response.virtual_base as usize),
+        link_base: KERNEL_LINK_BASE,
+    }
+}
+
+fn get_rsdp(hhdm: &BootHhdm) -> Option<PhysAddr> {
+    assert_not_released();
+    let address = RSDP_REQUEST.get_response().get()?.address.as_ptr()?;
+    hhdm.virt_to_phys(VirtAddr::from_ptr(address))
+}
+
+/// An empty command line if limine has none for us.
+fn get_cmdline() -> BootCmdline {
+    assert_not_released();
+    let cmdline = KERNEL_FILE_REQUEST
+        .get_response()
+        .get()
+        .and_then(|response| response.kernel_file.get())
+        .and_then(|file| file.cmdline.to_str())
+        .and_then(|cmdline| cmdline.to_str().ok())
+        .unwrap_or("");
+    BootCmdline::new(cmdline)
+}
+
 fn convert_memmap_entry(entry: &limine::MemmapEntry) -> memmap::MemmapEntry {
     let typ = match entry.typ {
         limine::MemoryMapEntryType::Usable => memmap::BootMemType::Usable,
         limine::MemoryMapEntryType::Reserved => memmap::BootMemType::Reserved,
         limine::MemoryMapEntryType::AcpiReclaimable => memmap::BootMemType::AcpiReclaimable,
         limine::MemoryMapEntryType::AcpiNvs => memmap::BootMemType::AcpiNvs,
-        limine::MemoryMapEntryType::BadMemory => memmap::BootMemType::Reserved,
+        limine::MemoryMapEntryType::BadMemory => memmap::BootMemType::BadMemory,
         limine::MemoryMapEntryType::BootloaderReclaimable => memmap::BootMemType::BootloaderReclaimable,
-        limine::MemoryMapEntryType::KernelAndModules => memmap::BootMemType::Reserved,
-        limine::MemoryMapEntryType::Framebuffer => memmap::BootMemType::Reserved,
+        limine::MemoryMapEntryType::KernelAndModules => memmap::BootMemType::KernelAndModules,
+        limine::MemoryMapEntryType::Framebuffer => memmap::BootMemType::Framebuffer,
     };
 
     memmap::MemmapEntry {
-        base: entry.base as usize,
+        base: PhysAddr::new(entry.base as usize),
         len: entry.len as usize,
         typ,
     }
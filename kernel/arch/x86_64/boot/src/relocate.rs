@@ -0,0 +1,80 @@
+//! Applying the kernel's own relocations. The kernel is linked as a position
+//! independent executable and limine relocates it when it applies KASLR, but
+//! nothing in the boot protocol promises it will.
+
+use core::{mem, ptr};
+
+const DT_NULL: i64 = 0;
+const DT_RELA: i64 = 7;
+const DT_RELASZ: i64 = 8;
+const DT_RELAENT: i64 = 9;
+
+const R_X86_64_RELATIVE: u32 = 8;
+
+#[repr(C)]
+struct Dyn {
+    tag: i64,
+    val: u64,
+}
+
+#[repr(C)]
+struct Rela {
+    offset: u64,
+    info: u64,
+    addend: i64,
+}
+
+extern "C" {
+    /// The dynamic section, defined by the linker.
+    static _DYNAMIC: Dyn;
+}
+
+static ANCHOR: u8 = 0;
+/// Points at ANCHOR only once the relocations have been applied.
+static ANCHOR_REF: &u8 = &ANCHOR;
+
+/// Whether the relocations have been applied.
+pub fn is_relocated() -> bool {
+    // Read the raw bits, an unrelocated ANCHOR_REF is not a valid reference.
+    let target = unsafe { ptr::read_volatile(ptr::addr_of!(ANCHOR_REF).cast::<usize>()) };
+    target == ptr::addr_of!(ANCHOR) as usize
+}
+
+/// Applies the relocations of an image moved SLIDE bytes from where it was
+/// linked, unless the bootloader already did. Only relative relocations are
+/// supported, which is all a static PIE has.
+///
+/// # Safety
+/// Must run before anything reads a pointer stored in a static.
+pub unsafe fn relocate(slide: usize) {
+    if is_relocated() {
+        return;
+    }
+
+    let mut rela = 0;
+    let mut rela_size = 0;
+    let mut rela_ent = mem::size_of::<Rela>();
+    let mut entry = ptr::addr_of!(_DYNAMIC);
+    while (*entry).tag != DT_NULL {
+        match (*entry).tag {
+            DT_RELA => rela = (*entry).val as usize,
+            DT_RELASZ => rela_size = (*entry).val as usize,
+            DT_RELAENT => rela_ent = (*entry).val as usize,
+            _ => {}
+        }
+        entry = entry.add(1);
+    }
+
+    // The dynamic section holds link addresses.
+    let table = rela.wrapping_add(slide) as *const u8;
+    for idx in 0..rela_size / rela_ent {
+        let rela = &*table.add(idx * rela_ent).cast::<Rela>();
+        // Nothing is relocated yet, so there is no printing the type.
+        assert!(
+            rela.info as u32 == R_X86_64_RELATIVE,
+            "unsupported kernel relocation"
+        );
+        let target = (rela.offset as usize).wrapping_add(slide) as *mut usize;
+        target.write((rela.addend as usize).wrapping_add(slide));
+    }
+}
@@ -0,0 +1,301 @@
+//! Entry points for all 256 vectors. Each one saves the general purpose
+//! registers and calls `exception_dispatch`. That handles page faults and
+//! breakpoints, dumps every other CPU exception before panicking and passes
+//! the vectors from 32 up to the handler set with `set_interrupt_handler`.
+
+use core::{arch::global_asm, fmt};
+
+use kernel_log::{kprintln, kprintln_forced};
+
+use crate::{idt, probe, InterruptStackFrame, PageFault, PageFaultError};
+
+pub const NUM_EXCEPTIONS: usize = 32;
+pub const BREAKPOINT_VECTOR: u8 = 3;
+pub const DOUBLE_FAULT_VECTOR: u8 = 8;
+pub const PAGE_FAULT_VECTOR: u8 = 14;
+/// Bytes between the entry stubs, so that the stub of vector N is at
+/// `__exception_stubs + N * EXCEPTION_STUB_SIZE`.
+pub(crate) const EXCEPTION_STUB_SIZE: usize = 16;
+
+const EXCEPTION_NAMES: [&str; NUM_EXCEPTIONS] = [
+    "Divide Error",
+    "Debug",
+    "Non-Maskable Interrupt",
+    "Breakpoint",
+    "Overflow",
+    "BOUND Range Exceeded",
+    "Invalid Opcode",
+    "Device Not Available",
+    "Double Fault",
+    "Coprocessor Segment Overrun",
+    "Invalid TSS",
+    "Segment Not Present",
+    "Stack-Segment Fault",
+    "General Protection",
+    "Page Fault",
+    "Reserved",
+    "x87 Floating-Point",
+    "Alignment Check",
+    "Machine Check",
+    "SIMD Floating-Point",
+    "Virtualization",
+    "Control Protection",
+    "Reserved",
+    "Reserved",
+    "Reserved",
+    "Reserved",
+    "Reserved",
+    "Reserved",
+    "Hypervisor Injection",
+    "VMM Communication",
+    "Security",
+    "Reserved",
+];
+
+// The CPU pushes an error code for some exceptions only. The stubs of the
+// others, and of every interrupt, push a zero in its place so that every
+// vector leaves the same frame.
+global_asm!(
+    ".macro exception_stub vector, error_code",
+    "    .p2align 4",
+    "    .if \\error_code == 0",
+    "    push 0",
+    "    .endif",
+    "    push \\vector",
+    "    jmp __exception_common",
+    ".endm",
+    "",
+    ".global __exception_stubs",
+    ".p2align 4",
+    "__exception_stubs:",
+    "exception_stub 0, 0",
+    "exception_stub 1, 0",
+    "exception_stub 2, 0",
+    "exception_stub 3, 0",
+    "exception_stub 4, 0",
+    "exception_stub 5, 0",
+    "exception_stub 6, 0",
+    "exception_stub 7, 0",
+    "exception_stub 8, 1",
+    "exception_stub 9, 0",
+    "exception_stub 10, 1",
+    "exception_stub 11, 1",
+    "exception_stub 12, 1",
+    "exception_stub 13, 1",
+    "exception_stub 14, 1",
+    "exception_stub 15, 0",
+    "exception_stub 16, 0",
+    "exception_stub 17, 1",
+    "exception_stub 18, 0",
+    "exception_stub 19, 0",
+    "exception_stub 20, 0",
+    "exception_stub 21, 1",
+    "exception_stub 22, 0",
+    "exception_stub 23, 0",
+    "exception_stub 24, 0",
+    "exception_stub 25, 0",
+    "exception_stub 26, 0",
+    "exception_stub 27, 0",
+    "exception_stub 28, 0",
+    "exception_stub 29, 1",
+    "exception_stub 30, 1",
+    "exception_stub 31, 0",
+    ".set vector, 32",
+    ".rept 256 - 32",
+    "exception_stub vector, 0",
+    ".set vector, vector + 1",
+    ".endr",
+    "",
+    // The CPU aligned the stack to 16 bytes before pushing its 5 words. With
+    // the error code, the vector and 15 registers it is aligned again here.
+    "__exception_common:",
+    "    push rax",
+    "    push rbx",
+    "    push rcx",
+    "    push rdx",
+    "    push rsi",
+    "    push rdi",
+    "    push rbp",
+    "    push r8",
+    "    push r9",
+    "    push r10",
+    "    push r11",
+    "    push r12",
+    "    push r13",
+    "    push r14",
+    "    push r15",
+    "    mov rdi, rsp",
+    "    cld",
+    "    call {dispatch}",
+    "    pop r15",
+    "    pop r14",
+    "    pop r13",
+    "    pop r12",
+    "    pop r11",
+    "    pop r10",
+    "    pop r9",
+    "    pop r8",
+    "    pop rbp",
+    "    pop rdi",
+    "    pop rsi",
+    "    pop rdx",
+    "    pop rcx",
+    "    pop rbx",
+    "    pop rax",
+    // Drop the vector and the error code.
+    "    add rsp, 16",
+    "    iretq",
+    dispatch = sym exception_dispatch,
+);
+
+extern "C" {
+    static __exception_stubs: u8;
+}
+
+/// The general purpose registers at the time of the exception.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Registers {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+}
+
+/// Everything on the stack when an exception is dispatched. Changes to it
+/// are what the interrupted code resumes with.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct ExceptionFrame {
+    pub regs: Registers,
+    pub vector: u64,
+    /// Zero for the exceptions that have none.
+    pub error_code: u64,
+    pub frame: InterruptStackFrame,
+}
+
+/// Name of exception VECTOR, as the Intel manual has it.
+pub fn exception_name(vector: u8) -> &'static str {
+    EXCEPTION_NAMES
+        .get(vector as usize)
+        .copied()
+        .unwrap_or("Unknown")
+}
+
+/// Address of the entry stub of VECTOR.
+pub(crate) fn exception_stub(vector: u8) -> usize {
+    core::ptr::addr_of!(__exception_stubs) as usize + vector as usize * EXCEPTION_STUB_SIZE
+}
+
+extern "C" fn exception_dispatch(frame: &mut ExceptionFrame) {
+    let vector = frame.vector as u8;
+    if vector as usize >= NUM_EXCEPTIONS {
+        interrupt(vector);
+        return;
+    }
+    match vector {
+        PAGE_FAULT_VECTOR => {
+            if page_fault(frame) {
+                return;
+            }
+        }
+        BREAKPOINT_VECTOR => {
+            kprintln!("{}", frame);
+            return;
+        }
+        _ => {}
+    }
+    // Whatever was interrupted may hold the serial port, and is not coming
+    // back to release it.
+    kprintln_forced!("{}", frame);
+    if vector == DOUBLE_FAULT_VECTOR {
+        if let Some(handler) = idt::double_fault_handler() {
+            handler(&frame.frame);
+        }
+    }
+    panic!(
+        "CPU exception {} (#{}) at {:#x}",
+        exception_name(vector),
+        vector,
+        frame.frame.rip
+    );
+}
+
+fn interrupt(vector: u8) {
+    match idt::interrupt_handler() {
+        Some(handler) => handler(vector),
+        None => {
+            kprintln!("Unexpected interrupt on vector {:#x}", vector);
+        }
+    }
+}
+
+/// Returns whether the fault was dealt with, either by a probe or by the
+/// kernel's handler.
+fn page_fault(frame: &mut ExceptionFrame) -> bool {
+    let fault = PageFault {
+        addr: crate::cr2(),
+        error: PageFaultError::new(frame.error_code),
+        frame: frame.frame,
+    };
+    if let Some(fixup) = probe::fixup(frame.frame.rip) {
+        probe::record(fault);
+        frame.frame.rip = fixup;
+        return true;
+    }
+    match idt::page_fault_handler() {
+        Some(handler) => {
+            handler(&fault);
+            true
+        }
+        None => false,
+    }
+}
+
+impl fmt::Display for ExceptionFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let regs = &self.regs;
+        let frame = &self.frame;
+        writeln!(
+            f,
+            "EXCEPTION: {} (#{}), error code {:#x}",
+            exception_name(self.vector as u8),
+            self.vector,
+            self.error_code
+        )?;
+        writeln!(
+            f,
+            "  rip {:#018x} cs {:#06x} rflags {:#018x}",
+            frame.rip, frame.cs, frame.rflags
+        )?;
+        writeln!(f, "  rsp {:#018x} ss {:#06x}", frame.rsp, frame.ss)?;
+        writeln!(f, "  cr2 {:#018x} cr3 {:#018x}", crate::cr2(), crate::cr3())?;
+        let rows = [
+            [("rax", regs.rax), ("rbx", regs.rbx), ("rcx", regs.rcx)],
+            [("rdx", regs.rdx), ("rsi", regs.rsi), ("rdi", regs.rdi)],
+            [("rbp", regs.rbp), ("r8", regs.r8), ("r9", regs.r9)],
+            [("r10", regs.r10), ("r11", regs.r11), ("r12", regs.r12)],
+            [("r13", regs.r13), ("r14", regs.r14), ("r15", regs.r15)],
+        ];
+        for (idx, row) in rows.iter().enumerate() {
+            for (name, value) in row {
+                write!(f, "  {:>3} {:#018x}", name, value)?;
+            }
+            if idx + 1 < rows.len() {
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
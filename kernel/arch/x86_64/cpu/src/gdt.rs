@@ -0,0 +1,185 @@
+//! The kernel's own GDT, replacing limine's, and the TSS holding the stacks
+//! the CPU switches to on interrupts.
+
+use core::{arch::asm, mem, ptr};
+
+use crate::DescriptorTablePointer;
+
+pub const KERNEL_CODE_SELECTOR: u16 = 0x08;
+pub const KERNEL_DATA_SELECTOR: u16 = 0x10;
+/// User data comes before user code, the order `sysret` expects.
+pub const USER_DATA_SELECTOR: u16 = 0x18 | 3;
+pub const USER_CODE_SELECTOR: u16 = 0x20 | 3;
+pub const TSS_SELECTOR: u16 = 0x28;
+
+const KERNEL_CODE: u64 = 0x00af_9a00_0000_ffff;
+const KERNEL_DATA: u64 = 0x00cf_9200_0000_ffff;
+const USER_CODE: u64 = 0x00af_fa00_0000_ffff;
+const USER_DATA: u64 = 0x00cf_f200_0000_ffff;
+/// Present, available 64-bit TSS.
+const TSS_AVAILABLE: u64 = 0x89;
+
+const IST_STACK_SIZE: usize = 5 * 4096;
+const NUM_GDT_ENTRIES: usize = 7;
+
+static GDT: spin::Once<Gdt> = spin::Once::new();
+static mut TSS: TaskStateSegment = TaskStateSegment::new();
+static mut IST_STACKS: [IstStackMemory; IstStack::ALL.len()] =
+    [IstStackMemory([0; IST_STACK_SIZE]); IstStack::ALL.len()];
+
+/// The stacks of the Interrupt Stack Table. Exceptions that may hit with the
+/// current stack unusable run on one of these, whatever the stack was.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum IstStack {
+    DoubleFault = 1,
+    Nmi = 2,
+    MachineCheck = 3,
+}
+
+#[repr(C, packed(4))]
+struct TaskStateSegment {
+    reserved_1: u32,
+    /// Stacks for entering ring 0 to 2 from a less privileged ring.
+    privilege_stacks: [u64; 3],
+    reserved_2: u64,
+    /// Stacks 1 to 7 of the Interrupt Stack Table.
+    ist: [u64; 7],
+    reserved_3: u64,
+    reserved_4: u16,
+    iomap_base: u16,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C, align(16))]
+struct IstStackMemory([u8; IST_STACK_SIZE]);
+
+#[repr(C, align(16))]
+struct Gdt([u64; NUM_GDT_ENTRIES]);
+
+impl IstStack {
+    pub const ALL: [IstStack; 3] = [IstStack::DoubleFault, IstStack::Nmi, IstStack::MachineCheck];
+
+    /// Index of the stack in the Interrupt Stack Table, as gates name it.
+    pub const fn index(self) -> u8 {
+        self as u8
+    }
+}
+
+impl TaskStateSegment {
+    const fn new() -> Self {
+        Self {
+            reserved_1: 0,
+            privilege_stacks: [0; 3],
+            reserved_2: 0,
+            ist: [0; 7],
+            reserved_3: 0,
+            reserved_4: 0,
+            // No I/O permission bitmap.
+            iomap_base: mem::size_of::<TaskStateSegment>() as u16,
+        }
+    }
+}
+
+/// Builds the GDT and the TSS, loads them and reloads every segment register.
+/// Later calls only load them again.
+pub fn init_gdt() {
+    let gdt = GDT.call_once(|| {
+        let tss = unsafe {
+            let tss = ptr::addr_of_mut!(TSS);
+            for stack in IstStack::ALL {
+                let memory = ptr::addr_of_mut!(IST_STACKS[stack.index() as usize - 1]);
+                (*tss).ist[stack.index() as usize - 1] =
+                    memory as u64 + mem::size_of::<IstStackMemory>() as u64;
+            }
+            tss as u64
+        };
+        let (tss_low, tss_high) = tss_descriptor(tss);
+        Gdt([
+            0,
+            KERNEL_CODE,
+            KERNEL_DATA,
+            USER_DATA,
+            USER_CODE,
+            tss_low,
+            tss_high,
+        ])
+    });
+
+    let gdtr = DescriptorTablePointer {
+        limit: (mem::size_of::<Gdt>() - 1) as u16,
+        base: gdt as *const Gdt as u64,
+    };
+    unsafe {
+        asm!("lgdt [{}]", in(reg) &gdtr, options(readonly, nostack, preserves_flags));
+        // A far return is the only way to load CS in long mode.
+        asm!(
+            "push {cs}",
+            "lea {tmp}, [rip + 2f]",
+            "push {tmp}",
+            "retfq",
+            "2:",
+            "mov ds, {ds:x}",
+            "mov es, {ds:x}",
+            "mov ss, {ds:x}",
+            "mov fs, {null:x}",
+            "mov gs, {null:x}",
+            cs = in(reg) KERNEL_CODE_SELECTOR as u64,
+            ds = in(reg) KERNEL_DATA_SELECTOR,
+            null = in(reg) 0u16,
+            tmp = out(reg) _,
+            options(preserves_flags)
+        );
+        // Loading the TSS marks its descriptor busy and loading a busy one
+        // faults, so it is only loaded once.
+        if task_register() != TSS_SELECTOR {
+            asm!("ltr {:x}", in(reg) TSS_SELECTOR, options(nostack, preserves_flags));
+        }
+    }
+}
+
+/// The two GDT entries describing the TSS at BASE.
+fn tss_descriptor(base: u64) -> (u64, u64) {
+    let limit = (mem::size_of::<TaskStateSegment>() - 1) as u64;
+    let low = (limit & 0xffff)
+        | (base & 0xff_ffff) << 16
+        | TSS_AVAILABLE << 40
+        | (limit >> 16 & 0xf) << 48
+        | (base >> 24 & 0xff) << 56;
+    (low, base >> 32)
+}
+
+/// Address just past the top of STACK.
+pub fn ist_stack_top(stack: IstStack) -> usize {
+    unsafe { ptr::addr_of!(TSS.ist[stack.index() as usize - 1]).read_unaligned() as usize }
+}
+
+/// Sets the stack the CPU switches to when an interrupt arrives in user mode.
+pub fn set_kernel_stack(top: usize) {
+    unsafe { ptr::addr_of_mut!(TSS.privilege_stacks[0]).write_unaligned(top as u64) };
+}
+
+pub fn code_segment() -> u16 {
+    let cs: u16;
+    unsafe {
+        asm!("mov {:x}, cs", out(reg) cs, options(nomem, nostack, preserves_flags));
+    }
+    cs
+}
+
+pub fn stack_segment() -> u16 {
+    let ss: u16;
+    unsafe {
+        asm!("mov {:x}, ss", out(reg) ss, options(nomem, nostack, preserves_flags));
+    }
+    ss
+}
+
+/// The selector of the loaded TSS.
+pub fn task_register() -> u16 {
+    let tr: u16;
+    unsafe {
+        asm!("str {:x}", out(reg) tr, options(nomem, nostack, preserves_flags));
+    }
+    tr
+}
@@ -0,0 +1,204 @@
+use core::{arch::asm, fmt, mem};
+
+use crate::{exception, DescriptorTablePointer, IstStack, KERNEL_CODE_SELECTOR};
+
+const NUM_VECTORS: usize = 256;
+/// Present, DPL 0, 64-bit interrupt gate.
+const GATE_INTERRUPT: u8 = 0x8e;
+
+static IDT: spin::Once<Idt> = spin::Once::new();
+static PAGE_FAULT_HANDLER: spin::Once<fn(&PageFault)> = spin::Once::new();
+static INTERRUPT_HANDLER: spin::Once<fn(u8)> = spin::Once::new();
+static DOUBLE_FAULT_HANDLER: spin::Once<fn(&InterruptStackFrame)> = spin::Once::new();
+
+/// What the CPU pushes before calling an interrupt handler.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct InterruptStackFrame {
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+/// The error code of a page fault.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct PageFaultError(u64);
+
+impl PageFaultError {
+    pub(crate) const fn new(error_code: u64) -> Self {
+        Self(error_code)
+    }
+
+    /// The page was present, so the access broke its permissions.
+    pub const fn present(self) -> bool {
+        self.0 & (1 << 0) != 0
+    }
+
+    pub const fn write(self) -> bool {
+        self.0 & (1 << 1) != 0
+    }
+
+    pub const fn user(self) -> bool {
+        self.0 & (1 << 2) != 0
+    }
+
+    /// A reserved bit was set in one of the paging structures.
+    pub const fn reserved_bit(self) -> bool {
+        self.0 & (1 << 3) != 0
+    }
+
+    pub const fn instruction_fetch(self) -> bool {
+        self.0 & (1 << 4) != 0
+    }
+}
+
+impl fmt::Debug for PageFaultError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} in {} mode{} ({:#x})",
+            if self.present() {
+                "protection violation"
+            } else {
+                "non-present page"
+            },
+            if self.instruction_fetch() {
+                "fetch"
+            } else if self.write() {
+                "write"
+            } else {
+                "read"
+            },
+            if self.user() { "user" } else { "kernel" },
+            if self.reserved_bit() {
+                ", reserved bit set"
+            } else {
+                ""
+            },
+            self.0
+        )
+    }
+}
+
+/// Everything known about a page fault.
+#[derive(Debug, Clone, Copy)]
+pub struct PageFault {
+    /// The address whose access faulted, read from CR2.
+    pub addr: usize,
+    pub error: PageFaultError,
+    pub frame: InterruptStackFrame,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct GateDescriptor {
+    offset_low: u16,
+    selector: u16,
+    ist: u8,
+    attributes: u8,
+    offset_mid: u16,
+    offset_high: u32,
+    reserved: u32,
+}
+
+impl GateDescriptor {
+    const fn missing() -> Self {
+        Self {
+            offset_low: 0,
+            selector: 0,
+            ist: 0,
+            attributes: 0,
+            offset_mid: 0,
+            offset_high: 0,
+            reserved: 0,
+        }
+    }
+
+    fn interrupt(handler: usize, selector: u16) -> Self {
+        Self {
+            offset_low: handler as u16,
+            selector,
+            ist: 0,
+            attributes: GATE_INTERRUPT,
+            offset_mid: (handler >> 16) as u16,
+            offset_high: (handler >> 32) as u32,
+            reserved: 0,
+        }
+    }
+
+    /// Makes the CPU switch to STACK before calling the handler.
+    const fn on_stack(mut self, stack: IstStack) -> Self {
+        self.ist = stack.index();
+        self
+    }
+}
+
+#[repr(C, align(16))]
+struct Idt([GateDescriptor; NUM_VECTORS]);
+
+/// Loads the kernel's GDT, then builds the IDT with a gate for every vector
+/// and loads it. Double faults, NMIs and machine checks run on their own
+/// stacks, so that they are reported even when the stack is gone.
+pub fn init_idt() {
+    crate::init_gdt();
+    let idt = IDT.call_once(|| {
+        let cs = KERNEL_CODE_SELECTOR;
+        let mut idt = Idt([GateDescriptor::missing(); NUM_VECTORS]);
+        for vector in 0..=u8::MAX {
+            let gate = GateDescriptor::interrupt(exception::exception_stub(vector), cs);
+            idt.0[vector as usize] = match vector {
+                2 => gate.on_stack(IstStack::Nmi),
+                8 => gate.on_stack(IstStack::DoubleFault),
+                18 => gate.on_stack(IstStack::MachineCheck),
+                _ => gate,
+            };
+        }
+        idt
+    });
+
+    let idtr = DescriptorTablePointer {
+        limit: (mem::size_of::<Idt>() - 1) as u16,
+        base: idt as *const Idt as u64,
+    };
+    unsafe {
+        asm!("lidt [{}]", in(reg) &idtr, options(readonly, nostack, preserves_flags));
+    }
+}
+
+/// HANDLER is called on every page fault outside of a probe and returning
+/// from it retries the faulting access, so it must either fix the mapping or
+/// panic. Only the first handler set is kept.
+pub fn set_page_fault_handler(handler: fn(&PageFault)) {
+    PAGE_FAULT_HANDLER.call_once(|| handler);
+}
+
+pub(crate) fn page_fault_handler() -> Option<fn(&PageFault)> {
+    PAGE_FAULT_HANDLER.get().copied()
+}
+
+/// HANDLER is called with the vector of every interrupt, that is every
+/// vector from 32 up, with interrupts disabled. It must signal the end of the
+/// interrupt to the interrupt controller itself. Only the first handler set
+/// is kept.
+pub fn set_interrupt_handler(handler: fn(u8)) {
+    INTERRUPT_HANDLER.call_once(|| handler);
+}
+
+pub(crate) fn interrupt_handler() -> Option<fn(u8)> {
+    INTERRUPT_HANDLER.get().copied()
+}
+
+/// HANDLER is called on a double fault, after the registers were dumped and
+/// before the generic panic. It may panic with a better diagnosis, such as a
+/// page fault the CPU could not deliver because the stack ran into its guard
+/// page, in which case cr2 still holds the faulting address. Only the first
+/// handler set is kept.
+pub fn set_double_fault_handler(handler: fn(&InterruptStackFrame)) {
+    DOUBLE_FAULT_HANDLER.call_once(|| handler);
+}
+
+pub(crate) fn double_fault_handler() -> Option<fn(&InterruptStackFrame)> {
+    DOUBLE_FAULT_HANDLER.get().copied()
+}
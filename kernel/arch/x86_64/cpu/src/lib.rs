@@ -1,7 +1,25 @@
 #![no_std]
 
+mod exception;
+mod gdt;
+mod idt;
+mod probe;
+
 use core::arch::asm;
 
+pub use exception::{
+    exception_name, ExceptionFrame, Registers, BREAKPOINT_VECTOR, DOUBLE_FAULT_VECTOR,
+    NUM_EXCEPTIONS, PAGE_FAULT_VECTOR,
+};
+pub use gdt::*;
+pub use idt::*;
+pub use probe::*;
+
+const IA32_EFER: u32 = 0xc000_0080;
+const EFER_NXE: u64 = 1 << 11;
+const CR0_WP: usize = 1 << 16;
+const RFLAGS_IF: u64 = 1 << 9;
+
 pub fn hcf() -> ! {
     unsafe {
         asm!("cli");
@@ -10,3 +28,166 @@ pub fn hcf() -> ! {
         }
     }
 }
+
+/// Reads the time stamp counter.
+pub fn rdtsc() -> u64 {
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+/// Current value of the stack pointer.
+pub fn stack_pointer() -> usize {
+    let rsp: usize;
+    unsafe {
+        asm!("mov {}, rsp", out(reg) rsp, options(nomem, nostack, preserves_flags));
+    }
+    rsp
+}
+
+/// Address of the last page fault.
+pub fn cr2() -> usize {
+    let cr2: usize;
+    unsafe {
+        asm!("mov {}, cr2", out(reg) cr2, options(nomem, nostack, preserves_flags));
+    }
+    cr2
+}
+
+/// Physical address of the root page table and the PCID or flag bits.
+pub fn cr3() -> usize {
+    let cr3: usize;
+    unsafe {
+        asm!("mov {}, cr3", out(reg) cr3, options(nomem, nostack, preserves_flags));
+    }
+    cr3
+}
+
+/// Makes read only pages read only for the kernel as well.
+pub fn enable_write_protect() {
+    unsafe {
+        asm!(
+            "mov {tmp}, cr0",
+            "or {tmp}, {wp}",
+            "mov cr0, {tmp}",
+            tmp = out(reg) _,
+            wp = const CR0_WP,
+            options(nostack, preserves_flags)
+        );
+    }
+}
+
+/// Lets page table entries forbid instruction fetches.
+pub fn enable_no_execute() {
+    unsafe { wrmsr(IA32_EFER, rdmsr(IA32_EFER) | EFER_NXE) };
+}
+
+/// Reads the model specific register MSR.
+///
+/// # Safety
+/// MSR must exist on this CPU.
+pub unsafe fn rdmsr(msr: u32) -> u64 {
+    let (low, high): (u32, u32);
+    asm!(
+        "rdmsr",
+        in("ecx") msr,
+        out("eax") low,
+        out("edx") high,
+        options(nomem, nostack, preserves_flags)
+    );
+    (high as u64) << 32 | low as u64
+}
+
+/// Writes VALUE to the model specific register MSR.
+///
+/// # Safety
+/// MSR must exist on this CPU and VALUE must not break the kernel.
+pub unsafe fn wrmsr(msr: u32, value: u64) {
+    asm!(
+        "wrmsr",
+        in("ecx") msr,
+        in("eax") value as u32,
+        in("edx") (value >> 32) as u32,
+        options(nostack, preserves_flags)
+    );
+}
+
+/// Writes the byte VALUE to I/O port PORT.
+///
+/// # Safety
+/// Writing to the port must not break anything.
+pub unsafe fn outb(port: u16, value: u8) {
+    asm!("out dx, al", in("dx") port, in("al") value, options(nomem, nostack, preserves_flags));
+}
+
+/// Reads a byte from I/O port PORT.
+///
+/// # Safety
+/// Reading the port must not break anything.
+pub unsafe fn inb(port: u16) -> u8 {
+    let value: u8;
+    asm!("in al, dx", in("dx") port, out("al") value, options(nomem, nostack, preserves_flags));
+    value
+}
+
+/// Lets maskable interrupts in. Also a compiler barrier, so memory accesses
+/// are not moved out of the section interrupts were off for.
+pub fn enable_interrupts() {
+    unsafe { asm!("sti", options(nostack)) };
+}
+
+/// Keeps maskable interrupts out. Also a compiler barrier.
+pub fn disable_interrupts() {
+    unsafe { asm!("cli", options(nostack)) };
+}
+
+pub fn interrupts_enabled() -> bool {
+    let rflags: u64;
+    unsafe {
+        asm!("pushfq", "pop {}", out(reg) rflags, options(nomem, preserves_flags));
+    }
+    rflags & RFLAGS_IF != 0
+}
+
+/// Runs F with maskable interrupts off, then turns them back on if they were.
+pub fn without_interrupts<T>(f: impl FnOnce() -> T) -> T {
+    let enabled = interrupts_enabled();
+    if enabled {
+        disable_interrupts();
+    }
+    let ret = f();
+    if enabled {
+        enable_interrupts();
+    }
+    ret
+}
+
+#[repr(C, packed)]
+struct DescriptorTablePointer {
+    limit: u16,
+    base: u64,
+}
+
+/// Virtual address and size in bytes of the loaded GDT.
+pub fn gdt_region() -> (usize, usize) {
+    let mut gdtr = DescriptorTablePointer { limit: 0, base: 0 };
+    unsafe {
+        asm!("sgdt [{}]", in(reg) &mut gdtr, options(nostack, preserves_flags));
+    }
+    (gdtr.base as usize, gdtr.limit as usize + 1)
+}
+
+/// Moves onto the stack ending at TOP and calls ENTRY there. Nothing on the
+/// old stack is used again.
+///
+/// # Safety
+/// TOP must be the 16 byte aligned end of a mapped, writable stack which
+/// nothing else uses.
+pub unsafe fn switch_stack(top: usize, entry: extern "C" fn() -> !) -> ! {
+    asm!(
+        "mov rsp, {top}",
+        "xor ebp, ebp",
+        "call {entry}",
+        top = in(reg) top,
+        entry = in(reg) entry,
+        options(noreturn)
+    );
+}
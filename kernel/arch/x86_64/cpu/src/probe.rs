@@ -0,0 +1,55 @@
+//! Memory accesses which may fault. The page fault handler recognises the
+//! faulting instruction and resumes at its fixup, so the fault comes back to
+//! the caller instead of going to the kernel's handler.
+
+use core::{arch::global_asm, ptr};
+
+use crate::PageFault;
+
+static LAST_FAULT: spin::Mutex<Option<PageFault>> = spin::Mutex::new(None);
+
+global_asm!(
+    ".global __probe_write_u8",
+    ".global __probe_write_u8_insn",
+    ".global __probe_write_u8_fixup",
+    "__probe_write_u8:",
+    "__probe_write_u8_insn:",
+    "    mov byte ptr [rdi], sil",
+    "    xor eax, eax",
+    "    ret",
+    "__probe_write_u8_fixup:",
+    "    mov eax, 1",
+    "    ret",
+);
+
+extern "C" {
+    /// Returns 1 if the write faulted.
+    fn __probe_write_u8(addr: usize, value: u8) -> u32;
+    static __probe_write_u8_insn: u8;
+    static __probe_write_u8_fixup: u8;
+}
+
+/// Writes VALUE to ADDR and returns the page fault this caused, if any. The
+/// IDT must be loaded.
+///
+/// # Safety
+/// The write must not break anything if it does go through.
+pub unsafe fn probe_write_u8(addr: usize, value: u8) -> Result<(), PageFault> {
+    if __probe_write_u8(addr, value) == 0 {
+        return Ok(());
+    }
+    Err(LAST_FAULT
+        .lock()
+        .take()
+        .expect("A probe faulted without the fault being recorded"))
+}
+
+/// Where to resume after a page fault at RIP, if RIP is a probe.
+pub(crate) fn fixup(rip: u64) -> Option<u64> {
+    (rip == ptr::addr_of!(__probe_write_u8_insn) as u64)
+        .then_some(ptr::addr_of!(__probe_write_u8_fixup) as u64)
+}
+
+pub(crate) fn record(fault: PageFault) {
+    *LAST_FAULT.lock() = Some(fault);
+}
@@ -0,0 +1,156 @@
+//! IOAPICs, which turn the interrupt lines of devices into messages to the
+//! local APICs.
+
+use teensy_std::addr::VirtAddr;
+
+use crate::{Polarity, Trigger};
+
+const IOREGSEL: usize = 0x00;
+const IOWIN: usize = 0x10;
+
+const REG_ID: u32 = 0x00;
+const REG_VERSION: u32 = 0x01;
+const REG_REDIRECTION: u32 = 0x10;
+
+const REDIRECTION_ACTIVE_LOW: u64 = 1 << 13;
+const REDIRECTION_LEVEL: u64 = 1 << 15;
+const REDIRECTION_MASKED: u64 = 1 << 16;
+const REDIRECTION_DESTINATION_SHIFT: u64 = 56;
+
+/// Where an IOAPIC input goes. Delivery is fixed to one physical APIC ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Redirection {
+    pub vector: u8,
+    pub destination: u8,
+    pub polarity: Polarity,
+    pub trigger: Trigger,
+    pub masked: bool,
+}
+
+pub struct IoApic {
+    base: VirtAddr,
+    gsi_base: u32,
+    num_inputs: u32,
+}
+
+impl Redirection {
+    fn encode(&self) -> u64 {
+        let mut entry =
+            self.vector as u64 | (self.destination as u64) << REDIRECTION_DESTINATION_SHIFT;
+        if self.polarity == Polarity::ActiveLow {
+            entry |= REDIRECTION_ACTIVE_LOW;
+        }
+        if self.trigger == Trigger::Level {
+            entry |= REDIRECTION_LEVEL;
+        }
+        if self.masked {
+            entry |= REDIRECTION_MASKED;
+        }
+        entry
+    }
+
+    fn decode(entry: u64) -> Self {
+        Self {
+            vector: entry as u8,
+            destination: (entry >> REDIRECTION_DESTINATION_SHIFT) as u8,
+            polarity: if entry & REDIRECTION_ACTIVE_LOW != 0 {
+                Polarity::ActiveLow
+            } else {
+                Polarity::ActiveHigh
+            },
+            trigger: if entry & REDIRECTION_LEVEL != 0 {
+                Trigger::Level
+            } else {
+                Trigger::Edge
+            },
+            masked: entry & REDIRECTION_MASKED != 0,
+        }
+    }
+}
+
+impl IoApic {
+    /// Takes over the IOAPIC whose registers are mapped uncached at BASE and
+    /// which handles the GSIs from GSI_BASE up. Every input is masked.
+    ///
+    /// # Safety
+    /// BASE must map the IOAPIC and nothing else may program it.
+    pub unsafe fn new(base: VirtAddr, gsi_base: u32) -> Self {
+        let mut ioapic = Self {
+            base,
+            gsi_base,
+            num_inputs: 0,
+        };
+        ioapic.num_inputs = (ioapic.read(REG_VERSION) >> 16 & 0xff) + 1;
+        for input in 0..ioapic.num_inputs {
+            ioapic.write_redirection(input, REDIRECTION_MASKED);
+        }
+        ioapic
+    }
+
+    pub fn id(&self) -> u8 {
+        (self.read(REG_ID) >> 24 & 0xf) as u8
+    }
+
+    pub fn gsi_base(&self) -> u32 {
+        self.gsi_base
+    }
+
+    pub fn num_inputs(&self) -> u32 {
+        self.num_inputs
+    }
+
+    pub fn handles(&self, gsi: u32) -> bool {
+        (self.gsi_base..self.gsi_base + self.num_inputs).contains(&gsi)
+    }
+
+    /// The redirection of GSI, which the IOAPIC must handle.
+    pub fn redirection(&self, gsi: u32) -> Redirection {
+        Redirection::decode(self.read_redirection(gsi - self.gsi_base))
+    }
+
+    pub fn set_redirection(&mut self, gsi: u32, redirection: &Redirection) {
+        self.write_redirection(gsi - self.gsi_base, redirection.encode());
+    }
+
+    pub fn set_masked(&mut self, gsi: u32, masked: bool) {
+        let input = gsi - self.gsi_base;
+        let entry = self.read_redirection(input) & !REDIRECTION_MASKED;
+        let mask = if masked { REDIRECTION_MASKED } else { 0 };
+        self.write_redirection(input, entry | mask);
+    }
+
+    fn read_redirection(&self, input: u32) -> u64 {
+        debug_assert!(input < self.num_inputs);
+        let reg = REG_REDIRECTION + 2 * input;
+        self.read(reg) as u64 | (self.read(reg + 1) as u64) << 32
+    }
+
+    fn write_redirection(&mut self, input: u32, entry: u64) {
+        debug_assert!(input < self.num_inputs);
+        let reg = REG_REDIRECTION + 2 * input;
+        // Mask the input first, so that it never fires half programmed.
+        self.write(reg, REDIRECTION_MASKED as u32);
+        self.write(reg + 1, (entry >> 32) as u32);
+        self.write(reg, entry as u32);
+    }
+
+    fn read(&self, reg: u32) -> u32 {
+        unsafe {
+            (self.base + IOREGSEL)
+                .as_mut_ptr::<u32>()
+                .write_volatile(reg);
+            (self.base + IOWIN).as_ptr::<u32>().read_volatile()
+        }
+    }
+
+    fn write(&mut self, reg: u32, value: u32) {
+        unsafe {
+            (self.base + IOREGSEL)
+                .as_mut_ptr::<u32>()
+                .write_volatile(reg);
+            (self.base + IOWIN)
+                .as_mut_ptr::<u32>()
+                .write_volatile(value);
+        }
+    }
+}
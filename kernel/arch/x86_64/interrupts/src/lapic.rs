@@ -0,0 +1,225 @@
+//! The local APIC of the running CPU, in xAPIC mode through its MMIO page
+//! or in x2APIC mode through MSRs.
+
+use core::arch::x86_64::__cpuid;
+
+use teensy_std::addr::{PhysAddr, VirtAddr};
+
+use crate::{LapicNmi, Polarity, Trigger};
+
+const IA32_APIC_BASE: u32 = 0x1b;
+const APIC_BASE_X2APIC: u64 = 1 << 10;
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+const APIC_BASE_ADDRESS: u64 = 0x000f_ffff_ffff_f000;
+/// The x2APIC registers are MSRs from here on, one per 16 bytes of MMIO.
+const X2APIC_MSR_BASE: u32 = 0x800;
+const X2APIC_SELF_IPI: u32 = 0x83f;
+const CPUID_X2APIC: u32 = 1 << 21;
+
+const REG_ID: u32 = 0x20;
+const REG_VERSION: u32 = 0x30;
+const REG_TPR: u32 = 0x80;
+const REG_EOI: u32 = 0xb0;
+const REG_SPURIOUS: u32 = 0xf0;
+const REG_ESR: u32 = 0x280;
+const REG_ICR_LOW: u32 = 0x300;
+const REG_ICR_HIGH: u32 = 0x310;
+const REG_LVT_TIMER: u32 = 0x320;
+const REG_LVT_LINT0: u32 = 0x350;
+const REG_LVT_LINT1: u32 = 0x360;
+const REG_LVT_ERROR: u32 = 0x370;
+const REG_TIMER_INITIAL_COUNT: u32 = 0x380;
+const REG_TIMER_CURRENT_COUNT: u32 = 0x390;
+const REG_TIMER_DIVIDE: u32 = 0x3e0;
+
+const SPURIOUS_APIC_ENABLE: u32 = 1 << 8;
+const LVT_MASKED: u32 = 1 << 16;
+const LVT_DELIVERY_NMI: u32 = 0b100 << 8;
+const LVT_ACTIVE_LOW: u32 = 1 << 13;
+const LVT_LEVEL: u32 = 1 << 15;
+const LVT_TIMER_PERIODIC: u32 = 0b01 << 17;
+/// Divide configuration for dividing the bus clock by `LAPIC_TIMER_DIVIDER`.
+const TIMER_DIVIDE_BY_16: u32 = 0b0011;
+const ICR_DELIVERY_PENDING: u32 = 1 << 12;
+const ICR_DESTINATION_SELF: u32 = 0b01 << 18;
+
+/// Vector of the interrupts the local APIC makes up when the one it was
+/// delivering went away. Its low four bits must be set on older CPUs.
+pub const SPURIOUS_VECTOR: u8 = 0xff;
+/// Vector of the local APIC's own error interrupt.
+pub const LAPIC_ERROR_VECTOR: u8 = 0xfe;
+
+/// What the local APIC timer divides its input clock by.
+pub const LAPIC_TIMER_DIVIDER: u32 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LapicMode {
+    XApic(VirtAddr),
+    X2Apic,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerMode {
+    /// Counts down from the initial count once.
+    OneShot,
+    /// Reloads the initial count every time it reaches zero.
+    Periodic,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LocalApic {
+    mode: LapicMode,
+}
+
+/// Whether the CPU can run its local APIC in x2APIC mode.
+pub fn has_x2apic() -> bool {
+    __cpuid(1).ecx & CPUID_X2APIC != 0
+}
+
+/// The physical address of the local APIC's MMIO page, from the MSR.
+pub fn lapic_base() -> PhysAddr {
+    PhysAddr::new((unsafe { kernel_cpu::rdmsr(IA32_APIC_BASE) } & APIC_BASE_ADDRESS) as usize)
+}
+
+impl LocalApic {
+    /// Switches the local APIC on, in x2APIC mode if the CPU has it and
+    /// otherwise in xAPIC mode through MMIO, which must be the local APIC's
+    /// page mapped uncached. Every LVT entry but the error one stays masked.
+    pub fn enable(mmio: impl FnOnce() -> VirtAddr) -> Self {
+        let base = unsafe { kernel_cpu::rdmsr(IA32_APIC_BASE) } | APIC_BASE_ENABLE;
+        let lapic = if has_x2apic() {
+            // xAPIC mode has to be on before x2APIC mode is.
+            unsafe {
+                kernel_cpu::wrmsr(IA32_APIC_BASE, base);
+                kernel_cpu::wrmsr(IA32_APIC_BASE, base | APIC_BASE_X2APIC);
+            }
+            Self {
+                mode: LapicMode::X2Apic,
+            }
+        } else {
+            unsafe { kernel_cpu::wrmsr(IA32_APIC_BASE, base) };
+            Self {
+                mode: LapicMode::XApic(mmio()),
+            }
+        };
+
+        lapic.write(REG_TPR, 0);
+        lapic.write(REG_LVT_TIMER, LVT_MASKED);
+        lapic.write(REG_LVT_LINT0, LVT_MASKED);
+        lapic.write(REG_LVT_LINT1, LVT_MASKED);
+        lapic.write(REG_LVT_ERROR, LAPIC_ERROR_VECTOR as u32);
+        lapic.clear_errors();
+        lapic.write(REG_SPURIOUS, SPURIOUS_APIC_ENABLE | SPURIOUS_VECTOR as u32);
+        lapic.end_of_interrupt();
+        lapic
+    }
+
+    pub fn mode(&self) -> LapicMode {
+        self.mode
+    }
+
+    /// The APIC ID of the CPU, all 32 bits of it in x2APIC mode.
+    pub fn id(&self) -> u32 {
+        match self.mode {
+            LapicMode::XApic(_) => self.read(REG_ID) >> 24,
+            LapicMode::X2Apic => self.read(REG_ID),
+        }
+    }
+
+    pub fn version(&self) -> u8 {
+        self.read(REG_VERSION) as u8
+    }
+
+    /// Tells the local APIC the interrupt being handled is done with.
+    pub fn end_of_interrupt(&self) {
+        self.write(REG_EOI, 0);
+    }
+
+    /// Reads and clears the error status.
+    pub fn clear_errors(&self) -> u32 {
+        // The register latches the errors on a write.
+        self.write(REG_ESR, 0);
+        self.read(REG_ESR)
+    }
+
+    /// Starts the timer counting down from COUNT, at the input clock divided
+    /// by `LAPIC_TIMER_DIVIDER`. It raises VECTOR whenever it reaches zero, or
+    /// nothing if VECTOR is None.
+    pub fn start_timer(&self, mode: TimerMode, count: u32, vector: Option<u8>) {
+        let mut lvt = vector.map_or(LVT_MASKED, |vector| vector as u32);
+        if mode == TimerMode::Periodic {
+            lvt |= LVT_TIMER_PERIODIC;
+        }
+        self.write(REG_TIMER_DIVIDE, TIMER_DIVIDE_BY_16);
+        self.write(REG_LVT_TIMER, lvt);
+        // Writing the initial count starts the timer.
+        self.write(REG_TIMER_INITIAL_COUNT, count);
+    }
+
+    pub fn stop_timer(&self) {
+        self.write(REG_TIMER_INITIAL_COUNT, 0);
+        self.write(REG_LVT_TIMER, LVT_MASKED);
+    }
+
+    /// Where the timer is in its count down, zero once a one-shot count ran
+    /// out.
+    pub fn timer_count(&self) -> u32 {
+        self.read(REG_TIMER_CURRENT_COUNT)
+    }
+
+    /// Raises VECTOR on this CPU, once interrupts are enabled.
+    pub fn send_self_ipi(&self, vector: u8) {
+        match self.mode {
+            LapicMode::XApic(_) => {
+                self.write(REG_ICR_HIGH, 0);
+                self.write(REG_ICR_LOW, ICR_DESTINATION_SELF | vector as u32);
+                while self.read(REG_ICR_LOW) & ICR_DELIVERY_PENDING != 0 {
+                    core::hint::spin_loop();
+                }
+            }
+            LapicMode::X2Apic => unsafe { kernel_cpu::wrmsr(X2APIC_SELF_IPI, vector as u64) },
+        }
+    }
+
+    /// Wires the LINT pin of NMI to deliver NMIs, if NMI applies to the CPU
+    /// with ACPI processor UID PROCESSOR.
+    pub fn set_nmi(&self, nmi: &LapicNmi, processor: u32) {
+        if nmi.processor.is_some_and(|uid| uid != processor) {
+            return;
+        }
+        let mut lvt = LVT_DELIVERY_NMI;
+        if nmi.polarity == Polarity::ActiveLow {
+            lvt |= LVT_ACTIVE_LOW;
+        }
+        if nmi.trigger == Trigger::Level {
+            lvt |= LVT_LEVEL;
+        }
+        match nmi.lint {
+            0 => self.write(REG_LVT_LINT0, lvt),
+            1 => self.write(REG_LVT_LINT1, lvt),
+            _ => {}
+        }
+    }
+
+    pub(crate) fn read(&self, reg: u32) -> u32 {
+        match self.mode {
+            LapicMode::XApic(base) => unsafe {
+                (base + reg as usize).as_ptr::<u32>().read_volatile()
+            },
+            LapicMode::X2Apic => unsafe { kernel_cpu::rdmsr(X2APIC_MSR_BASE + (reg >> 4)) as u32 },
+        }
+    }
+
+    pub(crate) fn write(&self, reg: u32, value: u32) {
+        match self.mode {
+            LapicMode::XApic(base) => unsafe {
+                (base + reg as usize)
+                    .as_mut_ptr::<u32>()
+                    .write_volatile(value)
+            },
+            LapicMode::X2Apic => unsafe {
+                kernel_cpu::wrmsr(X2APIC_MSR_BASE + (reg >> 4), value as u64)
+            },
+        }
+    }
+}
@@ -0,0 +1,222 @@
+#![no_std]
+
+mod ioapic;
+mod lapic;
+mod madt;
+mod pic;
+
+use core::fmt;
+
+use teensy_std::addr::{PhysAddr, VirtAddr};
+
+pub use ioapic::{IoApic, Redirection};
+pub use lapic::{
+    has_x2apic, lapic_base, LapicMode, LocalApic, TimerMode, LAPIC_ERROR_VECTOR,
+    LAPIC_TIMER_DIVIDER, SPURIOUS_VECTOR,
+};
+pub use madt::{
+    IoApicEntry, LapicNmi, Madt, MadtCpu, MadtError, SourceOverride, MAX_CPUS, MAX_IOAPICS,
+    MAX_LAPIC_NMIS, MAX_OVERRIDES,
+};
+pub use pic::{disable_legacy_pics, legacy_pic_masks, PIC1_VECTOR_BASE, PIC2_VECTOR_BASE};
+
+/// Bytes of MMIO the local APIC and the IOAPICs each need mapped.
+pub const MMIO_SIZE: usize = 4096;
+/// Number of legacy ISA IRQs.
+pub const NUM_ISA_IRQS: u8 = 16;
+
+static MADT: spin::Once<Madt> = spin::Once::new();
+static LAPIC: spin::Once<LocalApic> = spin::Once::new();
+static IOAPICS: spin::Once<spin::Mutex<IoApics>> = spin::Once::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Polarity {
+    ActiveHigh,
+    ActiveLow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    Edge,
+    Level,
+}
+
+/// A device interrupt line: a global system interrupt and how it signals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IrqLine {
+    pub gsi: u32,
+    pub polarity: Polarity,
+    pub trigger: Trigger,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterruptError {
+    Madt(MadtError),
+    /// `init` has not run.
+    NotInitialised,
+    /// No IOAPIC handles the GSI.
+    NoSuchGsi(u32),
+    /// The vector is one of the CPU exceptions'.
+    BadVector(u8),
+    /// IOAPICs can only name the first 256 APIC IDs without interrupt
+    /// remapping.
+    UnreachableApic(u32),
+}
+
+struct IoApics {
+    ioapics: [Option<IoApic>; madt::MAX_IOAPICS],
+}
+
+impl fmt::Display for InterruptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InterruptError::Madt(err) => write!(f, "bad MADT: {:?}", err),
+            InterruptError::NotInitialised => write!(f, "interrupt controllers not set up"),
+            InterruptError::NoSuchGsi(gsi) => write!(f, "no IOAPIC handles GSI {}", gsi),
+            InterruptError::BadVector(vector) => {
+                write!(f, "vector {} belongs to a CPU exception", vector)
+            }
+            InterruptError::UnreachableApic(id) => {
+                write!(f, "IOAPICs cannot send to APIC ID {}", id)
+            }
+        }
+    }
+}
+
+impl IoApics {
+    fn find(&mut self, gsi: u32) -> Result<&mut IoApic, InterruptError> {
+        self.ioapics
+            .iter_mut()
+            .flatten()
+            .find(|ioapic| ioapic.handles(gsi))
+            .ok_or(InterruptError::NoSuchGsi(gsi))
+    }
+}
+
+/// Brings up the interrupt controllers from the MADT: masks the legacy PICs,
+/// enables the local APIC and masks every IOAPIC input. MAP_MMIO maps the
+/// given bytes of MMIO uncached and returns where. Interrupts stay disabled.
+/// Later calls do nothing.
+pub fn init(
+    madt: &[u8],
+    mut map_mmio: impl FnMut(PhysAddr, usize) -> VirtAddr,
+) -> Result<(), InterruptError> {
+    if MADT.is_completed() {
+        return Ok(());
+    }
+    let parsed = Madt::parse(madt).map_err(InterruptError::Madt)?;
+    let madt = MADT.call_once(|| parsed);
+
+    // Remapping and masking the PICs is harmless where there are none.
+    disable_legacy_pics();
+
+    let lapic = LAPIC.call_once(|| LocalApic::enable(|| map_mmio(madt.lapic_address, MMIO_SIZE)));
+    let id = lapic.id();
+    if let Some(cpu) = madt.cpus().iter().find(|cpu| cpu.apic_id == id) {
+        for nmi in madt.lapic_nmis() {
+            lapic.set_nmi(nmi, cpu.processor);
+        }
+    }
+
+    IOAPICS.call_once(|| {
+        let mut ioapics = IoApics {
+            ioapics: [const { None }; madt::MAX_IOAPICS],
+        };
+        for (slot, entry) in ioapics.ioapics.iter_mut().zip(madt.ioapics()) {
+            let base = map_mmio(entry.address, MMIO_SIZE);
+            *slot = Some(unsafe { IoApic::new(base, entry.gsi_base) });
+        }
+        spin::Mutex::new(ioapics)
+    });
+    Ok(())
+}
+
+/// The MADT `init` was given.
+pub fn madt() -> Option<&'static Madt> {
+    MADT.get()
+}
+
+/// The running CPU's local APIC.
+pub fn local_apic() -> Option<&'static LocalApic> {
+    LAPIC.get()
+}
+
+/// Signals the end of the interrupt being handled. Every interrupt from the
+/// IOAPICs or the local APIC but a spurious one needs it.
+pub fn end_of_interrupt() {
+    if let Some(lapic) = LAPIC.get() {
+        lapic.end_of_interrupt();
+    }
+}
+
+/// The line legacy ISA IRQ is wired to, as the MADT's overrides have it.
+/// Without an override, ISA IRQs are edge triggered, active high and wired
+/// to the GSI of the same number.
+pub fn isa_irq(irq: u8) -> IrqLine {
+    MADT.get()
+        .and_then(|madt| madt.overrides().iter().find(|source| source.irq == irq))
+        .map_or(
+            IrqLine {
+                gsi: irq as u32,
+                polarity: Polarity::ActiveHigh,
+                trigger: Trigger::Edge,
+            },
+            |source| IrqLine {
+                gsi: source.gsi,
+                polarity: source.polarity,
+                trigger: source.trigger,
+            },
+        )
+}
+
+/// Programs LINE to raise VECTOR on this CPU. The line is left masked.
+pub fn route(line: IrqLine, vector: u8) -> Result<(), InterruptError> {
+    if (vector as usize) < kernel_cpu::NUM_EXCEPTIONS {
+        return Err(InterruptError::BadVector(vector));
+    }
+    let lapic = LAPIC.get().ok_or(InterruptError::NotInitialised)?;
+    let redirection = Redirection {
+        vector,
+        destination: u8::try_from(lapic.id())
+            .map_err(|_| InterruptError::UnreachableApic(lapic.id()))?,
+        polarity: line.polarity,
+        trigger: line.trigger,
+        masked: true,
+    };
+    with_ioapics(|ioapics| {
+        ioapics
+            .find(line.gsi)?
+            .set_redirection(line.gsi, &redirection);
+        Ok(())
+    })
+}
+
+/// Stops GSI from raising its vector.
+pub fn mask(gsi: u32) -> Result<(), InterruptError> {
+    with_ioapics(|ioapics| {
+        ioapics.find(gsi)?.set_masked(gsi, true);
+        Ok(())
+    })
+}
+
+/// Lets GSI raise the vector it was routed to.
+pub fn unmask(gsi: u32) -> Result<(), InterruptError> {
+    with_ioapics(|ioapics| {
+        ioapics.find(gsi)?.set_masked(gsi, false);
+        Ok(())
+    })
+}
+
+/// Where GSI is routed to now.
+pub fn redirection(gsi: u32) -> Result<Redirection, InterruptError> {
+    with_ioapics(|ioapics| Ok(ioapics.find(gsi)?.redirection(gsi)))
+}
+
+/// Runs F on the IOAPICs, with interrupts off so that a handler on this CPU
+/// cannot wait for the lock.
+fn with_ioapics<T>(
+    f: impl FnOnce(&mut IoApics) -> Result<T, InterruptError>,
+) -> Result<T, InterruptError> {
+    let ioapics = IOAPICS.get().ok_or(InterruptError::NotInitialised)?;
+    kernel_cpu::without_interrupts(|| f(&mut ioapics.lock()))
+}
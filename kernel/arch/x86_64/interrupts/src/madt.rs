@@ -0,0 +1,339 @@
+//! The ACPI Multiple APIC Description Table, which lists the interrupt
+//! controllers and how the legacy IRQs are wired to them.
+
+use core::fmt;
+
+use kernel_log::kprintln;
+use teensy_std::addr::PhysAddr;
+
+use crate::{Polarity, Trigger};
+
+pub const MAX_IOAPICS: usize = 8;
+pub const MAX_OVERRIDES: usize = 16;
+pub const MAX_LAPIC_NMIS: usize = 8;
+pub const MAX_CPUS: usize = 64;
+
+/// Length of the standard ACPI table header.
+const SDT_HEADER_LEN: usize = 36;
+
+const ENTRY_LAPIC: u8 = 0;
+const ENTRY_IOAPIC: u8 = 1;
+const ENTRY_SOURCE_OVERRIDE: u8 = 2;
+const ENTRY_LAPIC_NMI: u8 = 4;
+const ENTRY_LAPIC_ADDRESS_OVERRIDE: u8 = 5;
+const ENTRY_X2APIC: u8 = 9;
+const ENTRY_X2APIC_NMI: u8 = 0xa;
+
+/// Processor entries with this flag can be used.
+const PROCESSOR_ENABLED: u32 = 1 << 0;
+/// Processor entries with this flag can be brought online later.
+const PROCESSOR_ONLINE_CAPABLE: u32 = 1 << 1;
+/// The machine also has the two legacy 8259 PICs.
+const PCAT_COMPAT: u32 = 1 << 0;
+/// An NMI entry with this processor UID applies to every processor.
+const ALL_PROCESSORS: u32 = 0xff;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MadtError {
+    BadSignature,
+    /// The table or one of its entries is cut short.
+    Truncated,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoApicEntry {
+    pub id: u8,
+    pub address: PhysAddr,
+    /// First global system interrupt the IOAPIC handles.
+    pub gsi_base: u32,
+}
+
+/// A legacy ISA IRQ which is not wired to the global system interrupt of the
+/// same number, or not with the ISA polarity and trigger mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceOverride {
+    pub irq: u8,
+    pub gsi: u32,
+    pub polarity: Polarity,
+    pub trigger: Trigger,
+}
+
+/// A local APIC LINT pin wired to NMI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LapicNmi {
+    /// ACPI processor UID, `None` for every processor.
+    pub processor: Option<u32>,
+    pub lint: u8,
+    pub polarity: Polarity,
+    pub trigger: Trigger,
+}
+
+/// A processor's local APIC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MadtCpu {
+    /// ACPI processor UID, which the NMI entries refer to.
+    pub processor: u32,
+    pub apic_id: u32,
+}
+
+/// What the MADT says, in fixed size lists.
+#[derive(Clone, Copy)]
+pub struct Madt {
+    pub lapic_address: PhysAddr,
+    /// Whether the legacy PICs are there and need masking.
+    pub has_legacy_pics: bool,
+    cpus: [MadtCpu; MAX_CPUS],
+    num_cpus: usize,
+    ioapics: [IoApicEntry; MAX_IOAPICS],
+    num_ioapics: usize,
+    overrides: [SourceOverride; MAX_OVERRIDES],
+    num_overrides: usize,
+    nmis: [LapicNmi; MAX_LAPIC_NMIS],
+    num_nmis: usize,
+    /// Entries dropped because their list was full.
+    ignored: usize,
+}
+
+/// Reads little endian integers out of a table.
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn bytes<const N: usize>(&self, offset: usize) -> Result<[u8; N], MadtError> {
+        self.0
+            .get(offset..offset + N)
+            .map(|bytes| bytes.try_into().unwrap())
+            .ok_or(MadtError::Truncated)
+    }
+
+    fn u8(&self, offset: usize) -> Result<u8, MadtError> {
+        self.bytes::<1>(offset).map(|[byte]| byte)
+    }
+
+    fn u16(&self, offset: usize) -> Result<u16, MadtError> {
+        self.bytes(offset).map(u16::from_le_bytes)
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32, MadtError> {
+        self.bytes(offset).map(u32::from_le_bytes)
+    }
+
+    fn u64(&self, offset: usize) -> Result<u64, MadtError> {
+        self.bytes(offset).map(u64::from_le_bytes)
+    }
+}
+
+/// Adds ITEM to the first LEN items of LIST, unless LIST is full. Counts it
+/// in IGNORED then.
+fn push<T>(list: &mut [T], len: &mut usize, ignored: &mut usize, item: T) {
+    match list.get_mut(*len) {
+        Some(slot) => {
+            *slot = item;
+            *len += 1;
+        }
+        None => *ignored += 1,
+    }
+}
+
+/// The polarity and trigger mode of the MPS INTI FLAGS of an entry. Those
+/// that conform to the bus are the ISA ones.
+fn inti_flags(flags: u16) -> (Polarity, Trigger) {
+    let polarity = match flags & 0b11 {
+        0b11 => Polarity::ActiveLow,
+        _ => Polarity::ActiveHigh,
+    };
+    let trigger = match flags >> 2 & 0b11 {
+        0b11 => Trigger::Level,
+        _ => Trigger::Edge,
+    };
+    (polarity, trigger)
+}
+
+impl Madt {
+    /// Parses TABLE, the whole MADT with its header. Entries of kinds the
+    /// kernel has no use for are skipped, as are the entries of a kind past
+    /// the first the lists have room for.
+    pub fn parse(table: &[u8]) -> Result<Self, MadtError> {
+        let reader = Reader(table);
+        if reader.bytes::<4>(0)? != *b"APIC" {
+            return Err(MadtError::BadSignature);
+        }
+        let len = reader.u32(4)? as usize;
+        let table = table.get(..len).ok_or(MadtError::Truncated)?;
+        let reader = Reader(table);
+
+        let mut madt = Self {
+            lapic_address: PhysAddr::new(reader.u32(SDT_HEADER_LEN)? as usize),
+            has_legacy_pics: reader.u32(SDT_HEADER_LEN + 4)? & PCAT_COMPAT != 0,
+            cpus: [MadtCpu {
+                processor: 0,
+                apic_id: 0,
+            }; MAX_CPUS],
+            num_cpus: 0,
+            ioapics: [IoApicEntry {
+                id: 0,
+                address: PhysAddr::zero(),
+                gsi_base: 0,
+            }; MAX_IOAPICS],
+            num_ioapics: 0,
+            overrides: [SourceOverride {
+                irq: 0,
+                gsi: 0,
+                polarity: Polarity::ActiveHigh,
+                trigger: Trigger::Edge,
+            }; MAX_OVERRIDES],
+            num_overrides: 0,
+            nmis: [LapicNmi {
+                processor: None,
+                lint: 0,
+                polarity: Polarity::ActiveHigh,
+                trigger: Trigger::Edge,
+            }; MAX_LAPIC_NMIS],
+            num_nmis: 0,
+            ignored: 0,
+        };
+
+        let mut offset = SDT_HEADER_LEN + 8;
+        while offset < table.len() {
+            let typ = reader.u8(offset)?;
+            let entry_len = reader.u8(offset + 1)? as usize;
+            if entry_len < 2 || offset + entry_len > table.len() {
+                return Err(MadtError::Truncated);
+            }
+            let entry = Reader(&table[offset..offset + entry_len]);
+            madt.add_entry(typ, &entry)?;
+            offset += entry_len;
+        }
+        if madt.ignored > 0 {
+            kprintln!(
+                "madt: more entries than the lists hold, ignoring {} of them",
+                madt.ignored
+            );
+        }
+        Ok(madt)
+    }
+
+    fn add_entry(&mut self, typ: u8, entry: &Reader) -> Result<(), MadtError> {
+        let usable = |flags: u32| flags & (PROCESSOR_ENABLED | PROCESSOR_ONLINE_CAPABLE) != 0;
+        match typ {
+            ENTRY_LAPIC if usable(entry.u32(4)?) => {
+                let cpu = MadtCpu {
+                    processor: entry.u8(2)? as u32,
+                    apic_id: entry.u8(3)? as u32,
+                };
+                // CPUs past the limit are not brought up, but their
+                // interrupts still work.
+                push(&mut self.cpus, &mut self.num_cpus, &mut self.ignored, cpu);
+            }
+            ENTRY_X2APIC if usable(entry.u32(8)?) => {
+                let cpu = MadtCpu {
+                    processor: entry.u32(12)?,
+                    apic_id: entry.u32(4)?,
+                };
+                push(&mut self.cpus, &mut self.num_cpus, &mut self.ignored, cpu);
+            }
+            ENTRY_IOAPIC => {
+                let ioapic = IoApicEntry {
+                    id: entry.u8(2)?,
+                    address: PhysAddr::new(entry.u32(4)? as usize),
+                    gsi_base: entry.u32(8)?,
+                };
+                push(
+                    &mut self.ioapics,
+                    &mut self.num_ioapics,
+                    &mut self.ignored,
+                    ioapic,
+                );
+            }
+            ENTRY_SOURCE_OVERRIDE => {
+                let (polarity, trigger) = inti_flags(entry.u16(8)?);
+                let source = SourceOverride {
+                    irq: entry.u8(3)?,
+                    gsi: entry.u32(4)?,
+                    polarity,
+                    trigger,
+                };
+                push(
+                    &mut self.overrides,
+                    &mut self.num_overrides,
+                    &mut self.ignored,
+                    source,
+                );
+            }
+            ENTRY_LAPIC_NMI => {
+                let processor = entry.u8(2)? as u32;
+                let (polarity, trigger) = inti_flags(entry.u16(3)?);
+                let nmi = LapicNmi {
+                    processor: (processor != ALL_PROCESSORS).then_some(processor),
+                    lint: entry.u8(5)?,
+                    polarity,
+                    trigger,
+                };
+                push(&mut self.nmis, &mut self.num_nmis, &mut self.ignored, nmi);
+            }
+            ENTRY_X2APIC_NMI => {
+                let processor = entry.u32(4)?;
+                let (polarity, trigger) = inti_flags(entry.u16(2)?);
+                let nmi = LapicNmi {
+                    processor: (processor != u32::MAX).then_some(processor),
+                    lint: entry.u8(8)?,
+                    polarity,
+                    trigger,
+                };
+                push(&mut self.nmis, &mut self.num_nmis, &mut self.ignored, nmi);
+            }
+            ENTRY_LAPIC_ADDRESS_OVERRIDE => {
+                self.lapic_address = PhysAddr::new(entry.u64(4)? as usize);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// The usable processors.
+    pub fn cpus(&self) -> &[MadtCpu] {
+        &self.cpus[..self.num_cpus]
+    }
+
+    pub fn ioapics(&self) -> &[IoApicEntry] {
+        &self.ioapics[..self.num_ioapics]
+    }
+
+    pub fn overrides(&self) -> &[SourceOverride] {
+        &self.overrides[..self.num_overrides]
+    }
+
+    pub fn lapic_nmis(&self) -> &[LapicNmi] {
+        &self.nmis[..self.num_nmis]
+    }
+}
+
+impl fmt::Display for Madt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "madt: lapic at {:?}, {} cpus{}",
+            self.lapic_address,
+            self.num_cpus,
+            if self.has_legacy_pics {
+                ", legacy PICs"
+            } else {
+                ""
+            }
+        )?;
+        for ioapic in self.ioapics() {
+            writeln!(
+                f,
+                "  ioapic {} at {:?}, gsi base {}",
+                ioapic.id, ioapic.address, ioapic.gsi_base
+            )?;
+        }
+        for source in self.overrides() {
+            writeln!(
+                f,
+                "  irq {} -> gsi {} {:?} {:?}",
+                source.irq, source.gsi, source.polarity, source.trigger
+            )?;
+        }
+        write!(f, "  {} lapic nmi entries", self.num_nmis)
+    }
+}
@@ -0,0 +1,47 @@
+//! The legacy 8259 PICs, which are only ever moved out of the way.
+
+const PIC1_COMMAND: u16 = 0x20;
+const PIC1_DATA: u16 = 0x21;
+const PIC2_COMMAND: u16 = 0xa0;
+const PIC2_DATA: u16 = 0xa1;
+/// A port nothing listens on, written to give the PICs time between commands.
+const IO_WAIT_PORT: u16 = 0x80;
+
+const ICW1_INIT: u8 = 0x10;
+const ICW1_ICW4: u8 = 0x01;
+const ICW4_8086: u8 = 0x01;
+
+/// Where the PICs' vectors are moved to, clear of the CPU exceptions, so
+/// that a spurious IRQ from them is never taken for one.
+pub const PIC1_VECTOR_BASE: u8 = 0x20;
+pub const PIC2_VECTOR_BASE: u8 = 0x28;
+
+fn write(port: u16, value: u8) {
+    unsafe {
+        kernel_cpu::outb(port, value);
+        kernel_cpu::outb(IO_WAIT_PORT, 0);
+    }
+}
+
+/// Remaps both PICs and masks every line on them. Whatever state the
+/// firmware left them in, they deliver nothing afterwards.
+pub fn disable_legacy_pics() {
+    write(PIC1_COMMAND, ICW1_INIT | ICW1_ICW4);
+    write(PIC2_COMMAND, ICW1_INIT | ICW1_ICW4);
+    write(PIC1_DATA, PIC1_VECTOR_BASE);
+    write(PIC2_DATA, PIC2_VECTOR_BASE);
+    // The secondary PIC hangs off line 2 of the primary one.
+    write(PIC1_DATA, 1 << 2);
+    write(PIC2_DATA, 2);
+    write(PIC1_DATA, ICW4_8086);
+    write(PIC2_DATA, ICW4_8086);
+
+    write(PIC1_DATA, 0xff);
+    write(PIC2_DATA, 0xff);
+}
+
+/// The interrupt mask registers of both PICs, the primary one's in the low
+/// byte. A set bit masks the line.
+pub fn legacy_pic_masks() -> u16 {
+    unsafe { (kernel_cpu::inb(PIC2_DATA) as u16) << 8 | kernel_cpu::inb(PIC1_DATA) as u16 }
+}
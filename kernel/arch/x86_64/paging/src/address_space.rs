@@ -0,0 +1,343 @@
+use core::arch::asm;
+
+use kernel_boot_interface::hhdm::BootHhdm;
+
+use crate::table::{MapFlags, PageTable, PageTableEntry, ENTRIES_PER_TABLE};
+use crate::{GIB, KIB, MIB};
+
+const CR3_ADDR_MASK: usize = 0x000f_ffff_ffff_f000;
+/// Index of the first PML4 entry of the higher half.
+const KERNEL_HALF_START: usize = ENTRIES_PER_TABLE / 2;
+
+/// Source of the frames used for intermediate page tables.
+pub trait FrameAllocator {
+    /// Returns the physical address of a free 4KiB frame.
+    fn allocate_frame(&mut self) -> Option<usize>;
+    /// Takes back FRAME, which `allocate_frame` handed out.
+    fn free_frame(&mut self, frame: usize);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageSize {
+    Size4KiB,
+    Size2MiB,
+    Size1GiB,
+}
+
+impl PageSize {
+    pub const fn bytes(self) -> usize {
+        match self {
+            PageSize::Size4KiB => 4 * KIB,
+            PageSize::Size2MiB => 2 * MIB,
+            PageSize::Size1GiB => GIB,
+        }
+    }
+
+    /// Level of the table holding the leaf entry, 1 being the PT.
+    const fn level(self) -> usize {
+        match self {
+            PageSize::Size4KiB => 1,
+            PageSize::Size2MiB => 2,
+            PageSize::Size1GiB => 3,
+        }
+    }
+
+    const fn from_level(level: usize) -> Self {
+        match level {
+            1 => PageSize::Size4KiB,
+            2 => PageSize::Size2MiB,
+            _ => PageSize::Size1GiB,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapError {
+    /// The virtual or physical address is not aligned to the page size.
+    NotAligned,
+    /// The virtual address is not in canonical form.
+    NonCanonical,
+    /// No frame could be allocated for an intermediate table.
+    OutOfFrames,
+    /// Something is already mapped at the virtual address.
+    AlreadyMapped,
+    /// Nothing is mapped at the virtual address.
+    NotMapped,
+    /// A huge page is in the way of the walk to a smaller page.
+    HugePageConflict,
+}
+
+/// A leaf of the page tables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mapping {
+    pub phys: usize,
+    pub size: PageSize,
+    pub flags: MapFlags,
+}
+
+/// A four level page table hierarchy. The tables themselves are reached
+/// through the HHDM.
+pub struct AddressSpace {
+    root: usize,
+    hhdm_base: usize,
+}
+
+impl AddressSpace {
+    /// The address space currently loaded in CR3.
+    pub fn current(hhdm: &BootHhdm) -> Self {
+        let cr3: usize;
+        unsafe {
+            asm!("mov {}, cr3", out(reg) cr3, options(nomem, nostack, preserves_flags));
+        }
+        Self {
+            root: cr3 & CR3_ADDR_MASK,
+            hhdm_base: hhdm.base,
+        }
+    }
+
+    /// Creates an address space with nothing mapped at all.
+    pub fn empty(hhdm: &BootHhdm, alloc: &mut impl FrameAllocator) -> Result<Self, MapError> {
+        let mut space = Self {
+            root: alloc.allocate_frame().ok_or(MapError::OutOfFrames)?,
+            hhdm_base: hhdm.base,
+        };
+        // Safety: the root is a fresh frame nothing else uses.
+        unsafe { space.table_mut(space.root) }.zero();
+        Ok(space)
+    }
+
+    /// Creates an empty address space which shares the higher half with the
+    /// current one.
+    pub fn new(hhdm: &BootHhdm, alloc: &mut impl FrameAllocator) -> Result<Self, MapError> {
+        let current = Self::current(hhdm);
+        let mut space = Self {
+            root: alloc.allocate_frame().ok_or(MapError::OutOfFrames)?,
+            hhdm_base: hhdm.base,
+        };
+
+        // Safety: both roots are valid tables and the new one is not in use.
+        let cur_root = unsafe { current.table(current.root) };
+        let new_root = unsafe { space.table_mut(space.root) };
+        new_root.zero();
+        new_root.entries[KERNEL_HALF_START..]
+            .copy_from_slice(&cur_root.entries[KERNEL_HALF_START..]);
+        Ok(space)
+    }
+
+    /// Physical address of the PML4.
+    pub fn root(&self) -> usize {
+        self.root
+    }
+
+    /// Loads this address space into CR3.
+    ///
+    /// # Safety
+    /// The address space must map the running kernel, its stack and the HHDM.
+    pub unsafe fn activate(&self) {
+        asm!("mov cr3, {}", in(reg) self.root, options(nostack, preserves_flags));
+    }
+
+    /// Maps the page of SIZE at VIRT to the frame at PHYS.
+    pub fn map(
+        &mut self,
+        virt: usize,
+        phys: usize,
+        size: PageSize,
+        flags: MapFlags,
+        alloc: &mut impl FrameAllocator,
+    ) -> Result<(), MapError> {
+        check_addrs(virt, phys, size)?;
+
+        // The tables allocated on the way down, each linked from the one
+        // before, and where the first of them is linked from.
+        let mut new_tables = [0; 3];
+        let mut num_new = 0;
+        let mut first_link: Option<(usize, usize)> = None;
+
+        let mut table_phys = self.root;
+        for level in ((size.level() + 1)..=4).rev() {
+            let index = table_index(virt, level);
+            let entry = unsafe { self.table(table_phys) }.entries[index];
+            if !entry.is_present() {
+                let Some(frame) = alloc.allocate_frame() else {
+                    // Only the first new table is reachable from the
+                    // hierarchy, and the rest from it.
+                    if let Some((link_table, link_index)) = first_link {
+                        unsafe { self.table_mut(link_table) }.entries[link_index].clear();
+                    }
+                    for &frame in &new_tables[..num_new] {
+                        alloc.free_frame(frame);
+                    }
+                    return Err(MapError::OutOfFrames);
+                };
+                unsafe { self.table_mut(frame) }.zero();
+                unsafe { self.table_mut(table_phys) }.entries[index] =
+                    PageTableEntry::table(frame, flags.user);
+                if num_new == 0 {
+                    first_link = Some((table_phys, index));
+                }
+                new_tables[num_new] = frame;
+                num_new += 1;
+            } else if level <= 3 && entry.is_huge() {
+                return Err(MapError::HugePageConflict);
+            } else if flags.user {
+                unsafe { self.table_mut(table_phys) }.entries[index].allow_user();
+            }
+            table_phys = unsafe { self.table(table_phys) }.entries[index].addr();
+        }
+
+        let entry =
+            &mut unsafe { self.table_mut(table_phys) }.entries[table_index(virt, size.level())];
+        if entry.is_present() {
+            return Err(MapError::AlreadyMapped);
+        }
+        *entry = PageTableEntry::leaf(phys, flags, size != PageSize::Size4KiB);
+        flush(virt);
+        Ok(())
+    }
+
+    /// Removes the page starting at VIRT and returns what was mapped there.
+    /// Intermediate tables are kept around.
+    pub fn unmap(&mut self, virt: usize) -> Result<Mapping, MapError> {
+        let (entry, size) = self.leaf_mut(virt)?;
+        if !virt.is_multiple_of(size.bytes()) {
+            return Err(MapError::NotAligned);
+        }
+        let mapping = leaf_mapping(entry, size);
+        entry.clear();
+        flush(virt);
+        Ok(mapping)
+    }
+
+    /// Points the page starting at VIRT to PHYS with FLAGS, keeping its size.
+    /// Returns the previous mapping.
+    pub fn remap(
+        &mut self,
+        virt: usize,
+        phys: usize,
+        flags: MapFlags,
+    ) -> Result<Mapping, MapError> {
+        let (entry, size) = self.leaf_mut(virt)?;
+        check_addrs(virt, phys, size)?;
+        let mapping = leaf_mapping(entry, size);
+        *entry = PageTableEntry::leaf(phys, flags, size != PageSize::Size4KiB);
+        flush(virt);
+        Ok(mapping)
+    }
+
+    /// Returns the mapping of the page containing VIRT.
+    pub fn lookup(&self, virt: usize) -> Option<Mapping> {
+        self.leaf(virt)
+            .ok()
+            .map(|(entry, size)| leaf_mapping(&entry, size))
+    }
+
+    /// Returns the physical address VIRT is mapped to.
+    pub fn translate(&self, virt: usize) -> Option<usize> {
+        self.lookup(virt)
+            .map(|mapping| mapping.phys + virt % mapping.size.bytes())
+    }
+
+    /// Calls F with the physical address of every table in the hierarchy,
+    /// the root included.
+    pub fn for_each_table(&self, mut f: impl FnMut(usize)) {
+        self.visit_tables(self.root, 4, &mut f);
+    }
+
+    fn visit_tables(&self, table: usize, level: usize, f: &mut impl FnMut(usize)) {
+        f(table);
+        if level == 1 {
+            return;
+        }
+        for entry in unsafe { self.table(table) }.entries.iter() {
+            if entry.is_present() && !(level <= 3 && entry.is_huge()) {
+                self.visit_tables(entry.addr(), level - 1, f);
+            }
+        }
+    }
+
+    /// The table holding the leaf entry that maps VIRT, as a physical
+    /// address, the index of the entry in it and the size of the page.
+    fn find_leaf(&self, virt: usize) -> Result<(usize, usize, PageSize), MapError> {
+        if !is_canonical(virt) {
+            return Err(MapError::NonCanonical);
+        }
+
+        let mut table = self.root;
+        for level in (1..=4).rev() {
+            let index = table_index(virt, level);
+            let entry = unsafe { self.table(table) }.entries[index];
+            if !entry.is_present() {
+                return Err(MapError::NotMapped);
+            }
+            if level == 1 || (level <= 3 && entry.is_huge()) {
+                return Ok((table, index, PageSize::from_level(level)));
+            }
+            table = entry.addr();
+        }
+        unreachable!("The PT always holds leaves");
+    }
+
+    /// The leaf entry that maps VIRT and the size of its page.
+    fn leaf(&self, virt: usize) -> Result<(PageTableEntry, PageSize), MapError> {
+        let (table, index, size) = self.find_leaf(virt)?;
+        Ok((unsafe { self.table(table) }.entries[index], size))
+    }
+
+    /// `leaf`, for changing the entry.
+    fn leaf_mut(&mut self, virt: usize) -> Result<(&mut PageTableEntry, PageSize), MapError> {
+        let (table, index, size) = self.find_leaf(virt)?;
+        Ok((&mut unsafe { self.table_mut(table) }.entries[index], size))
+    }
+
+    /// # Safety
+    /// PHYS must be the address of a page table owned by this address space.
+    unsafe fn table(&self, phys: usize) -> &PageTable {
+        &*((phys + self.hhdm_base) as *const PageTable)
+    }
+
+    /// `table`, for changing it.
+    ///
+    /// # Safety
+    /// As for `table`.
+    unsafe fn table_mut(&mut self, phys: usize) -> &mut PageTable {
+        &mut *((phys + self.hhdm_base) as *mut PageTable)
+    }
+}
+
+fn leaf_mapping(entry: &PageTableEntry, size: PageSize) -> Mapping {
+    let huge = size != PageSize::Size4KiB;
+    Mapping {
+        phys: entry.addr() & !(size.bytes() - 1),
+        size,
+        flags: entry.flags(huge),
+    }
+}
+
+fn check_addrs(virt: usize, phys: usize, size: PageSize) -> Result<(), MapError> {
+    if !is_canonical(virt) {
+        Err(MapError::NonCanonical)
+    } else if !virt.is_multiple_of(size.bytes()) || !phys.is_multiple_of(size.bytes()) {
+        Err(MapError::NotAligned)
+    } else {
+        Ok(())
+    }
+}
+
+/// Index into the table at LEVEL (4 being the PML4) that VIRT walks through.
+const fn table_index(virt: usize, level: usize) -> usize {
+    (virt >> (12 + 9 * (level - 1))) % ENTRIES_PER_TABLE
+}
+
+/// Bits 48 to 63 must be copies of bit 47.
+pub const fn is_canonical(virt: usize) -> bool {
+    let upper = virt >> 47;
+    upper == 0 || upper == 0x1_ffff
+}
+
+/// Invalidates the TLB entry of the page containing VIRT.
+pub fn flush(virt: usize) {
+    unsafe {
+        asm!("invlpg [{}]", in(reg) virt, options(nostack, preserves_flags));
+    }
+}
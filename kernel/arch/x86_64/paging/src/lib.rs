@@ -1,5 +1,13 @@
 #![no_std]
 
+mod address_space;
+mod pat;
+mod table;
+
+pub use address_space::*;
+pub use pat::*;
+pub use table::*;
+
 // TODO: write some tests
 const GIB: usize = 1024 * 1024 * 1024;
 const MIB: usize = 1024 * 1024;
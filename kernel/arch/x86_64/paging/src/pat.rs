@@ -0,0 +1,62 @@
+use core::arch::asm;
+
+const IA32_PAT: u32 = 0x277;
+
+const PAT_UNCACHED: u64 = 0x00;
+const PAT_WRITE_COMBINING: u64 = 0x01;
+const PAT_WRITE_THROUGH: u64 = 0x04;
+const PAT_WRITE_PROTECT: u64 = 0x05;
+const PAT_WRITE_BACK: u64 = 0x06;
+const PAT_UNCACHED_MINUS: u64 = 0x07;
+
+/// The first four entries are the reset values, so mappings made before
+/// `init_pat` keep their meaning.
+const PAT_ENTRIES: [u64; 8] = [
+    PAT_WRITE_BACK,
+    PAT_WRITE_THROUGH,
+    PAT_UNCACHED_MINUS,
+    PAT_UNCACHED,
+    PAT_WRITE_PROTECT,
+    PAT_WRITE_COMBINING,
+    PAT_UNCACHED_MINUS,
+    PAT_UNCACHED,
+];
+
+/// Programs the PAT MSR so that every `CacheMode` means what it says.
+pub fn init_pat() {
+    let pat = PAT_ENTRIES
+        .iter()
+        .enumerate()
+        .fold(0, |pat, (idx, entry)| pat | entry << (idx * 8));
+    unsafe {
+        asm!(
+            "wrmsr",
+            in("ecx") IA32_PAT,
+            in("eax") pat as u32,
+            in("edx") (pat >> 32) as u32,
+            options(nostack, preserves_flags)
+        );
+        // Drop whatever the TLB cached with the old attributes.
+        asm!(
+            "mov {tmp}, cr3",
+            "mov cr3, {tmp}",
+            tmp = out(reg) _,
+            options(nostack, preserves_flags)
+        );
+    }
+}
+
+/// Reads the PAT MSR.
+pub fn read_pat() -> u64 {
+    let (low, high): (u32, u32);
+    unsafe {
+        asm!(
+            "rdmsr",
+            in("ecx") IA32_PAT,
+            out("eax") low,
+            out("edx") high,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+    (high as u64) << 32 | low as u64
+}
@@ -0,0 +1,246 @@
+use core::fmt;
+
+pub const ENTRIES_PER_TABLE: usize = 512;
+
+const ENTRY_PRESENT: u64 = 1 << 0;
+const ENTRY_WRITABLE: u64 = 1 << 1;
+const ENTRY_USER: u64 = 1 << 2;
+const ENTRY_WRITE_THROUGH: u64 = 1 << 3;
+const ENTRY_CACHE_DISABLE: u64 = 1 << 4;
+const ENTRY_ACCESSED: u64 = 1 << 5;
+const ENTRY_DIRTY: u64 = 1 << 6;
+/// Marks a 2MiB/1GiB page in the PD/PDPT. Shares its bit with the PAT bit of a PTE.
+const ENTRY_HUGE: u64 = 1 << 7;
+const ENTRY_PAT_4K: u64 = 1 << 7;
+const ENTRY_GLOBAL: u64 = 1 << 8;
+const ENTRY_PAT_HUGE: u64 = 1 << 12;
+const ENTRY_NO_EXECUTE: u64 = 1 << 63;
+
+const ENTRY_ADDR_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+/// Caching policy of a mapping. Each mode is an index into the PAT MSR as
+/// `init_pat` programs it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheMode {
+    WriteBack,
+    WriteThrough,
+    UncachedMinus,
+    Uncached,
+    WriteProtect,
+    WriteCombining,
+}
+
+impl CacheMode {
+    const fn pat_index(self) -> u64 {
+        match self {
+            CacheMode::WriteBack => 0,
+            CacheMode::WriteThrough => 1,
+            CacheMode::UncachedMinus => 2,
+            CacheMode::Uncached => 3,
+            CacheMode::WriteProtect => 4,
+            CacheMode::WriteCombining => 5,
+        }
+    }
+
+    const fn from_pat_index(idx: u64) -> Self {
+        match idx & 0b111 {
+            0 => CacheMode::WriteBack,
+            1 => CacheMode::WriteThrough,
+            2 | 6 => CacheMode::UncachedMinus,
+            4 => CacheMode::WriteProtect,
+            5 => CacheMode::WriteCombining,
+            _ => CacheMode::Uncached,
+        }
+    }
+}
+
+/// Architecture independent description of the permissions of a mapping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MapFlags {
+    pub writable: bool,
+    pub user: bool,
+    pub no_execute: bool,
+    pub global: bool,
+    pub cache: CacheMode,
+}
+
+impl MapFlags {
+    /// Read only, executable, kernel only, write back.
+    pub const fn new() -> Self {
+        Self {
+            writable: false,
+            user: false,
+            no_execute: false,
+            global: false,
+            cache: CacheMode::WriteBack,
+        }
+    }
+
+    /// The usual flags for kernel data: writable and not executable.
+    pub const fn kernel_data() -> Self {
+        Self::new().writable().no_execute()
+    }
+
+    pub const fn writable(mut self) -> Self {
+        self.writable = true;
+        self
+    }
+
+    pub const fn user(mut self) -> Self {
+        self.user = true;
+        self
+    }
+
+    pub const fn no_execute(mut self) -> Self {
+        self.no_execute = true;
+        self
+    }
+
+    pub const fn global(mut self) -> Self {
+        self.global = true;
+        self
+    }
+
+    pub const fn cache(mut self, cache: CacheMode) -> Self {
+        self.cache = cache;
+        self
+    }
+}
+
+impl Default for MapFlags {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct PageTableEntry(u64);
+
+impl PageTableEntry {
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn raw(&self) -> u64 {
+        self.0
+    }
+
+    pub const fn is_present(&self) -> bool {
+        self.0 & ENTRY_PRESENT != 0
+    }
+
+    /// Only meaningful for PDPT and PD entries.
+    pub const fn is_huge(&self) -> bool {
+        self.0 & ENTRY_HUGE != 0
+    }
+
+    pub const fn is_accessed(&self) -> bool {
+        self.0 & ENTRY_ACCESSED != 0
+    }
+
+    pub const fn is_dirty(&self) -> bool {
+        self.0 & ENTRY_DIRTY != 0
+    }
+
+    /// Physical address the entry points to, either the next table or the frame.
+    pub const fn addr(&self) -> usize {
+        (self.0 & ENTRY_ADDR_MASK) as usize
+    }
+
+    /// Builds an entry pointing to the next level table. Intermediate entries are
+    /// as permissive as possible so that the leaf decides the access rights.
+    pub const fn table(phys: usize, user: bool) -> Self {
+        let mut bits = (phys as u64 & ENTRY_ADDR_MASK) | ENTRY_PRESENT | ENTRY_WRITABLE;
+        if user {
+            bits |= ENTRY_USER;
+        }
+        Self(bits)
+    }
+
+    /// Builds a leaf entry. HUGE must be set for 2MiB and 1GiB pages.
+    pub const fn leaf(phys: usize, flags: MapFlags, huge: bool) -> Self {
+        let mut bits = (phys as u64 & ENTRY_ADDR_MASK) | ENTRY_PRESENT;
+        if flags.writable {
+            bits |= ENTRY_WRITABLE;
+        }
+        if flags.user {
+            bits |= ENTRY_USER;
+        }
+        if flags.no_execute {
+            bits |= ENTRY_NO_EXECUTE;
+        }
+        if flags.global {
+            bits |= ENTRY_GLOBAL;
+        }
+
+        let pat = flags.cache.pat_index();
+        if pat & 0b001 != 0 {
+            bits |= ENTRY_WRITE_THROUGH;
+        }
+        if pat & 0b010 != 0 {
+            bits |= ENTRY_CACHE_DISABLE;
+        }
+        if huge {
+            bits |= ENTRY_HUGE;
+            if pat & 0b100 != 0 {
+                bits |= ENTRY_PAT_HUGE;
+            }
+        } else if pat & 0b100 != 0 {
+            bits |= ENTRY_PAT_4K;
+        }
+        Self(bits)
+    }
+
+    /// Decodes the flags of a leaf entry.
+    pub const fn flags(&self, huge: bool) -> MapFlags {
+        let pat_bit = if huge { ENTRY_PAT_HUGE } else { ENTRY_PAT_4K };
+        let mut pat = 0;
+        if self.0 & ENTRY_WRITE_THROUGH != 0 {
+            pat |= 0b001;
+        }
+        if self.0 & ENTRY_CACHE_DISABLE != 0 {
+            pat |= 0b010;
+        }
+        if self.0 & pat_bit != 0 {
+            pat |= 0b100;
+        }
+        MapFlags {
+            writable: self.0 & ENTRY_WRITABLE != 0,
+            user: self.0 & ENTRY_USER != 0,
+            no_execute: self.0 & ENTRY_NO_EXECUTE != 0,
+            global: self.0 & ENTRY_GLOBAL != 0,
+            cache: CacheMode::from_pat_index(pat),
+        }
+    }
+
+    /// Lets user mode through an intermediate entry.
+    pub fn allow_user(&mut self) {
+        self.0 |= ENTRY_USER;
+    }
+
+    pub fn clear(&mut self) {
+        self.0 = 0;
+    }
+}
+
+impl fmt::Debug for PageTableEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PageTableEntry({:#018x})", self.0)
+    }
+}
+
+#[repr(C, align(4096))]
+pub struct PageTable {
+    pub entries: [PageTableEntry; ENTRIES_PER_TABLE],
+}
+
+impl PageTable {
+    pub fn zero(&mut self) {
+        self.entries.fill(PageTableEntry::empty());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.iter().all(|entry| !entry.is_present())
+    }
+}
@@ -23,3 +23,15 @@ pub fn serial_println(args: ::core::fmt::Arguments) {
         .write_fmt(args)
         .expect("Printing to serial failed");
 }
+
+/// Like `serial_println`, for fatal paths such as a CPU exception, which may
+/// have interrupted the holder of the port. The lock is broken if it is
+/// held, so the interrupted output may run into this one.
+pub fn serial_println_forced(args: ::core::fmt::Arguments) {
+    use core::fmt::Write;
+    let mut serial = SERIAL.try_lock().unwrap_or_else(|| {
+        unsafe { SERIAL.force_unlock() };
+        SERIAL.lock()
+    });
+    let _ = serial.write_fmt(args);
+}
@@ -0,0 +1,155 @@
+//! The high precision event timer, whose main counter runs at a fixed rate
+//! the hardware reports.
+
+use teensy_std::addr::{PhysAddr, VirtAddr};
+
+const REG_CAPABILITIES: usize = 0x000;
+const REG_CONFIG: usize = 0x010;
+const REG_MAIN_COUNTER: usize = 0x0f0;
+
+const CAP_COUNTER_64BIT: u64 = 1 << 13;
+const CAP_NUM_COMPARATORS_SHIFT: u64 = 8;
+const CAP_PERIOD_SHIFT: u64 = 32;
+const CONFIG_ENABLE: u64 = 1 << 0;
+const CONFIG_LEGACY_REPLACEMENT: u64 = 1 << 1;
+
+/// Longest counter period the specification allows, 100 ns.
+const MAX_PERIOD_FS: u64 = 100_000_000;
+pub const FEMTOSECONDS_PER_SECOND: u64 = 1_000_000_000_000_000;
+const FEMTOSECONDS_PER_NANOSECOND: u64 = 1_000_000;
+
+/// Bytes of MMIO the HPET needs mapped.
+pub const HPET_MMIO_SIZE: usize = 1024;
+/// Length of the ACPI HPET table.
+const HPET_TABLE_LEN: usize = 56;
+/// ACPI address space ID of system memory.
+const ADDRESS_SPACE_MEMORY: u8 = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HpetError {
+    BadSignature,
+    Truncated,
+    /// The registers are not in memory space.
+    NotMemoryMapped,
+    /// The counter claims a period of this many femtoseconds, which is not
+    /// one the specification allows.
+    BadPeriod(u64),
+}
+
+/// What the ACPI HPET table says about the first HPET.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HpetTable {
+    pub address: PhysAddr,
+    pub number: u8,
+    /// Fewest counter ticks a periodic comparator may be set to.
+    pub min_tick: u16,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Hpet {
+    base: VirtAddr,
+    period_fs: u64,
+    counter_64bit: bool,
+    num_comparators: u8,
+}
+
+impl HpetTable {
+    /// Parses the whole ACPI HPET table, header included, whose checksum
+    /// the caller has checked.
+    pub fn parse(table: &[u8]) -> Result<Self, HpetError> {
+        if table.get(..4) != Some(b"HPET") {
+            return Err(HpetError::BadSignature);
+        }
+        let table = table.get(..HPET_TABLE_LEN).ok_or(HpetError::Truncated)?;
+        if table[40] != ADDRESS_SPACE_MEMORY {
+            return Err(HpetError::NotMemoryMapped);
+        }
+        Ok(Self {
+            address: PhysAddr::new(u64::from_le_bytes(table[44..52].try_into().unwrap()) as usize),
+            number: table[52],
+            min_tick: u16::from_le_bytes([table[53], table[54]]),
+        })
+    }
+}
+
+impl Hpet {
+    /// Takes over the HPET whose registers are mapped uncached at BASE and
+    /// starts its main counter, with the legacy IRQ routing off.
+    ///
+    /// # Safety
+    /// BASE must map the HPET and nothing else may program it.
+    pub unsafe fn new(base: VirtAddr) -> Result<Self, HpetError> {
+        let mut hpet = Self {
+            base,
+            period_fs: 0,
+            counter_64bit: false,
+            num_comparators: 0,
+        };
+        let capabilities = hpet.read(REG_CAPABILITIES);
+        hpet.period_fs = capabilities >> CAP_PERIOD_SHIFT;
+        if hpet.period_fs == 0 || hpet.period_fs > MAX_PERIOD_FS {
+            return Err(HpetError::BadPeriod(hpet.period_fs));
+        }
+        hpet.counter_64bit = capabilities & CAP_COUNTER_64BIT != 0;
+        hpet.num_comparators = ((capabilities >> CAP_NUM_COMPARATORS_SHIFT) & 0x1f) as u8 + 1;
+
+        let config = hpet.read(REG_CONFIG) & !CONFIG_LEGACY_REPLACEMENT;
+        hpet.write(REG_CONFIG, config | CONFIG_ENABLE);
+        Ok(hpet)
+    }
+
+    /// Femtoseconds between two ticks of the main counter.
+    pub fn period_fs(&self) -> u64 {
+        self.period_fs
+    }
+
+    pub fn frequency_hz(&self) -> u64 {
+        FEMTOSECONDS_PER_SECOND / self.period_fs
+    }
+
+    /// Whether the main counter has 64 bits rather than 32, so that it
+    /// never wraps around in practice.
+    pub fn has_64bit_counter(&self) -> bool {
+        self.counter_64bit
+    }
+
+    pub fn num_comparators(&self) -> u8 {
+        self.num_comparators
+    }
+
+    /// The main counter.
+    pub fn counter(&self) -> u64 {
+        let counter = self.read(REG_MAIN_COUNTER);
+        if self.counter_64bit {
+            counter
+        } else {
+            counter as u32 as u64
+        }
+    }
+
+    /// Ticks between the counter readings START and END, END being the
+    /// later one and less than one wrap around away.
+    pub fn ticks_between(&self, start: u64, end: u64) -> u64 {
+        if self.counter_64bit {
+            end.wrapping_sub(start)
+        } else {
+            (end as u32).wrapping_sub(start as u32) as u64
+        }
+    }
+
+    pub fn ticks_to_ns(&self, ticks: u64) -> u64 {
+        (ticks as u128 * self.period_fs as u128 / FEMTOSECONDS_PER_NANOSECOND as u128) as u64
+    }
+
+    pub fn ns_to_ticks(&self, ns: u64) -> u64 {
+        (ns as u128 * FEMTOSECONDS_PER_NANOSECOND as u128).div_ceil(self.period_fs as u128) as u64
+    }
+
+    fn read(&self, reg: usize) -> u64 {
+        unsafe { (self.base + reg).as_ptr::<u64>().read_volatile() }
+    }
+
+    fn write(&mut self, reg: usize, value: u64) {
+        unsafe { (self.base + reg).as_mut_ptr::<u64>().write_volatile(value) }
+    }
+}
@@ -0,0 +1,20 @@
+#![no_std]
+
+mod hpet;
+mod pit;
+
+use core::arch::x86_64::__cpuid;
+
+pub use hpet::{Hpet, HpetError, HpetTable, FEMTOSECONDS_PER_SECOND, HPET_MMIO_SIZE};
+pub use pit::*;
+
+const CPUID_MAX_EXTENDED_LEAF: u32 = 0x8000_0000;
+const CPUID_ADVANCED_POWER: u32 = 0x8000_0007;
+const CPUID_INVARIANT_TSC: u32 = 1 << 8;
+
+/// Whether the TSC ticks at the same rate whatever the power state, so
+/// that it can keep time.
+pub fn has_invariant_tsc() -> bool {
+    __cpuid(CPUID_MAX_EXTENDED_LEAF).eax >= CPUID_ADVANCED_POWER
+        && __cpuid(CPUID_ADVANCED_POWER).edx & CPUID_INVARIANT_TSC != 0
+}
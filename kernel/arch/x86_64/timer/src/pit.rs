@@ -0,0 +1,107 @@
+//! The 8254 programmable interval timer. Channel 0 raises ISA IRQ 0 and
+//! channel 2, gated through port B, is polled for delays that need no
+//! interrupts.
+
+use core::hint::spin_loop;
+
+use kernel_cpu::{inb, outb};
+
+const PIT_CHANNEL0: u16 = 0x40;
+const PIT_CHANNEL2: u16 = 0x42;
+const PIT_COMMAND: u16 = 0x43;
+/// Port B of the keyboard controller, which gates channel 2 and shows its
+/// output.
+const PORT_B: u16 = 0x61;
+const PORT_B_GATE2: u8 = 1 << 0;
+const PORT_B_SPEAKER: u8 = 1 << 1;
+const PORT_B_OUT2: u8 = 1 << 5;
+
+const COMMAND_CHANNEL0: u8 = 0b00 << 6;
+const COMMAND_CHANNEL2: u8 = 0b10 << 6;
+const COMMAND_LATCH: u8 = 0b00 << 4;
+const COMMAND_LOW_HIGH: u8 = 0b11 << 4;
+const MODE_TERMINAL_COUNT: u8 = 0b000 << 1;
+const MODE_RATE_GENERATOR: u8 = 0b010 << 1;
+
+/// Rate the PIT counts down at.
+pub const PIT_FREQUENCY_HZ: u64 = 1_193_182;
+/// The ISA IRQ channel 0 raises.
+pub const PIT_IRQ: u8 = 0;
+/// Longest delay `pit_delay_ticks` can wait, in PIT periods.
+const MAX_DELAY_TICKS: u64 = u16::MAX as u64;
+
+/// Loads COUNT into CHANNEL, low byte first. COUNT 0 stands for 65536.
+fn write_count(channel: u16, count: u16) {
+    unsafe {
+        outb(channel, count as u8);
+        outb(channel, (count >> 8) as u8);
+    }
+}
+
+/// Makes channel 0 raise IRQ 0 every DIVISOR periods.
+pub fn pit_start_periodic(divisor: u16) {
+    kernel_cpu::without_interrupts(|| {
+        unsafe {
+            outb(
+                PIT_COMMAND,
+                COMMAND_CHANNEL0 | COMMAND_LOW_HIGH | MODE_RATE_GENERATOR,
+            )
+        };
+        write_count(PIT_CHANNEL0, divisor);
+    });
+}
+
+/// Makes channel 0 raise IRQ 0 once, COUNT periods from now.
+pub fn pit_start_oneshot(count: u16) {
+    kernel_cpu::without_interrupts(|| {
+        unsafe {
+            outb(
+                PIT_COMMAND,
+                COMMAND_CHANNEL0 | COMMAND_LOW_HIGH | MODE_TERMINAL_COUNT,
+            )
+        };
+        write_count(PIT_CHANNEL0, count);
+    });
+}
+
+/// Periods left before channel 0 next reaches zero.
+pub fn pit_count() -> u16 {
+    kernel_cpu::without_interrupts(|| unsafe {
+        outb(PIT_COMMAND, COMMAND_CHANNEL0 | COMMAND_LATCH);
+        let low = inb(PIT_CHANNEL0) as u16;
+        let high = inb(PIT_CHANNEL0) as u16;
+        high << 8 | low
+    })
+}
+
+/// Busy waits TICKS periods of the PIT, at least one, on channel 2.
+pub fn pit_delay_ticks(ticks: u16) {
+    let ticks = ticks.max(1);
+    kernel_cpu::without_interrupts(|| unsafe {
+        let port_b = inb(PORT_B) & !(PORT_B_SPEAKER | PORT_B_GATE2);
+        outb(PORT_B, port_b);
+        outb(
+            PIT_COMMAND,
+            COMMAND_CHANNEL2 | COMMAND_LOW_HIGH | MODE_TERMINAL_COUNT,
+        );
+        write_count(PIT_CHANNEL2, ticks);
+        // The count down starts when the gate goes high and the output goes
+        // high when it reaches zero.
+        outb(PORT_B, port_b | PORT_B_GATE2);
+        while inb(PORT_B) & PORT_B_OUT2 == 0 {
+            spin_loop();
+        }
+        outb(PORT_B, port_b);
+    });
+}
+
+/// Busy waits at least US microseconds on channel 2.
+pub fn pit_delay_us(us: u64) {
+    let ticks = (us as u128 * PIT_FREQUENCY_HZ as u128).div_ceil(1_000_000);
+    let mut ticks = ticks.min(u64::MAX as u128) as u64;
+    while ticks > 0 {
+        let chunk = ticks.min(MAX_DELAY_TICKS);
+        pit_delay_ticks(chunk as u16);
+        ticks -= chunk;
+    }
+}
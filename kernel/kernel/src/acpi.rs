@@ -0,0 +1,199 @@
+//! Finding the ACPI tables the firmware left in memory. The tables are read
+//! in place through the HHDM; nothing here interprets their contents.
+
+use core::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use kernel_boot_interface::{hhdm::BootHhdm, BootInfo};
+use kernel_log::kprintln;
+use teensy_std::addr::PhysAddr;
+
+/// Most tables the RSDT or XSDT may list that are kept track of.
+pub const MAX_ACPI_TABLES: usize = 64;
+
+/// Length of the header every table but the RSDP starts with.
+pub const SDT_HEADER_LEN: usize = 36;
+/// Length of the ACPI 1.0 RSDP, which the first checksum covers.
+const RSDP_V1_LEN: usize = 20;
+/// Length of the ACPI 2.0 RSDP, which the extended checksum covers.
+const RSDP_V2_LEN: usize = 36;
+
+static TABLES: spin::Once<AcpiTables> = spin::Once::new();
+/// Set once the memory the tables live in may have been reclaimed.
+static FORGOTTEN: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcpiError {
+    /// Limine found no RSDP.
+    NoRsdp,
+    BadRsdp,
+    /// The table with this signature failed its checksum.
+    BadChecksum([u8; 4]),
+    /// The root table with this signature is shorter than its header.
+    TooShort([u8; 4]),
+}
+
+struct AcpiTables {
+    hhdm: BootHhdm,
+    revision: u8,
+    tables: [PhysAddr; MAX_ACPI_TABLES],
+    len: usize,
+}
+
+impl fmt::Display for AcpiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AcpiError::NoRsdp => write!(f, "no RSDP"),
+            AcpiError::BadRsdp => write!(f, "the RSDP is corrupt"),
+            AcpiError::BadChecksum(signature) => write!(
+                f,
+                "bad checksum in table {}",
+                core::str::from_utf8(signature).unwrap_or("????")
+            ),
+            AcpiError::TooShort(signature) => write!(
+                f,
+                "table {} is shorter than its header",
+                core::str::from_utf8(signature).unwrap_or("????")
+            ),
+        }
+    }
+}
+
+/// Whether the bytes of TABLE add up to zero, as every ACPI table's must.
+fn checksum_ok(table: &[u8]) -> bool {
+    table.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)) == 0
+}
+
+/// The LEN bytes at PHYS.
+///
+/// # Safety
+/// The bytes must be mapped in the HHDM and not change while in use.
+unsafe fn phys_bytes(hhdm: &BootHhdm, phys: PhysAddr, len: usize) -> &'static [u8] {
+    core::slice::from_raw_parts(hhdm.phys_to_virt(phys).as_ptr(), len)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+/// Checks the RSDP and records the tables the XSDT, or on ACPI 1.0 machines
+/// the RSDT, lists. Later calls do nothing.
+pub fn init(boot_info: &BootInfo) -> Result<(), AcpiError> {
+    if TABLES.is_completed() {
+        return Ok(());
+    }
+    let tables = AcpiTables::from_rsdp(boot_info)?;
+    kprintln!("acpi: revision {}, {} tables", tables.revision, tables.len);
+    TABLES.call_once(|| tables);
+    Ok(())
+}
+
+/// Stops handing out tables, as the memory they live in is about to be
+/// reclaimed. Tables found before must no longer be used.
+pub fn forget_tables() {
+    FORGOTTEN.store(true, Ordering::SeqCst);
+}
+
+fn tables() -> Option<&'static AcpiTables> {
+    if FORGOTTEN.load(Ordering::SeqCst) {
+        return None;
+    }
+    TABLES.get()
+}
+
+/// The whole table with SIGNATURE, header included, if there is one with a
+/// good checksum. `init` must have run, and `reclaim_acpi_memory` must not
+/// have, or there are no tables.
+pub fn find_table(signature: &[u8; 4]) -> Option<&'static [u8]> {
+    tables()?
+        .iter()
+        .find(|table| table[..4] == *signature && checksum_ok(table))
+}
+
+/// Signatures of every table listed, in the order the firmware lists them.
+/// Empty once `reclaim_acpi_memory` has run.
+pub fn signatures() -> impl Iterator<Item = [u8; 4]> {
+    tables()
+        .into_iter()
+        .flat_map(|tables| tables.iter())
+        .map(|table| table[..4].try_into().unwrap())
+}
+
+impl AcpiTables {
+    fn from_rsdp(boot_info: &BootInfo) -> Result<Self, AcpiError> {
+        let hhdm = boot_info.hhdm;
+        let rsdp_phys = boot_info.rsdp.ok_or(AcpiError::NoRsdp)?;
+        let rsdp = unsafe { phys_bytes(&hhdm, rsdp_phys, RSDP_V1_LEN) };
+        if rsdp[..8] != *b"RSD PTR " || !checksum_ok(rsdp) {
+            return Err(AcpiError::BadRsdp);
+        }
+        let revision = rsdp[15];
+
+        // ACPI 2.0 and later have the XSDT, with 64-bit table addresses.
+        let (root, entry_len) = if revision >= 2 {
+            let rsdp = unsafe { phys_bytes(&hhdm, rsdp_phys, RSDP_V2_LEN) };
+            if !checksum_ok(rsdp) {
+                return Err(AcpiError::BadRsdp);
+            }
+            (read_u64(rsdp, 24) as usize, 8)
+        } else {
+            (read_u32(rsdp, 16) as usize, 4)
+        };
+
+        let root = PhysAddr::new(root);
+        let header = unsafe { phys_bytes(&hhdm, root, SDT_HEADER_LEN) };
+        let root_len = read_u32(header, 4) as usize;
+        if root_len < SDT_HEADER_LEN {
+            return Err(AcpiError::TooShort(header[..4].try_into().unwrap()));
+        }
+        let root_table = unsafe { phys_bytes(&hhdm, root, root_len) };
+        if !checksum_ok(root_table) {
+            return Err(AcpiError::BadChecksum(header[..4].try_into().unwrap()));
+        }
+
+        let mut tables = Self {
+            hhdm,
+            revision,
+            tables: [PhysAddr::zero(); MAX_ACPI_TABLES],
+            len: 0,
+        };
+        for entry in root_table[SDT_HEADER_LEN..].chunks_exact(entry_len) {
+            if tables.len == MAX_ACPI_TABLES {
+                kprintln!(
+                    "acpi: more than {} tables, ignoring the rest",
+                    MAX_ACPI_TABLES
+                );
+                break;
+            }
+            let addr = match entry_len {
+                8 => read_u64(entry, 0) as usize,
+                _ => read_u32(entry, 0) as usize,
+            };
+            let phys = PhysAddr::new(addr);
+            let header = unsafe { phys_bytes(&hhdm, phys, SDT_HEADER_LEN) };
+            if (read_u32(header, 4) as usize) < SDT_HEADER_LEN {
+                kprintln!(
+                    "acpi: table at {:#x} is shorter than its header, ignoring it",
+                    addr
+                );
+                continue;
+            }
+            tables.tables[tables.len] = phys;
+            tables.len += 1;
+        }
+        Ok(tables)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &'static [u8]> + '_ {
+        self.tables[..self.len].iter().map(|&phys| unsafe {
+            let header = phys_bytes(&self.hhdm, phys, SDT_HEADER_LEN);
+            phys_bytes(&self.hhdm, phys, read_u32(header, 4) as usize)
+        })
+    }
+}
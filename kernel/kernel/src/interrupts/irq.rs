@@ -0,0 +1,427 @@
+//! Who handles which interrupt. Drivers register a handler and a cookie for
+//! an IOAPIC line or for a vector of their own, raised by MSIs or by a
+//! source in the local APIC. Every handler on a line is called on each of
+//! its interrupts, as any of the devices sharing it may have raised it, and
+//! the interrupt counts as handled if one of them claims it.
+
+use core::fmt;
+
+use kernel_interrupts::{
+    InterruptError, IrqLine, Polarity, Trigger, LAPIC_ERROR_VECTOR, PIC1_VECTOR_BASE,
+    SPURIOUS_VECTOR,
+};
+use kernel_log::kprintln;
+
+use crate::synch::Mutex;
+
+/// Most handlers one line or vector can have.
+pub const MAX_HANDLERS_PER_VECTOR: usize = 8;
+/// First vector handed out. The ones below belong to the CPU exceptions and
+/// to the masked legacy PICs.
+pub const FIRST_DEVICE_VECTOR: u8 = 0x30;
+/// Last vector handed out. The ones above belong to the local APIC.
+pub const LAST_DEVICE_VECTOR: u8 = LAPIC_ERROR_VECTOR - 1;
+const NUM_DEVICE_VECTORS: usize = (LAST_DEVICE_VECTOR - FIRST_DEVICE_VECTOR) as usize + 1;
+
+/// Where MSIs are written to, with the destination APIC ID from bit 12 up.
+const MSI_ADDRESS_BASE: u64 = 0xfee0_0000;
+const MSI_DESTINATION_SHIFT: u64 = 12;
+
+static IRQS: Mutex<IrqTable> = Mutex::new(IrqTable::new());
+
+/// Called with the cookie it was registered with, in interrupt context and
+/// with interrupts disabled. It must not register or free handlers.
+pub type IrqHandler = fn(cookie: usize) -> IrqReturn;
+
+/// Whether a handler's device raised the interrupt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqReturn {
+    Handled,
+    NotHandled,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqError {
+    Controller(InterruptError),
+    /// Every device vector is taken.
+    NoFreeVector,
+    /// The line already has `MAX_HANDLERS_PER_VECTOR` handlers.
+    TooManyHandlers(u32),
+    /// The line is already in use with another polarity or trigger mode.
+    LineMismatch(u32),
+}
+
+/// A registered handler, to give back to `free_irq`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct IrqHandle {
+    vector: u8,
+    slot: usize,
+}
+
+/// What a device must write to raise an MSI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MsiMessage {
+    pub address: u64,
+    pub data: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VectorSource {
+    Line(IrqLine),
+    Msi,
+    Local,
+}
+
+#[derive(Clone, Copy)]
+struct Action {
+    name: &'static str,
+    handler: IrqHandler,
+    cookie: usize,
+    handled: u64,
+}
+
+#[derive(Clone, Copy)]
+struct VectorDesc {
+    source: Option<VectorSource>,
+    actions: [Option<Action>; MAX_HANDLERS_PER_VECTOR],
+    count: u64,
+    unhandled: u64,
+}
+
+struct IrqTable {
+    vectors: [VectorDesc; NUM_DEVICE_VECTORS],
+    spurious: u64,
+    unhandled: u64,
+}
+
+impl fmt::Display for IrqError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IrqError::Controller(err) => write!(f, "{}", err),
+            IrqError::NoFreeVector => write!(f, "no free interrupt vector"),
+            IrqError::TooManyHandlers(gsi) => write!(f, "GSI {} has too many handlers", gsi),
+            IrqError::LineMismatch(gsi) => {
+                write!(f, "GSI {} is in use with another polarity or trigger", gsi)
+            }
+        }
+    }
+}
+
+impl From<InterruptError> for IrqError {
+    fn from(err: InterruptError) -> Self {
+        IrqError::Controller(err)
+    }
+}
+
+impl IrqHandle {
+    /// The vector the handler runs on.
+    pub fn vector(&self) -> u8 {
+        self.vector
+    }
+
+    /// What the device must write to raise the handle's MSI on this CPU.
+    pub fn msi_message(&self) -> MsiMessage {
+        let apic_id = kernel_interrupts::local_apic().map_or(0, |lapic| lapic.id());
+        MsiMessage {
+            address: MSI_ADDRESS_BASE | (apic_id as u64) << MSI_DESTINATION_SHIFT,
+            data: self.vector as u32,
+        }
+    }
+}
+
+impl VectorDesc {
+    const EMPTY: Self = Self {
+        source: None,
+        actions: [None; MAX_HANDLERS_PER_VECTOR],
+        count: 0,
+        unhandled: 0,
+    };
+
+    fn is_idle(&self) -> bool {
+        self.actions.iter().all(Option::is_none)
+    }
+}
+
+impl IrqTable {
+    const fn new() -> Self {
+        Self {
+            vectors: [VectorDesc::EMPTY; NUM_DEVICE_VECTORS],
+            spurious: 0,
+            unhandled: 0,
+        }
+    }
+
+    fn desc(&self, vector: u8) -> Option<&VectorDesc> {
+        self.vectors
+            .get(vector.checked_sub(FIRST_DEVICE_VECTOR)? as usize)
+    }
+
+    fn desc_mut(&mut self, vector: u8) -> Option<&mut VectorDesc> {
+        self.vectors
+            .get_mut(vector.checked_sub(FIRST_DEVICE_VECTOR)? as usize)
+    }
+
+    fn vector_of(&self, gsi: u32) -> Option<u8> {
+        self.iter()
+            .find(|(_, desc)| matches!(desc.source, Some(VectorSource::Line(line)) if line.gsi == gsi))
+            .map(|(vector, _)| vector)
+    }
+
+    /// A vector nothing uses, with its counts reset.
+    fn alloc_vector(&mut self, source: VectorSource) -> Result<u8, IrqError> {
+        let (idx, desc) = self
+            .vectors
+            .iter_mut()
+            .enumerate()
+            .find(|(_, desc)| desc.source.is_none())
+            .ok_or(IrqError::NoFreeVector)?;
+        *desc = VectorDesc {
+            source: Some(source),
+            ..VectorDesc::EMPTY
+        };
+        Ok(FIRST_DEVICE_VECTOR + idx as u8)
+    }
+
+    fn add_action(&mut self, vector: u8, action: Action) -> Option<IrqHandle> {
+        let desc = self.desc_mut(vector)?;
+        let slot = desc.actions.iter().position(Option::is_none)?;
+        desc.actions[slot] = Some(action);
+        Some(IrqHandle { vector, slot })
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (u8, &VectorDesc)> {
+        (FIRST_DEVICE_VECTOR..=LAST_DEVICE_VECTOR).zip(self.vectors.iter())
+    }
+}
+
+impl fmt::Display for IrqTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "irq: {} spurious, {} unhandled",
+            self.spurious, self.unhandled
+        )?;
+        for (vector, desc) in self.iter() {
+            let Some(source) = desc.source else {
+                continue;
+            };
+            write!(f, "\n  vector {:#04x} ", vector)?;
+            match source {
+                VectorSource::Line(line) => write!(
+                    f,
+                    "GSI {} {} {}",
+                    line.gsi,
+                    match line.trigger {
+                        Trigger::Edge => "edge",
+                        Trigger::Level => "level",
+                    },
+                    match line.polarity {
+                        Polarity::ActiveHigh => "high",
+                        Polarity::ActiveLow => "low",
+                    }
+                )?,
+                VectorSource::Msi => write!(f, "MSI")?,
+                VectorSource::Local => write!(f, "local")?,
+            }
+            write!(f, ": {} ({} unhandled)", desc.count, desc.unhandled)?;
+            for action in desc.actions.iter().flatten() {
+                write!(f, " {}={}", action.name, action.handled)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Registers HANDLER for LINE, which is routed to this CPU and unmasked if
+/// it was not in use yet. A line in use can take more handlers if its
+/// polarity and trigger mode match.
+pub fn request_irq(
+    line: IrqLine,
+    name: &'static str,
+    handler: IrqHandler,
+    cookie: usize,
+) -> Result<IrqHandle, IrqError> {
+    let action = new_action(name, handler, cookie);
+    with_irqs(|irqs| {
+        let (vector, fresh) = match irqs.vector_of(line.gsi) {
+            Some(vector) => {
+                if irqs.desc(vector).and_then(|desc| desc.source) != Some(VectorSource::Line(line))
+                {
+                    return Err(IrqError::LineMismatch(line.gsi));
+                }
+                (vector, false)
+            }
+            None => {
+                let vector = irqs.alloc_vector(VectorSource::Line(line))?;
+                if let Err(err) = kernel_interrupts::route(line, vector) {
+                    *irqs.desc_mut(vector).unwrap() = VectorDesc::EMPTY;
+                    return Err(err.into());
+                }
+                (vector, true)
+            }
+        };
+        let handle = irqs
+            .add_action(vector, action)
+            .ok_or(IrqError::TooManyHandlers(line.gsi))?;
+        if let Err(err) = kernel_interrupts::unmask(line.gsi) {
+            // Routing left a fresh line masked, so its vector can go back.
+            let desc = irqs.desc_mut(vector).unwrap();
+            if fresh {
+                *desc = VectorDesc::EMPTY;
+            } else {
+                desc.actions[handle.slot] = None;
+            }
+            return Err(err.into());
+        }
+        Ok(handle)
+    })
+}
+
+/// Registers HANDLER on a vector of its own, for a device to raise with the
+/// MSI message of the returned handle.
+pub fn request_msi(
+    name: &'static str,
+    handler: IrqHandler,
+    cookie: usize,
+) -> Result<IrqHandle, IrqError> {
+    request_own_vector(VectorSource::Msi, new_action(name, handler, cookie))
+}
+
+/// Registers HANDLER on a vector of its own, for a source in the local APIC,
+/// such as its timer, to raise.
+pub fn request_vector(
+    name: &'static str,
+    handler: IrqHandler,
+    cookie: usize,
+) -> Result<IrqHandle, IrqError> {
+    request_own_vector(VectorSource::Local, new_action(name, handler, cookie))
+}
+
+fn request_own_vector(source: VectorSource, action: Action) -> Result<IrqHandle, IrqError> {
+    with_irqs(|irqs| {
+        let vector = irqs.alloc_vector(source)?;
+        Ok(irqs.add_action(vector, action).unwrap())
+    })
+}
+
+/// Unregisters the handler of HANDLE. A line left without handlers is
+/// masked, and a vector left without handlers is free to be handed out
+/// again.
+pub fn free_irq(handle: IrqHandle) {
+    with_irqs(|irqs| {
+        let desc = irqs
+            .desc_mut(handle.vector)
+            .expect("IRQ handles are for device vectors");
+        assert!(
+            desc.actions[handle.slot].take().is_some(),
+            "IRQ handler freed twice"
+        );
+        if !desc.is_idle() {
+            return;
+        }
+        if let Some(VectorSource::Line(line)) = desc.source {
+            kernel_interrupts::mask(line.gsi).expect("A routed line has an IOAPIC");
+        }
+        desc.source = None;
+    });
+}
+
+/// Interrupts nobody raised: the local APIC's spurious ones and those of the
+/// masked legacy PICs.
+pub fn spurious_count() -> u64 {
+    with_irqs(|irqs| irqs.spurious)
+}
+
+/// Interrupts on a device vector no handler claimed, or that had none.
+pub fn unhandled_count() -> u64 {
+    with_irqs(|irqs| irqs.unhandled)
+}
+
+/// Times VECTOR was raised since it was last handed out.
+pub fn irq_count(vector: u8) -> u64 {
+    with_irqs(|irqs| irqs.desc(vector).map_or(0, |desc| desc.count))
+}
+
+/// Times the handler of HANDLE claimed an interrupt.
+pub fn handled_count(handle: &IrqHandle) -> u64 {
+    with_irqs(|irqs| {
+        irqs.desc(handle.vector)
+            .and_then(|desc| desc.actions[handle.slot])
+            .map_or(0, |action| action.handled)
+    })
+}
+
+/// Writes the interrupt counts of every vector in use to the serial log.
+pub fn dump_stats() {
+    with_irqs(|irqs| {
+        kprintln!("{}", irqs);
+    });
+}
+
+fn with_irqs<T>(f: impl FnOnce(&mut IrqTable) -> T) -> T {
+    kernel_cpu::without_interrupts(|| f(&mut IRQS.lock()))
+}
+
+fn new_action(name: &'static str, handler: IrqHandler, cookie: usize) -> Action {
+    Action {
+        name,
+        handler,
+        cookie,
+        handled: 0,
+    }
+}
+
+/// Called by the CPU code for every vector from 32 up.
+pub(super) fn dispatch(vector: u8) {
+    // The legacy PICs are masked, so all they can still raise is a spurious
+    // IRQ 7, which needs no end of interrupt.
+    if vector == SPURIOUS_VECTOR || (PIC1_VECTOR_BASE..FIRST_DEVICE_VECTOR).contains(&vector) {
+        IRQS.lock().spurious += 1;
+        return;
+    }
+    if vector == LAPIC_ERROR_VECTOR {
+        if let Some(lapic) = kernel_interrupts::local_apic() {
+            kprintln!("irq: local APIC error {:#x}", lapic.clear_errors());
+        }
+        kernel_interrupts::end_of_interrupt();
+        return;
+    }
+
+    // The handlers run without the lock, so that they can look at the
+    // statistics or start a timer that is served by this same table.
+    let actions = {
+        let mut irqs = IRQS.lock();
+        irqs.desc_mut(vector).map(|desc| {
+            desc.count += 1;
+            desc.actions
+        })
+    };
+    let mut results = [IrqReturn::NotHandled; MAX_HANDLERS_PER_VECTOR];
+    if let Some(actions) = actions {
+        for (action, result) in actions.iter().zip(&mut results) {
+            if let Some(action) = action {
+                *result = (action.handler)(action.cookie);
+            }
+        }
+    }
+    let handled = results.contains(&IrqReturn::Handled);
+
+    // Handlers may not register or free handlers, so each slot still holds
+    // the action that was called.
+    let mut irqs = IRQS.lock();
+    if let Some(desc) = irqs.desc_mut(vector) {
+        for (action, result) in desc.actions.iter_mut().zip(results) {
+            if let (Some(action), IrqReturn::Handled) = (action, result) {
+                action.handled += 1;
+            }
+        }
+        if !handled {
+            desc.unhandled += 1;
+        }
+    }
+    if !handled {
+        irqs.unhandled += 1;
+    }
+    drop(irqs);
+    kernel_interrupts::end_of_interrupt();
+}
@@ -0,0 +1,25 @@
+mod irq;
+
+use kernel_log::kprintln;
+use kernel_paging::CacheMode;
+
+pub use irq::*;
+
+use crate::{acpi, memory::ioremap::ioremap};
+
+/// Masks the legacy PICs and brings up the local APIC and the IOAPICs the
+/// MADT lists, with every line masked, then takes over every vector from 32
+/// up. ACPI, paging, the heap and the IDT must be up. Interrupts stay
+/// disabled.
+pub fn init() {
+    let madt = acpi::find_table(b"APIC").expect("No MADT, so no APICs to take interrupts");
+    kernel_interrupts::init(madt, |phys, len| {
+        ioremap(phys, len, CacheMode::Uncached).expect("Could not map an interrupt controller")
+    })
+    .unwrap_or_else(|err| panic!("interrupts: {}", err));
+
+    if let Some(madt) = kernel_interrupts::madt() {
+        kprintln!("{}", madt);
+    }
+    kernel_cpu::set_interrupt_handler(irq::dispatch);
+}
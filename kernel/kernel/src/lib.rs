@@ -1,12 +1,18 @@
 #![no_std]
 #![no_main]
 #![feature(custom_test_frameworks)]
+#![feature(alloc_error_handler)]
 #![test_runner(kernel_test::test_runner)]
 #![reexport_test_harness_main = "test_main"]
 
+extern crate alloc;
+
+pub mod acpi;
+pub mod interrupts;
 pub mod memory;
-mod panic;
+pub mod panic;
 pub mod synch;
+pub mod time;
 
 #[cfg(test)]
 #[no_mangle]
@@ -1,23 +1,31 @@
 #![no_std]
 #![no_main]
 #![feature(custom_test_frameworks)]
+#![feature(alloc_error_handler)]
 #![test_runner(kernel_test::test_runner)]
 #![reexport_test_harness_main = "test_main"]
 
+extern crate alloc;
+
+mod acpi;
+mod interrupts;
 mod memory;
 mod panic;
 mod synch;
+mod time;
 
 use kernel_boot;
-use kernel_boot_interface;
 use kernel_cpu;
 use kernel_log::kprintln;
-use memory::palloc;
+use kernel_paging::CacheMode;
+use memory::{
+    fault, heap, ioremap::ioremap, kstack::KernelStack, memmap, memtest, paging, palloc, reclaim,
+    slab,
+};
 
-unsafe fn put_white(x: u64, y: u64, binfo: &kernel_boot_interface::BootInfo) {
-    let ptr = (binfo.frame_buffer.phys_address + binfo.hhdm.base) as *mut u8;
-    let offset = y * binfo.frame_buffer.pitch + x * 4;
-    *(ptr.offset(offset as isize) as *mut u32) = 0xffaa_3333;
+unsafe fn put_white(x: u64, y: u64, pixels: *mut u8, pitch: u64) {
+    let offset = y * pitch + x * 4;
+    *(pixels.offset(offset as isize) as *mut u32) = 0xffaa_3333;
 }
 
 #[no_mangle]
@@ -27,15 +35,54 @@ pub extern "C" fn _kernel_start() -> ! {
     #[cfg(test)]
     kernel_shutdown::shutdown(kernel_shutdown::ShutdownExitCode::Success);
 
+    kernel_cpu::init_gdt();
+    kernel_cpu::init_idt();
+    kprintln!("{}", boot_info.memmap);
+    if let Some(passes) = memtest::passes_requested(&boot_info.cmdline) {
+        memmap::init(&boot_info.memmap);
+        memtest::run(&boot_info.hhdm, passes);
+    }
     palloc::init(&boot_info.hhdm, &boot_info.memmap);
+    paging::init(boot_info);
+    heap::init(&boot_info.hhdm);
+    slab::init(&boot_info.hhdm);
+    fault::init(&boot_info.hhdm);
+    acpi::init(boot_info).unwrap_or_else(|err| panic!("acpi: {}", err));
+    interrupts::init();
+    time::init();
+    // Every ACPI table the kernel reads has been parsed by now.
+    reclaim::reclaim_acpi_memory(boot_info);
+    kernel_cpu::enable_interrupts();
+
+    // Leave limine's stack, which has no guard page, for good.
+    let stack = KernelStack::new().expect("Out of memory for the boot stack");
+    let top = stack.top().as_usize();
+    core::mem::forget(stack);
+    unsafe { kernel_cpu::switch_stack(top, kernel_main) };
+}
+
+extern "C" fn kernel_main() -> ! {
+    let boot_info = kernel_boot::arch_init();
+    reclaim::reclaim_bootloader_memory();
+    palloc::dump_stats();
+    interrupts::dump_stats();
+    kprintln!("Up for {} ms", time::now_ns() / 1_000_000);
 
-    let a = palloc::get_page().as_ptr::<u8>();
-    let b = palloc::get_page().as_ptr::<u8>();
+    let a = palloc::get_page();
+    let b = palloc::get_page();
 
     kprintln!("a has address: {:?}\n and here is b's {:?}", a, b);
+    let fb = &boot_info.frame_buffer;
+    let pixels = ioremap(
+        fb.phys_address,
+        (fb.pitch * fb.height) as usize,
+        CacheMode::WriteCombining,
+    )
+    .expect("Could not map the framebuffer")
+    .as_mut_ptr::<u8>();
     for j in 0..600 {
         for i in 0..600 {
-            unsafe { put_white(i, j, &boot_info) };
+            unsafe { put_white(i, j, pixels, fb.pitch) };
         }
     }
 
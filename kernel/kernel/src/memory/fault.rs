@@ -0,0 +1,121 @@
+use kernel_boot_interface::hhdm::BootHhdm;
+use kernel_cpu::{InterruptStackFrame, PageFault};
+use kernel_paging::{FrameAllocator, PageSize};
+use teensy_std::addr::{Page, PhysAddr, PhysFrame, VirtAddr, PAGE_SIZE};
+
+use crate::memory::{
+    kstack, paging, palloc,
+    vmregion::{self, VmKind, VmRegion},
+};
+
+static HHDM: spin::Once<BootHhdm> = spin::Once::new();
+
+/// Installs the page fault and double fault handlers. palloc and paging must
+/// be up.
+pub fn init(hhdm: &BootHhdm) {
+    HHDM.call_once(|| *hhdm);
+    kernel_cpu::set_page_fault_handler(handle_page_fault);
+    kernel_cpu::set_double_fault_handler(handle_double_fault);
+    kernel_cpu::init_idt();
+}
+
+fn handle_page_fault(fault: &PageFault) {
+    let addr = VirtAddr::new(fault.addr);
+    kstack::check_stack_overflow(addr);
+
+    let region = vmregion::try_find(addr).unwrap_or_else(|_| lock_held(addr, "vm region"));
+    if let Some(region) = region {
+        if can_demand_page(fault, &region) && map_zeroed(addr, &region) {
+            return;
+        }
+    }
+
+    panic!(
+        "Unhandled page fault at {:?}: {:?}\n  rip {:#x}, rsp {:#x}, cr3 {:#x}\n  region {:?}",
+        addr,
+        fault.error,
+        fault.frame.rip,
+        fault.frame.rsp,
+        kernel_cpu::cr3(),
+        region
+    );
+}
+
+/// The fault may have interrupted the holder of a lock the handler needs,
+/// which would then wait for it for ever, so the handler only tries its
+/// locks and gives up when one is held.
+fn lock_held(addr: VirtAddr, lock: &str) -> ! {
+    panic!(
+        "Page fault at {:?} while the {} lock is held, it cannot be handled",
+        addr, lock
+    );
+}
+
+/// A page fault on a guard page of a kernel stack cannot push its frame on
+/// that same stack, so the CPU raises a double fault instead, on a stack of
+/// its own, and leaves the faulting address in cr2.
+fn handle_double_fault(_frame: &InterruptStackFrame) {
+    kstack::check_stack_overflow(VirtAddr::new(kernel_cpu::cr2()));
+}
+
+/// Only a kernel access to a page of a demand zero region that is not mapped
+/// yet, and which the region allows, gets a page.
+fn can_demand_page(fault: &PageFault, region: &VmRegion) -> bool {
+    region.kind == VmKind::DemandZero
+        && !fault.error.present()
+        && !fault.error.user()
+        && (!fault.error.write() || region.flags.writable)
+        && (!fault.error.instruction_fetch() || !region.flags.no_execute)
+}
+
+/// Backs the page holding ADDR with a zeroed frame.
+fn map_zeroed(addr: VirtAddr, region: &VmRegion) -> bool {
+    let frame = match palloc::try_get_page() {
+        Ok(Some(frame)) => frame,
+        Ok(None) => return false,
+        Err(_) => lock_held(addr, "page pool"),
+    };
+    unsafe {
+        HHDM.wait()
+            .phys_to_virt(frame.start())
+            .as_mut_ptr::<u8>()
+            .write_bytes(0, PAGE_SIZE);
+    }
+    let Some(mut space) = paging::try_kernel_space() else {
+        palloc::free_page(frame);
+        lock_held(addr, "kernel address space");
+    };
+    let mapped = space.map(
+        Page::containing(addr).start().as_usize(),
+        frame.start().as_usize(),
+        PageSize::Size4KiB,
+        region.flags,
+        &mut FaultFrameAllocator { addr },
+    );
+    drop(space);
+    if mapped.is_err() {
+        palloc::free_page(frame);
+        return false;
+    }
+    true
+}
+
+/// Hands out page tables for the mapping of a faulting address, without
+/// waiting for the pool lock. Frees take it as usual: the pool was free a
+/// moment ago, so the fault did not interrupt its holder.
+struct FaultFrameAllocator {
+    addr: VirtAddr,
+}
+
+impl FrameAllocator for FaultFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<usize> {
+        match palloc::try_get_page() {
+            Ok(frame) => frame.map(|frame| frame.start().as_usize()),
+            Err(_) => lock_held(self.addr, "page pool"),
+        }
+    }
+
+    fn free_frame(&mut self, frame: usize) {
+        palloc::free_page(PhysFrame::containing(PhysAddr::new(frame)));
+    }
+}
@@ -0,0 +1,206 @@
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    mem, ptr,
+};
+
+use kernel_boot_interface::hhdm::BootHhdm;
+use kernel_log::kprintln;
+
+use crate::memory::palloc;
+use crate::synch::Mutex;
+
+/// Smallest number of pages the heap asks palloc for when it runs dry.
+const GROW_MIN_PAGES: usize = 16;
+/// Every block handed out or kept free is a multiple of this so that a free
+/// block header always fits.
+const BLOCK_ALIGN: usize = mem::align_of::<FreeBlock>();
+const BLOCK_MIN: usize = mem::size_of::<FreeBlock>();
+
+#[global_allocator]
+static HEAP: KernelHeap = KernelHeap {
+    inner: Mutex::new(Heap::empty()),
+};
+
+/// Header written at the start of every free region. The list is kept sorted
+/// by address so that neighbouring regions can be merged.
+#[repr(C, align(16))]
+struct FreeBlock {
+    size: usize,
+    next: *mut FreeBlock,
+}
+
+struct Heap {
+    head: *mut FreeBlock,
+    hhdm: Option<BootHhdm>,
+    pages: usize,
+}
+
+struct KernelHeap {
+    inner: Mutex<Heap>,
+}
+
+// Safety: the free list is only reached through the heap's mutex.
+unsafe impl Send for Heap {}
+
+/// Lets the heap grow out of palloc. palloc must be initialised first.
+pub fn init(hhdm: &BootHhdm) {
+    HEAP.inner.lock().hhdm = Some(*hhdm);
+}
+
+/// Number of pages the heap took from palloc.
+pub fn heap_pages() -> usize {
+    HEAP.inner.lock().pages
+}
+
+unsafe impl GlobalAlloc for KernelHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.inner.lock().alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.lock().dealloc(ptr, layout)
+    }
+}
+
+#[alloc_error_handler]
+fn alloc_error(layout: Layout) -> ! {
+    kprintln!(
+        "Kernel heap failed to allocate {} bytes aligned to {}",
+        layout.size(),
+        layout.align()
+    );
+    panic!("Kernel heap out of memory");
+}
+
+impl Heap {
+    const fn empty() -> Self {
+        Self {
+            head: ptr::null_mut(),
+            hhdm: None,
+            pages: 0,
+        }
+    }
+
+    fn alloc(&mut self, layout: Layout) -> *mut u8 {
+        let (size, align) = block_layout(layout);
+        if let Some(addr) = self.take_first_fit(size, align) {
+            return addr as *mut u8;
+        }
+        if self.grow(size + align + BLOCK_MIN) {
+            if let Some(addr) = self.take_first_fit(size, align) {
+                return addr as *mut u8;
+            }
+        }
+        ptr::null_mut()
+    }
+
+    fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        let (size, _) = block_layout(layout);
+        unsafe { self.insert(ptr as usize, size) };
+    }
+
+    /// Carves SIZE bytes aligned to ALIGN out of the first free block they fit in.
+    fn take_first_fit(&mut self, size: usize, align: usize) -> Option<usize> {
+        let mut prev: *mut FreeBlock = ptr::null_mut();
+        let mut cur = self.head;
+        while !cur.is_null() {
+            let block_start = cur as usize;
+            let (block_size, next) = unsafe { ((*cur).size, (*cur).next) };
+            let block_end = block_start + block_size;
+
+            let mut start = align_up(block_start, align);
+            if start != block_start && start - block_start < BLOCK_MIN {
+                // The leftover in front must be able to hold a header.
+                start = align_up(block_start + BLOCK_MIN, align);
+            }
+            let end = start + size;
+            let tail = block_end.saturating_sub(end);
+            if end <= block_end && (tail == 0 || tail >= BLOCK_MIN) {
+                unsafe {
+                    // Unlink the block and give back whatever is left around the allocation.
+                    if prev.is_null() {
+                        self.head = next;
+                    } else {
+                        (*prev).next = next;
+                    }
+                    if start != block_start {
+                        self.insert(block_start, start - block_start);
+                    }
+                    if tail != 0 {
+                        self.insert(end, tail);
+                    }
+                }
+                return Some(start);
+            }
+            prev = cur;
+            cur = next;
+        }
+        None
+    }
+
+    /// Pulls enough pages from palloc to hold at least BYTES.
+    fn grow(&mut self, bytes: usize) -> bool {
+        let Some(hhdm) = self.hhdm else {
+            return false;
+        };
+        let num_pages = usize::max(
+            GROW_MIN_PAGES,
+            kernel_paging::page_min_no(kernel_paging::page_min_round_up(bytes)),
+        );
+        let Some(frame) = palloc::get_pages(num_pages) else {
+            return false;
+        };
+
+        self.pages += num_pages;
+        unsafe {
+            self.insert(
+                hhdm.phys_to_virt(frame.start()).as_usize(),
+                num_pages * kernel_paging::PAGE_SIZE_MIN,
+            )
+        };
+        true
+    }
+
+    /// Adds [ADDR, ADDR + SIZE) to the free list, merging it with its neighbours.
+    ///
+    /// # Safety
+    /// The region must be unused heap memory aligned to BLOCK_ALIGN.
+    unsafe fn insert(&mut self, addr: usize, size: usize) {
+        debug_assert!(addr.is_multiple_of(BLOCK_ALIGN) && size >= BLOCK_MIN);
+
+        let mut prev: *mut FreeBlock = ptr::null_mut();
+        let mut next = self.head;
+        while !next.is_null() && (next as usize) < addr {
+            prev = next;
+            next = (*next).next;
+        }
+        debug_assert!(next.is_null() || addr + size <= next as usize, "Heap double free");
+
+        let block = addr as *mut FreeBlock;
+        block.write(FreeBlock { size, next });
+        if !next.is_null() && addr + size == next as usize {
+            (*block).size += (*next).size;
+            (*block).next = (*next).next;
+        }
+
+        if prev.is_null() {
+            self.head = block;
+        } else if prev as usize + (*prev).size == addr {
+            (*prev).size += (*block).size;
+            (*prev).next = (*block).next;
+        } else {
+            (*prev).next = block;
+        }
+    }
+}
+
+/// Size and alignment of the block backing LAYOUT.
+fn block_layout(layout: Layout) -> (usize, usize) {
+    let size = align_up(usize::max(layout.size(), BLOCK_MIN), BLOCK_ALIGN);
+    let align = usize::max(layout.align(), BLOCK_ALIGN);
+    (size, align)
+}
+
+const fn align_up(addr: usize, align: usize) -> usize {
+    (addr + align - 1) & !(align - 1)
+}
@@ -0,0 +1,94 @@
+use kernel_boot_interface::memmap::BootMemType;
+use kernel_paging::{CacheMode, MapFlags, PageSize};
+use teensy_std::addr::{PhysAddr, VirtAddr, PAGE_SIZE};
+
+use crate::memory::{
+    paging,
+    vmalloc::{release_range, reserve_range},
+    vmregion::{self, VmKind, VmRegion},
+};
+
+/// Memory types of the RAM the kernel lives in or hands out, which must
+/// never get a second mapping with another caching mode.
+const RAM_TYPES: [BootMemType; 4] = [
+    BootMemType::Usable,
+    BootMemType::BootloaderReclaimable,
+    BootMemType::AcpiReclaimable,
+    BootMemType::KernelAndModules,
+];
+
+/// Maps the SIZE bytes of device memory at PHYS into the vmalloc area with
+/// the caching mode CACHE and returns the address PHYS ended up at. The range
+/// need not be page aligned, but must not wrap around or touch RAM. The heap
+/// and paging must be up.
+pub fn ioremap(phys: PhysAddr, size: usize, cache: CacheMode) -> Option<VirtAddr> {
+    if size == 0 {
+        return None;
+    }
+    let first = phys.align_down(PAGE_SIZE);
+    let end = phys.checked_add(size)?.checked_align_up(PAGE_SIZE)?;
+    if is_ram(first, end) {
+        return None;
+    }
+    let pages = (end - first) / PAGE_SIZE;
+    let start = reserve_range(pages)?;
+    let flags = MapFlags::kernel_data().cache(cache);
+
+    for page in 0..pages {
+        let offset = page * PAGE_SIZE;
+        let mapped = paging::map(
+            start + offset,
+            (first + offset).as_usize(),
+            PageSize::Size4KiB,
+            flags,
+        );
+        if mapped.is_err() {
+            unmap_pages(start, page);
+            release_range(start, pages);
+            return None;
+        }
+    }
+
+    vmregion::register(VmRegion {
+        start: VirtAddr::new(start),
+        end: VirtAddr::new(start + pages * PAGE_SIZE),
+        flags,
+        kind: VmKind::Mmio,
+        name: "ioremap",
+    })
+    .expect("vmalloc ranges never overlap");
+    Some(VirtAddr::new(start) + (phys - first))
+}
+
+/// Removes a mapping made by `ioremap`. ADDR may be anywhere in its first page.
+///
+/// # Safety
+/// Nothing may access the mapping afterwards.
+pub unsafe fn iounmap(addr: VirtAddr) {
+    let start = addr.align_down(PAGE_SIZE);
+    assert!(
+        vmregion::find(start)
+            .is_some_and(|region| region.start == start && region.kind == VmKind::Mmio),
+        "iounmap of {:?}, which ioremap never returned",
+        addr
+    );
+    let region = vmregion::unregister(start).unwrap();
+    let pages = (region.end - region.start) / PAGE_SIZE;
+    unmap_pages(start.as_usize(), pages);
+    release_range(start.as_usize(), pages);
+}
+
+/// Whether any of the memory from START up to END is RAM.
+fn is_ram(start: PhysAddr, end: PhysAddr) -> bool {
+    kernel_boot::arch_init()
+        .memmap
+        .iter()
+        .any(|entry| RAM_TYPES.contains(&entry.typ) && entry.base < end && start < entry.end())
+}
+
+/// The frames belong to the device, so they are not freed.
+fn unmap_pages(start: usize, pages: usize) {
+    for page in 0..pages {
+        paging::unmap(start + page * PAGE_SIZE).expect("ioremap pages are mapped");
+    }
+}
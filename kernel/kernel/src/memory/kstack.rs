@@ -0,0 +1,107 @@
+use kernel_paging::{MapFlags, PageSize};
+use metamorphoses::bitmap::Bitmap;
+use teensy_std::addr::{Page, PhysAddr, PhysFrame, VirtAddr, PAGE_SIZE};
+
+use crate::memory::{paging, palloc};
+use crate::synch::Mutex;
+
+/// Pages of a kernel stack, not counting its guard page.
+pub const KERNEL_STACK_PAGES: usize = 16;
+/// Most kernel stacks that can exist at once.
+pub const MAX_KERNEL_STACKS: usize = 256;
+
+/// Start of the part of the higher half set aside for kernel stacks. Slot i
+/// holds an unmapped guard page followed by the pages of stack i.
+const REGION_START: usize = 0xffff_d000_0000_0000;
+const SLOT_SIZE: usize = (KERNEL_STACK_PAGES + 1) * PAGE_SIZE;
+const REGION_END: usize = REGION_START + MAX_KERNEL_STACKS * SLOT_SIZE;
+
+/// One bit per slot, set while the slot holds a stack.
+static SLOTS: spin::Once<Mutex<Bitmap<'static>>> = spin::Once::new();
+static mut SLOT_BITS: [u64; MAX_KERNEL_STACKS / 64] = [0; MAX_KERNEL_STACKS / 64];
+
+fn slots() -> &'static Mutex<Bitmap<'static>> {
+    // Safety: the bits are only ever borrowed here, once.
+    SLOTS.call_once(|| {
+        Mutex::new(Bitmap::new(
+            unsafe { &mut *core::ptr::addr_of_mut!(SLOT_BITS) },
+            MAX_KERNEL_STACKS,
+        ))
+    })
+}
+
+/// A kernel stack backed by palloc frames with an unmapped page below it,
+/// so that running off its end faults instead of corrupting memory.
+/// Everything is given back when it is dropped.
+pub struct KernelStack {
+    slot: usize,
+}
+
+impl KernelStack {
+    /// Reserves a slot and maps its stack pages. paging must be up.
+    pub fn new() -> Option<Self> {
+        let slot = slots().lock().find_and_flip(1, false)?;
+        let stack = Self { slot };
+        for page in 0..KERNEL_STACK_PAGES {
+            let frame = palloc::get_page()?;
+            let virt = stack.bottom() + page * PAGE_SIZE;
+            if paging::map(
+                virt.as_usize(),
+                frame.start().as_usize(),
+                PageSize::Size4KiB,
+                MapFlags::kernel_data(),
+            )
+            .is_err()
+            {
+                palloc::free_page(frame);
+                return None;
+            }
+        }
+        Some(stack)
+    }
+
+    /// Lowest mapped address of the stack.
+    pub fn bottom(&self) -> VirtAddr {
+        self.guard_page().start() + PAGE_SIZE
+    }
+
+    /// End of the stack, where the stack pointer starts.
+    pub fn top(&self) -> VirtAddr {
+        self.bottom() + KERNEL_STACK_PAGES * PAGE_SIZE
+    }
+
+    pub fn guard_page(&self) -> Page {
+        Page::containing(VirtAddr::new(REGION_START + self.slot * SLOT_SIZE))
+    }
+}
+
+impl Drop for KernelStack {
+    fn drop(&mut self) {
+        for page in 0..KERNEL_STACK_PAGES {
+            let virt = self.bottom() + page * PAGE_SIZE;
+            // Pages past a failed allocation in `new` were never mapped.
+            if let Ok(mapping) = paging::unmap(virt.as_usize()) {
+                palloc::free_page(PhysFrame::containing(PhysAddr::new(mapping.phys)));
+            }
+        }
+        slots().lock().clear(self.slot);
+    }
+}
+
+/// Whether ADDR lies in the guard page of a kernel stack.
+pub fn is_guard_page(addr: VirtAddr) -> bool {
+    let addr = addr.as_usize();
+    (REGION_START..REGION_END).contains(&addr) && (addr - REGION_START) % SLOT_SIZE < PAGE_SIZE
+}
+
+/// Called by the page fault and double fault paths with the faulting address.
+/// Panics if the fault hit the guard page of a kernel stack.
+pub fn check_stack_overflow(addr: VirtAddr) {
+    if is_guard_page(addr) {
+        let slot = (addr.as_usize() - REGION_START) / SLOT_SIZE;
+        panic!(
+            "kernel stack overflow: access to {:?} hit the guard page of stack {}",
+            addr, slot
+        );
+    }
+}
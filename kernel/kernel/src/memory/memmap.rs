@@ -1,8 +1,10 @@
 use kernel_boot_interface::{
     hhdm::BootHhdm,
-    memmap::{BootMemType, Memmap, MemmapEntry},
+    memmap::{BootMemType, Memmap, MemmapEntry, MAX_MEM_REGIONS},
 };
-use teensy_std::addr::Addr;
+use teensy_std::addr::{PhysAddr, PAGE_SIZE};
+
+use crate::synch::{Mutex, MutexGuard};
 
 pub fn get_num_memory_pages(memmap: &Memmap) -> usize {
     kernel_paging::page_min_no(kernel_paging::page_min_round_down(
@@ -17,14 +19,15 @@ pub fn get_num_memory_pages(memmap: &Memmap) -> usize {
                 }
             })
             .unwrap()
-            .end(),
+            .end()
+            .as_usize(),
     ))
 }
 
 pub fn get_largest_memmap_entry<'a>(hhdm: &BootHhdm, memmap: &'a Memmap) -> &'a MemmapEntry {
     let entry_size_in_hdmm = |entry: &MemmapEntry| {
-        let hhdm_size = usize::MAX - hhdm.base;
-        usize::min(hhdm_size, entry.base + entry.len) - entry.base
+        let hhdm_size = hhdm.max_len();
+        usize::min(hhdm_size, entry.end().as_usize()) - entry.base.as_usize()
     };
     let largest_entry = memmap
         .iter()
@@ -43,10 +46,326 @@ pub fn get_largest_memmap_entry<'a>(hhdm: &BootHhdm, memmap: &'a Memmap) -> &'a
     largest_entry
 }
 
-pub fn get_addr_entry<'a>(memmap: &'a Memmap, addr: &Addr) -> &'a MemmapEntry {
-    let offset = addr.as_usize();
+pub fn get_addr_entry(memmap: &Memmap, addr: PhysAddr) -> &MemmapEntry {
     memmap
-        .iter()
-        .find(|entry| offset >= entry.base && offset < entry.end())
+        .find(addr)
         .expect("This address should be in the memory map")
 }
+
+/// Most ranges either list of the memblock can hold.
+pub const MAX_MEMBLOCK_RANGES: usize = MAX_MEM_REGIONS;
+
+static MEMBLOCK: spin::Once<Mutex<Memblock>> = spin::Once::new();
+
+/// A page aligned range [start, end) of physical memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PhysRange {
+    pub start: PhysAddr,
+    pub end: PhysAddr,
+}
+
+/// Sorted, disjoint and non-adjacent ranges. The capacity is fixed as the
+/// memblock is used before there is a heap.
+struct RangeList {
+    ranges: [PhysRange; MAX_MEMBLOCK_RANGES],
+    len: usize,
+}
+
+/// The early boot view of physical memory: the RAM in the boot memory map,
+/// sorted, merged and clipped to whole pages, and the parts of it that are
+/// reserved. Early code reserves what it must keep before palloc comes up,
+/// and palloc then manages everything that is left.
+pub struct Memblock {
+    memory: RangeList,
+    reserved: RangeList,
+    /// RAM the firmware or the memtest found faulty. It is never free.
+    bad: RangeList,
+    handed_off: bool,
+}
+
+/// Builds the memblock from the boot memory map. Later calls do nothing.
+pub fn init(memmap: &Memmap) {
+    MEMBLOCK.call_once(|| Mutex::new(Memblock::from_memmap(memmap)));
+}
+
+pub fn memblock() -> MutexGuard<'static, Memblock> {
+    MEMBLOCK.get().expect("memmap::init has not run").lock()
+}
+
+/// Whether the kernel may use memory of type TYP once the bootloader's and
+/// the firmware's data in it is no longer needed.
+fn is_ram(typ: BootMemType) -> bool {
+    matches!(
+        typ,
+        BootMemType::Usable | BootMemType::BootloaderReclaimable | BootMemType::AcpiReclaimable
+    )
+}
+
+impl PhysRange {
+    pub fn new(start: PhysAddr, end: PhysAddr) -> Self {
+        Self { start, end }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end.as_usize().saturating_sub(self.start.as_usize())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start >= self.end
+    }
+
+    pub fn contains(&self, addr: PhysAddr) -> bool {
+        self.start <= addr && addr < self.end
+    }
+
+    /// The whole pages inside the range.
+    fn shrink_to_pages(self) -> Self {
+        Self::new(
+            self.start.align_up(PAGE_SIZE),
+            self.end.align_down(PAGE_SIZE),
+        )
+    }
+
+    /// The pages the range touches.
+    fn grow_to_pages(self) -> Self {
+        Self::new(
+            self.start.align_down(PAGE_SIZE),
+            self.end.align_up(PAGE_SIZE),
+        )
+    }
+}
+
+impl Memblock {
+    /// Entries may come in any order and overlap. Where RAM overlaps an entry
+    /// of another type, the other entry wins. Reclaimable memory is reserved
+    /// until it is reclaimed and so is page 0, so that a null physical
+    /// address is never handed out. Bad memory is not RAM but is listed as
+    /// bad.
+    pub fn from_memmap(memmap: &Memmap) -> Self {
+        let mut memblock = Self {
+            memory: RangeList::new(),
+            reserved: RangeList::new(),
+            bad: RangeList::new(),
+            handed_off: false,
+        };
+        let range = |entry: &MemmapEntry| PhysRange::new(entry.base, entry.end());
+
+        for entry in memmap.iter().filter(|entry| is_ram(entry.typ)) {
+            memblock.memory.add(range(entry).shrink_to_pages());
+        }
+        for entry in memmap.iter().filter(|entry| !is_ram(entry.typ)) {
+            memblock.memory.remove(range(entry).grow_to_pages());
+        }
+        for entry in memmap
+            .iter()
+            .filter(|entry| is_ram(entry.typ) && entry.typ != BootMemType::Usable)
+        {
+            memblock.reserve(entry.base, entry.len);
+        }
+        for entry in memmap.regions(BootMemType::BadMemory) {
+            memblock.bad.add(range(entry).grow_to_pages());
+        }
+        memblock.reserve(PhysAddr::zero(), PAGE_SIZE);
+        memblock
+    }
+
+    /// Keeps the pages touching the LEN bytes at START from palloc.
+    pub fn reserve(&mut self, start: PhysAddr, len: usize) {
+        assert!(
+            !self.handed_off,
+            "memblock reservation after palloc took over"
+        );
+        self.reserved
+            .add(PhysRange::new(start, start + len).grow_to_pages());
+    }
+
+    /// Reserves the pages touching the LEN bytes at START for good, as they
+    /// do not hold what is written to them. Returns false, changing nothing,
+    /// if the lists have no room left for them.
+    pub fn mark_bad(&mut self, start: PhysAddr, len: usize) -> bool {
+        let range = PhysRange::new(start, start + len).grow_to_pages();
+        if !self.reserved.has_room_for(range) || !self.bad.has_room_for(range) {
+            return false;
+        }
+        self.reserve(start, len);
+        self.bad.add(range);
+        true
+    }
+
+    /// Reserves SIZE bytes of RAM starting on an ALIGN byte boundary, ALIGN
+    /// being a power of two, and returns their address. Memory is handed out
+    /// from the top down to leave low memory to the devices that need it.
+    pub fn alloc(&mut self, size: usize, align: usize) -> Option<PhysAddr> {
+        assert!(
+            !self.handed_off,
+            "memblock allocation after palloc took over"
+        );
+        debug_assert!(align.is_power_of_two());
+        let size = size.next_multiple_of(PAGE_SIZE);
+        let align = align.max(PAGE_SIZE);
+
+        let mut found = None;
+        self.for_each_free(|free| {
+            let start = free
+                .end
+                .checked_sub(size)
+                .map(|start| start.align_down(align))
+                .filter(|&start| start >= free.start);
+            found = start.or(found);
+        });
+        let start = found?;
+        self.reserve(start, size);
+        Some(start)
+    }
+
+    /// Whether ADDR is not RAM or is reserved.
+    pub fn is_reserved(&self, addr: PhysAddr) -> bool {
+        !self.memory.iter().any(|range| range.contains(addr))
+            || self.reserved.iter().any(|range| range.contains(addr))
+    }
+
+    pub fn memory(&self) -> &[PhysRange] {
+        self.memory.as_slice()
+    }
+
+    pub fn reserved(&self) -> &[PhysRange] {
+        self.reserved.as_slice()
+    }
+
+    pub fn bad(&self) -> &[PhysRange] {
+        self.bad.as_slice()
+    }
+
+    /// Number of pages marked bad.
+    pub fn bad_pages(&self) -> usize {
+        self.bad.iter().map(|range| range.len() / PAGE_SIZE).sum()
+    }
+
+    /// End of the highest range of RAM.
+    pub fn end(&self) -> PhysAddr {
+        self.memory
+            .as_slice()
+            .last()
+            .map_or(PhysAddr::zero(), |range| range.end)
+    }
+
+    /// Calls F with every maximal range of RAM that is not reserved, in order.
+    pub fn for_each_free(&self, mut f: impl FnMut(PhysRange)) {
+        for memory in self.memory.iter() {
+            let mut start = memory.start;
+            for reserved in self
+                .reserved
+                .iter()
+                .filter(|reserved| reserved.end > memory.start && reserved.start < memory.end)
+            {
+                if start < reserved.start {
+                    f(PhysRange::new(start, reserved.start));
+                }
+                start = start.max(reserved.end);
+            }
+            if start < memory.end {
+                f(PhysRange::new(start, memory.end));
+            }
+        }
+    }
+
+    /// Marks the free memory as belonging to palloc. Reserving or allocating
+    /// afterwards panics.
+    pub fn hand_off(&mut self) {
+        self.handed_off = true;
+    }
+}
+
+impl RangeList {
+    const fn new() -> Self {
+        Self {
+            ranges: [PhysRange {
+                start: PhysAddr::zero(),
+                end: PhysAddr::zero(),
+            }; MAX_MEMBLOCK_RANGES],
+            len: 0,
+        }
+    }
+
+    fn as_slice(&self) -> &[PhysRange] {
+        &self.ranges[..self.len]
+    }
+
+    fn iter(&self) -> core::slice::Iter<'_, PhysRange> {
+        self.as_slice().iter()
+    }
+
+    /// The indices [first, last) of the ranges RANGE overlaps or touches.
+    fn touching(&self, range: PhysRange) -> (usize, usize) {
+        let first = self
+            .as_slice()
+            .partition_point(|other| other.end < range.start);
+        let last = self
+            .as_slice()
+            .partition_point(|other| other.start <= range.end);
+        (first, last)
+    }
+
+    /// Whether `add` can take RANGE without running out of ranges.
+    fn has_room_for(&self, range: PhysRange) -> bool {
+        let (first, last) = self.touching(range);
+        range.is_empty() || first < last || self.len < MAX_MEMBLOCK_RANGES
+    }
+
+    /// Adds RANGE, merging it with the ranges it overlaps or touches.
+    fn add(&mut self, range: PhysRange) {
+        if range.is_empty() {
+            return;
+        }
+        let (first, last) = self.touching(range);
+        if first == last {
+            self.insert(first, range);
+            return;
+        }
+
+        self.ranges[first] = PhysRange::new(
+            range.start.min(self.ranges[first].start),
+            range.end.max(self.ranges[last - 1].end),
+        );
+        self.ranges.copy_within(last..self.len, first + 1);
+        self.len -= last - first - 1;
+    }
+
+    /// Takes RANGE out of the list, splitting the ranges it lies within.
+    fn remove(&mut self, range: PhysRange) {
+        let mut idx = self
+            .as_slice()
+            .partition_point(|other| other.end <= range.start);
+        while idx < self.len && self.ranges[idx].start < range.end {
+            let cur = self.ranges[idx];
+            let below = PhysRange::new(cur.start, range.start);
+            let above = PhysRange::new(range.end, cur.end);
+            match (below.is_empty(), above.is_empty()) {
+                (false, false) => {
+                    self.ranges[idx] = below;
+                    self.insert(idx + 1, above);
+                    return;
+                }
+                (false, true) => {
+                    self.ranges[idx] = below;
+                    idx += 1;
+                }
+                (true, false) => {
+                    self.ranges[idx] = above;
+                    return;
+                }
+                (true, true) => {
+                    self.ranges.copy_within(idx + 1..self.len, idx);
+                    self.len -= 1;
+                }
+            }
+        }
+    }
+
+    fn insert(&mut self, idx: usize, range: PhysRange) {
+        assert!(self.len < MAX_MEMBLOCK_RANGES, "memblock: out of ranges");
+        self.ranges.copy_within(idx..self.len, idx + 1);
+        self.ranges[idx] = range;
+        self.len += 1;
+    }
+}
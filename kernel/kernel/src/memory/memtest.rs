@@ -0,0 +1,266 @@
+use core::fmt;
+
+use kernel_boot_interface::{cmdline::BootCmdline, hhdm::BootHhdm};
+use kernel_log::kprintln;
+use metamorphoses::bitmap::{self, Bitmap};
+use teensy_std::addr::{PhysAddr, PhysFrame, PAGE_SIZE};
+
+use crate::{
+    memory::memmap::{self, PhysRange, MAX_MEMBLOCK_RANGES},
+    synch::Mutex,
+};
+
+/// Pattern the moving inversions test starts from, alternating bits.
+const INVERSION_PATTERN: u64 = 0x5555_5555_5555_5555;
+
+/// One bit per page frame, set for the bad pages the memblock had no room
+/// left to list. palloc keeps them out of the pool all the same.
+static UNLISTED_BAD: spin::Once<Mutex<Bitmap<'static>>> = spin::Once::new();
+
+/// A word of memory that did not read back what was written to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Failure {
+    pub test: &'static str,
+    pub addr: PhysAddr,
+    pub expected: u64,
+    pub found: u64,
+}
+
+/// Outcome of a memtest run. Counts are in pages.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MemtestReport {
+    pub tested_pages: usize,
+    pub bad_pages: usize,
+}
+
+/// The words of a physical range, accessed through the HHDM.
+struct Words {
+    start: PhysAddr,
+    ptr: *mut u64,
+    len: usize,
+}
+
+/// Number of passes asked for on the command line: `memtest` runs one,
+/// `memtest=N` runs N.
+pub fn passes_requested(cmdline: &BootCmdline) -> Option<usize> {
+    match cmdline.value("memtest") {
+        Some(passes) => passes.parse().ok().filter(|&passes| passes > 0),
+        None => cmdline.has("memtest").then_some(1),
+    }
+}
+
+/// Tests all the RAM the memblock has free PASSES times and marks the pages
+/// that fail bad, so that palloc never hands them out. Must run after
+/// `memmap::init` and before palloc takes the memory over.
+pub fn run(hhdm: &BootHhdm, passes: usize) -> MemtestReport {
+    run_with(hhdm, passes, |range, fail| test_range(hhdm, range, fail))
+}
+
+/// `run`, calling TEST in place of `test_range` on every free range, so that
+/// a test can make pages fail.
+pub fn run_with(
+    hhdm: &BootHhdm,
+    passes: usize,
+    mut test: impl FnMut(PhysRange, &mut dyn FnMut(PhysFrame, Failure)),
+) -> MemtestReport {
+    // Taken from the memblock before the ranges are gathered, so that the
+    // bitmap is not tested over.
+    let unlisted = UNLISTED_BAD.call_once(|| Mutex::new(page_bitmap(hhdm)));
+
+    // Every reserved range splits at most one free range in two.
+    let mut ranges = [PhysRange::new(PhysAddr::zero(), PhysAddr::zero()); 2 * MAX_MEMBLOCK_RANGES];
+    let mut num_ranges = 0;
+    memmap::memblock().for_each_free(|free| {
+        ranges[num_ranges] = free;
+        num_ranges += 1;
+    });
+    let ranges = &ranges[..num_ranges];
+    let bad_before = memmap::memblock().bad_pages() + unlisted.lock().count_ones();
+
+    for pass in 1..=passes {
+        kprintln!("memtest: pass {}/{}", pass, passes);
+        for &range in ranges {
+            let mut last_bad = None;
+            test(range, &mut |frame, failure| {
+                if last_bad != Some(frame) {
+                    kprintln!("memtest: {}", failure);
+                    mark_bad(unlisted, frame);
+                    last_bad = Some(frame);
+                }
+            });
+        }
+    }
+
+    let unlisted_pages = unlisted.lock().count_ones();
+    if unlisted_pages > 0 {
+        kprintln!(
+            "memtest: out of memblock ranges, {} bad pages are only kept from palloc",
+            unlisted_pages
+        );
+    }
+    let report = MemtestReport {
+        tested_pages: ranges.iter().map(|range| range.len() / PAGE_SIZE).sum(),
+        bad_pages: memmap::memblock().bad_pages() + unlisted_pages - bad_before,
+    };
+    kprintln!(
+        "memtest: {} pages tested, {} bad",
+        report.tested_pages,
+        report.bad_pages
+    );
+    report
+}
+
+/// Lists FRAME as bad in the memblock, or in UNLISTED once the memblock has
+/// no room left. Adjacent bad pages share one memblock range.
+fn mark_bad(unlisted: &Mutex<Bitmap<'static>>, frame: PhysFrame) {
+    let mut memblock = memmap::memblock();
+    let mut unlisted = unlisted.lock();
+    if !unlisted.get(frame.number()) && !memblock.mark_bad(frame.start(), PAGE_SIZE) {
+        unlisted.set(frame.number());
+    }
+}
+
+/// A clear bitmap with a bit for every page frame of RAM, in memory taken
+/// from the memblock.
+fn page_bitmap(hhdm: &BootHhdm) -> Bitmap<'static> {
+    let mut memblock = memmap::memblock();
+    let pages = memblock.end().as_usize() / PAGE_SIZE;
+    let words = pages.div_ceil(bitmap::WORD_SIZE_BITS);
+    let base = memblock
+        .alloc(words * bitmap::WORD_SIZE, PAGE_SIZE)
+        .expect("No memory for the memtest bitmap");
+    let bits =
+        unsafe { core::slice::from_raw_parts_mut(hhdm.phys_to_virt(base).as_mut_ptr(), words) };
+    Bitmap::new(bits, pages)
+}
+
+/// Number of bad pages only palloc keeps out, as the memblock had no room
+/// left to list them.
+pub fn unlisted_bad_pages() -> usize {
+    UNLISTED_BAD
+        .get()
+        .map_or(0, |unlisted| unlisted.lock().count_ones())
+}
+
+/// Calls F with every run [start, end) of the frames of RANGE that are not
+/// unlisted bad pages, in order.
+pub fn for_each_good_run(range: PhysRange, mut f: impl FnMut(PhysFrame, PhysFrame)) {
+    let start = PhysFrame::containing(range.start).number();
+    let end = PhysFrame::containing(range.end).number();
+    let Some(unlisted) = UNLISTED_BAD.get() else {
+        f(PhysFrame::from_number(start), PhysFrame::from_number(end));
+        return;
+    };
+    let unlisted = unlisted.lock();
+    let mut run_start = start;
+    while run_start < end {
+        let bad = unlisted
+            .find_next(run_start, true)
+            .map_or(end, |pfn| pfn.min(end));
+        if run_start < bad {
+            f(
+                PhysFrame::from_number(run_start),
+                PhysFrame::from_number(bad),
+            );
+        }
+        run_start = bad + 1;
+    }
+}
+
+/// Runs every test once over RANGE, which must be unused RAM, and calls F
+/// with the page and the details of every word that fails. The contents of
+/// the range are lost.
+pub fn test_range(hhdm: &BootHhdm, range: PhysRange, mut f: impl FnMut(PhysFrame, Failure)) {
+    let words = Words {
+        start: range.start,
+        ptr: hhdm.phys_to_virt(range.start).as_mut_ptr(),
+        len: range.len() / core::mem::size_of::<u64>(),
+    };
+    let mut fail = |failure: Failure| f(PhysFrame::containing(failure.addr), failure);
+
+    // A single set bit, then a single clear one, walking through every bit
+    // position catches bits stuck at either value or shorted together.
+    words.pattern("walking ones", |idx| 1 << (idx % 64), &mut fail);
+    words.pattern("walking zeros", |idx| !(1 << (idx % 64)), &mut fail);
+    // Every word holding its own address catches address lines that are
+    // stuck or shorted, which make two words alias.
+    words.pattern(
+        "address in address",
+        |idx| words.addr(idx).as_usize() as u64,
+        &mut fail,
+    );
+    words.moving_inversions(INVERSION_PATTERN, &mut fail);
+}
+
+impl Words {
+    fn addr(&self, idx: usize) -> PhysAddr {
+        self.start + idx * core::mem::size_of::<u64>()
+    }
+
+    fn read(&self, idx: usize) -> u64 {
+        debug_assert!(idx < self.len);
+        unsafe { self.ptr.add(idx).read_volatile() }
+    }
+
+    fn write(&self, idx: usize, value: u64) {
+        debug_assert!(idx < self.len);
+        unsafe { self.ptr.add(idx).write_volatile(value) }
+    }
+
+    /// Reads word IDX back and reports it if it is not EXPECTED.
+    fn verify(
+        &self,
+        test: &'static str,
+        idx: usize,
+        expected: u64,
+        fail: &mut impl FnMut(Failure),
+    ) {
+        let found = self.read(idx);
+        if found != expected {
+            fail(Failure {
+                test,
+                addr: self.addr(idx),
+                expected,
+                found,
+            });
+        }
+    }
+
+    /// Fills the words with PATTERN of their index, then checks them all, so
+    /// that a write to one word that lands in another shows up.
+    fn pattern(
+        &self,
+        test: &'static str,
+        pattern: impl Fn(usize) -> u64,
+        fail: &mut impl FnMut(Failure),
+    ) {
+        (0..self.len).for_each(|idx| self.write(idx, pattern(idx)));
+        (0..self.len).for_each(|idx| self.verify(test, idx, pattern(idx), fail));
+    }
+
+    /// Fills the words with PATTERN, then going up checks each one and writes
+    /// its inverse, then going down checks the inverse and writes PATTERN
+    /// back. Catches cells that are disturbed by writes to their neighbours.
+    fn moving_inversions(&self, pattern: u64, fail: &mut impl FnMut(Failure)) {
+        const TEST: &str = "moving inversions";
+        (0..self.len).for_each(|idx| self.write(idx, pattern));
+        for idx in 0..self.len {
+            self.verify(TEST, idx, pattern, fail);
+            self.write(idx, !pattern);
+        }
+        for idx in (0..self.len).rev() {
+            self.verify(TEST, idx, !pattern, fail);
+            self.write(idx, pattern);
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} failed at {:?}: expected {:#018x}, found {:#018x}",
+            self.test, self.addr, self.expected, self.found
+        )
+    }
+}
@@ -1,4 +1,13 @@
+pub mod fault;
+pub mod heap;
+pub mod ioremap;
+pub mod kstack;
 pub mod memmap;
+pub mod memtest;
+pub mod paging;
 pub mod palloc;
-
-
+pub mod reclaim;
+pub mod remap;
+pub mod slab;
+pub mod vmalloc;
+pub mod vmregion;
@@ -0,0 +1,62 @@
+use spin;
+
+use kernel_boot_interface::BootInfo;
+use kernel_paging::{AddressSpace, FrameAllocator, MapError, MapFlags, Mapping, PageSize};
+use teensy_std::addr::{PhysAddr, PhysFrame};
+
+use crate::memory::{palloc, remap};
+use crate::synch::{Mutex, MutexGuard};
+
+static KERNEL_SPACE: spin::Once<Mutex<AddressSpace>> = spin::Once::new();
+
+/// Hands out the frames of page tables from palloc.
+pub struct PallocFrameAllocator;
+
+impl FrameAllocator for PallocFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<usize> {
+        palloc::get_page().map(|frame| frame.start().as_usize())
+    }
+
+    fn free_frame(&mut self, frame: usize) {
+        palloc::free_page(PhysFrame::containing(PhysAddr::new(frame)));
+    }
+}
+
+/// Programs the PAT and moves from the bootloader's page tables to the
+/// kernel's own, in which the kernel's sections are mapped W^X and the HHDM
+/// is not executable. palloc must be initialised first.
+pub fn init(boot_info: &BootInfo) {
+    kernel_paging::init_pat();
+    KERNEL_SPACE.call_once(|| {
+        kernel_cpu::enable_no_execute();
+        kernel_cpu::enable_write_protect();
+        let space = remap::build_kernel_space(boot_info);
+        assert!(
+            space.translate(kernel_cpu::stack_pointer()).is_some(),
+            "The boot stack is not in the HHDM"
+        );
+        unsafe { space.activate() };
+        Mutex::new(space)
+    });
+}
+
+pub fn kernel_space() -> MutexGuard<'static, AddressSpace> {
+    KERNEL_SPACE.wait().lock()
+}
+
+/// The kernel address space, unless someone holds its lock.
+pub fn try_kernel_space() -> Option<MutexGuard<'static, AddressSpace>> {
+    KERNEL_SPACE.wait().try_lock()
+}
+
+pub fn map(virt: usize, phys: usize, size: PageSize, flags: MapFlags) -> Result<(), MapError> {
+    kernel_space().map(virt, phys, size, flags, &mut PallocFrameAllocator)
+}
+
+pub fn unmap(virt: usize) -> Result<Mapping, MapError> {
+    kernel_space().unmap(virt)
+}
+
+pub fn translate(virt: usize) -> Option<usize> {
+    kernel_space().translate(virt)
+}
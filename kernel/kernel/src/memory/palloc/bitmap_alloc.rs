@@ -0,0 +1,42 @@
+use metamorphoses::bitmap::{Bitmap, BitmapRange};
+
+use super::PageAllocator;
+
+/// First fit allocator keeping one bit per page frame in [start_pfn, end_pfn).
+/// Allocation scans the bitmap, so it costs O(pages managed). Kept around as
+/// a baseline for the buddy allocator.
+pub struct BitmapAllocator<'a> {
+    bmap: Bitmap<'a>,
+    start_pfn: usize,
+}
+
+impl<'a> BitmapAllocator<'a> {
+    /// Creates an allocator with no free pages.
+    pub fn new(bits: &'a mut [u64], start_pfn: usize, end_pfn: usize) -> Self {
+        let mut bmap = Bitmap::new(bits, end_pfn - start_pfn);
+        bmap.fill(true);
+        Self { bmap, start_pfn }
+    }
+
+    /// Hands the pages [START_PFN, END_PFN) to the allocator.
+    pub fn add_free_range(&mut self, start_pfn: usize, end_pfn: usize) {
+        self.bmap.clear_range(&BitmapRange::new(
+            start_pfn - self.start_pfn,
+            end_pfn - self.start_pfn,
+        ));
+    }
+}
+
+impl<'a> PageAllocator for BitmapAllocator<'a> {
+    fn alloc(&mut self, num_pages: usize) -> Option<usize> {
+        self.bmap
+            .find_and_flip(num_pages, false)
+            .map(|idx| idx + self.start_pfn)
+    }
+
+    fn free(&mut self, pfn: usize, num_pages: usize) {
+        let start = pfn - self.start_pfn;
+        self.bmap
+            .clear_range(&BitmapRange::new(start, start + num_pages))
+    }
+}
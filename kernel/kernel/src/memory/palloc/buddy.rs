@@ -0,0 +1,414 @@
+use core::{array, mem};
+
+use kernel_boot_interface::hhdm::BootHhdm;
+use metamorphoses::bitmap::{self, Bitmap};
+
+use super::{debug::POISON, PageAllocator};
+
+/// Largest block is 2^MAX_ORDER pages, which makes it 1GiB.
+pub const MAX_ORDER: usize = 18;
+const NUM_ORDERS: usize = MAX_ORDER + 1;
+const NIL: usize = usize::MAX;
+/// Bytes at the start of a free block the allocator writes its links to.
+pub const LINK_BYTES: usize = mem::size_of::<FreeNode>();
+
+/// Written at the start of every free block to link it into its free list.
+struct FreeNode {
+    next: usize,
+    prev: usize,
+}
+
+/// Binary buddy allocator over the page frames [start_pfn, end_pfn).
+///
+/// Every free block of order k is 2^k pages long and aligned to 2^k page
+/// frames. Free blocks are kept in one doubly linked list per order, threaded
+/// through the free pages themselves via the HHDM, and a bitmap per order
+/// remembers which blocks are on a list so that buddies can be found and
+/// merged in constant time.
+pub struct BuddyAllocator<'a> {
+    free_lists: [usize; NUM_ORDERS],
+    free_blocks: [usize; NUM_ORDERS],
+    free_maps: [Bitmap<'a>; NUM_ORDERS],
+    start_pfn: usize,
+    end_pfn: usize,
+    hhdm_base: usize,
+    free_pages: usize,
+    /// Whether the links of a block are poisoned when it leaves its list.
+    poison_links: bool,
+}
+
+impl<'a> BuddyAllocator<'a> {
+    /// Number of words of metadata needed to manage [START_PFN, END_PFN).
+    pub fn metadata_words(start_pfn: usize, end_pfn: usize) -> usize {
+        (0..NUM_ORDERS)
+            .map(|order| words_for(map_bits(start_pfn, end_pfn, order)))
+            .sum()
+    }
+
+    /// Creates an allocator with no free pages. METADATA must hold at least
+    /// `metadata_words` words.
+    pub fn new(metadata: &'a mut [u64], start_pfn: usize, end_pfn: usize, hhdm: &BootHhdm) -> Self {
+        debug_assert!(start_pfn < end_pfn);
+        debug_assert!(metadata.len() >= Self::metadata_words(start_pfn, end_pfn));
+
+        let mut rest = metadata;
+        let free_maps = array::from_fn(|order| {
+            let bits = map_bits(start_pfn, end_pfn, order);
+            let (words, tail) = mem::take(&mut rest).split_at_mut(words_for(bits));
+            rest = tail;
+            Bitmap::new(words, bits)
+        });
+
+        Self {
+            free_lists: [NIL; NUM_ORDERS],
+            free_blocks: [0; NUM_ORDERS],
+            free_maps,
+            start_pfn,
+            end_pfn,
+            hhdm_base: hhdm.base,
+            free_pages: 0,
+            poison_links: false,
+        }
+    }
+
+    /// Hands the pages [START_PFN, END_PFN) to the allocator.
+    pub fn add_free_range(&mut self, start_pfn: usize, end_pfn: usize) {
+        let start_pfn = usize::max(start_pfn, self.start_pfn);
+        let end_pfn = usize::min(end_pfn, self.end_pfn);
+        if start_pfn < end_pfn {
+            self.free(start_pfn, end_pfn - start_pfn);
+        }
+    }
+
+    pub fn free_pages(&self) -> usize {
+        self.free_pages
+    }
+
+    /// From now on, fills the links of every block taken off a free list
+    /// with the poison pattern, so that palloc's debug mode can check them
+    /// like the rest of the block.
+    pub fn poison_links(&mut self) {
+        self.poison_links = true;
+    }
+
+    /// Whether PFN lies in one of the free blocks.
+    pub fn is_free(&self, pfn: usize) -> bool {
+        (0..NUM_ORDERS).any(|order| self.is_free_block(pfn & !((1 << order) - 1), order))
+    }
+
+    /// Calls F with the first page frame number and order of every free block.
+    pub fn for_each_free_block(&self, mut f: impl FnMut(usize, usize)) {
+        for order in 0..NUM_ORDERS {
+            let mut pfn = self.free_lists[order];
+            while pfn != NIL {
+                f(pfn, order);
+                pfn = unsafe { (*self.node(pfn)).next };
+            }
+        }
+    }
+
+    /// Number of free blocks of every order.
+    pub fn free_blocks(&self) -> &[usize; NUM_ORDERS] {
+        &self.free_blocks
+    }
+
+    /// Length in pages of the longest run of free pages. Adjacent free blocks
+    /// which could not be merged count as one run. Walks every page frame, so
+    /// it is meant for diagnostics only.
+    pub fn largest_free_run(&self) -> usize {
+        let (mut largest, mut run) = (0, 0);
+        let mut pfn = self.start_pfn;
+        while pfn < self.end_pfn {
+            let block = (0..NUM_ORDERS)
+                .rev()
+                .find(|&order| pfn.is_multiple_of(1 << order) && self.is_free_block(pfn, order));
+            match block {
+                Some(order) => {
+                    run += 1 << order;
+                    pfn += 1 << order;
+                }
+                None => {
+                    largest = largest.max(run);
+                    run = 0;
+                    pfn += 1;
+                }
+            }
+        }
+        largest.max(run)
+    }
+
+    /// Allocates NUM_PAGES pages whose first page frame number is a multiple
+    /// of ALIGN_PAGES, a power of two. Pages past NUM_PAGES in the block go
+    /// straight back to the free lists. Zero pages cannot be allocated.
+    pub fn alloc_aligned(&mut self, num_pages: usize, align_pages: usize) -> Option<usize> {
+        debug_assert!(align_pages.is_power_of_two());
+        if num_pages == 0 {
+            return None;
+        }
+        let order = order_for(num_pages.max(align_pages))?;
+        let mut cur = (order..NUM_ORDERS).find(|&k| self.free_lists[k] != NIL)?;
+
+        let pfn = self.free_lists[cur];
+        self.remove(pfn, cur);
+        while cur > order {
+            cur -= 1;
+            self.push(pfn + (1 << cur), cur);
+        }
+        self.free_pages -= 1 << order;
+
+        let excess = (1 << order) - num_pages;
+        if excess > 0 {
+            self.free(pfn + num_pages, excess);
+        }
+        Some(pfn)
+    }
+
+    /// Puts the block of 2^ORDER pages at PFN on its free list, merging it
+    /// with its buddy for as long as the buddy is free too.
+    fn free_block(&mut self, mut pfn: usize, mut order: usize) {
+        while order < MAX_ORDER {
+            let buddy = pfn ^ (1 << order);
+            if !self.is_free_block(buddy, order) {
+                break;
+            }
+            self.remove(buddy, order);
+            pfn &= !(1 << order);
+            order += 1;
+        }
+        self.push(pfn, order);
+    }
+
+    fn is_free_block(&self, pfn: usize, order: usize) -> bool {
+        pfn >= self.start_pfn
+            && pfn + (1 << order) <= self.end_pfn
+            && self.free_maps[order].get(self.map_idx(pfn, order))
+    }
+
+    fn push(&mut self, pfn: usize, order: usize) {
+        let head = self.free_lists[order];
+        unsafe {
+            self.node(pfn).write(FreeNode {
+                next: head,
+                prev: NIL,
+            });
+            if head != NIL {
+                (*self.node(head)).prev = pfn;
+            }
+        }
+        self.free_lists[order] = pfn;
+        self.free_blocks[order] += 1;
+        let idx = self.map_idx(pfn, order);
+        self.free_maps[order].set(idx);
+    }
+
+    fn remove(&mut self, pfn: usize, order: usize) {
+        let FreeNode { next, prev } = unsafe { self.node(pfn).read() };
+        if self.poison_links {
+            unsafe { (self.node(pfn) as *mut u8).write_bytes(POISON, LINK_BYTES) };
+        }
+        if prev == NIL {
+            self.free_lists[order] = next;
+        } else {
+            unsafe { (*self.node(prev)).next = next };
+        }
+        if next != NIL {
+            unsafe { (*self.node(next)).prev = prev };
+        }
+        self.free_blocks[order] -= 1;
+        let idx = self.map_idx(pfn, order);
+        self.free_maps[order].clear(idx);
+    }
+
+    fn map_idx(&self, pfn: usize, order: usize) -> usize {
+        (pfn >> order) - (self.start_pfn >> order)
+    }
+
+    fn node(&self, pfn: usize) -> *mut FreeNode {
+        (pfn * kernel_paging::PAGE_SIZE_MIN + self.hhdm_base) as *mut FreeNode
+    }
+}
+
+impl<'a> PageAllocator for BuddyAllocator<'a> {
+    /// Blocks are aligned to the power of two NUM_PAGES rounds up to.
+    fn alloc(&mut self, num_pages: usize) -> Option<usize> {
+        self.alloc_aligned(num_pages, 1)
+    }
+
+    fn free(&mut self, pfn: usize, num_pages: usize) {
+        debug_assert!(pfn >= self.start_pfn && pfn + num_pages <= self.end_pfn);
+        let end = pfn + num_pages;
+        let mut cur = pfn;
+        while cur < end {
+            let order = largest_order(cur, end);
+            self.free_block(cur, order);
+            cur += 1 << order;
+        }
+        self.free_pages += num_pages;
+    }
+}
+
+/// Number of bits the free map of ORDER needs to cover [START_PFN, END_PFN).
+fn map_bits(start_pfn: usize, end_pfn: usize, order: usize) -> usize {
+    ((end_pfn - 1) >> order) - (start_pfn >> order) + 1
+}
+
+fn words_for(bits: usize) -> usize {
+    bits.div_ceil(bitmap::WORD_SIZE_BITS)
+}
+
+/// Smallest order whose blocks hold NUM_PAGES.
+fn order_for(num_pages: usize) -> Option<usize> {
+    let order = num_pages.checked_next_power_of_two()?.trailing_zeros() as usize;
+    (num_pages != 0 && order <= MAX_ORDER).then_some(order)
+}
+
+/// Largest order of a block starting at PFN that is aligned and ends before END.
+fn largest_order(pfn: usize, end: usize) -> usize {
+    let align_order = pfn.trailing_zeros() as usize;
+    let len_order = (end - pfn).ilog2() as usize;
+    align_order.min(len_order).min(MAX_ORDER)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use kernel_boot_interface::BootInfo;
+
+    const ARENA_PAGES: usize = 32;
+
+    /// Pages the tests hand to the allocator. With an HHDM base of 0 the page
+    /// frame numbers are simply the virtual page numbers of the arena.
+    #[repr(C, align(131072))]
+    struct Arena([u8; ARENA_PAGES * kernel_paging::PAGE_SIZE_MIN]);
+
+    static mut ARENA: Arena = Arena([0; ARENA_PAGES * kernel_paging::PAGE_SIZE_MIN]);
+
+    fn arena_pfn() -> usize {
+        kernel_paging::page_min_no(core::ptr::addr_of!(ARENA) as usize)
+    }
+
+    fn with_buddy(f: impl FnOnce(&mut BuddyAllocator, usize)) {
+        let start = arena_pfn();
+        let end = start + ARENA_PAGES;
+        let mut metadata = [0u64; 64];
+        assert!(BuddyAllocator::metadata_words(start, end) <= metadata.len());
+
+        let mut buddy = BuddyAllocator::new(
+            &mut metadata,
+            start,
+            end,
+            &BootHhdm {
+                base: 0,
+                len: usize::MAX,
+            },
+        );
+        buddy.add_free_range(start, end);
+        assert_eq!(buddy.free_pages(), ARENA_PAGES);
+        f(&mut buddy, start);
+    }
+
+    #[test_case]
+    fn buddy_alloc_all_and_coalesce(_boot_info: &BootInfo) {
+        with_buddy(|buddy, start| {
+            assert_eq!(buddy.alloc(ARENA_PAGES), Some(start));
+            assert_eq!(buddy.alloc(1), None);
+            buddy.free(start, ARENA_PAGES);
+
+            for _ in 0..ARENA_PAGES {
+                let pfn = buddy.alloc(1).unwrap();
+                assert!(pfn >= start && pfn < start + ARENA_PAGES);
+            }
+            assert_eq!(buddy.free_pages(), 0);
+            for pfn in start..(start + ARENA_PAGES) {
+                buddy.free(pfn, 1);
+            }
+            // Only possible if all the single pages merged back together.
+            assert_eq!(buddy.alloc(ARENA_PAGES), Some(start));
+        });
+    }
+
+    #[test_case]
+    fn buddy_alloc_aligned(_boot_info: &BootInfo) {
+        with_buddy(|buddy, _| {
+            let one = buddy.alloc(1).unwrap();
+            let four = buddy.alloc(4).unwrap();
+            let eight = buddy.alloc(8).unwrap();
+            assert_eq!(four % 4, 0);
+            assert_eq!(eight % 8, 0);
+            assert!(one < four || one >= four + 4);
+            assert_eq!(buddy.free_pages(), ARENA_PAGES - 13);
+        });
+    }
+
+    #[test_case]
+    fn buddy_alloc_non_power_of_two(_boot_info: &BootInfo) {
+        with_buddy(|buddy, start| {
+            assert_eq!(buddy.alloc(3), Some(start));
+            // The fourth page of the block is given back straight away.
+            assert_eq!(buddy.alloc(1), Some(start + 3));
+            assert_eq!(buddy.free_pages(), ARENA_PAGES - 4);
+            buddy.free(start, 3);
+            buddy.free(start + 3, 1);
+            assert_eq!(buddy.alloc(ARENA_PAGES), Some(start));
+        });
+    }
+
+    #[test_case]
+    fn buddy_alloc_over_aligned(_boot_info: &BootInfo) {
+        with_buddy(|buddy, start| {
+            assert_eq!(buddy.alloc(1), Some(start));
+            let pfn = buddy.alloc_aligned(2, 16).unwrap();
+            assert_eq!(pfn % 16, 0);
+            assert_eq!(pfn, start + 16);
+            // Only the two pages asked for are taken out of the 16 page block.
+            assert_eq!(buddy.free_pages(), ARENA_PAGES - 3);
+            assert_eq!(buddy.alloc_aligned(1, 64), None);
+            buddy.free(pfn, 2);
+            buddy.free(start, 1);
+            assert_eq!(buddy.alloc(ARENA_PAGES), Some(start));
+        });
+    }
+
+    #[test_case]
+    fn buddy_fragmentation(_boot_info: &BootInfo) {
+        with_buddy(|buddy, start| {
+            assert_eq!(buddy.free_blocks()[5], 1);
+            assert_eq!(buddy.largest_free_run(), ARENA_PAGES);
+
+            // Taking page 8 leaves 0-7 and 9-31 free. The second run is made
+            // of blocks of order 0, 1, 2 and 4 which cannot be merged.
+            assert_eq!(buddy.alloc_aligned(1, 8), Some(start));
+            assert_eq!(buddy.alloc_aligned(1, 8), Some(start + 8));
+            buddy.free(start, 1);
+            assert_eq!(buddy.largest_free_run(), ARENA_PAGES - 9);
+            assert_eq!(buddy.free_blocks()[..5], [1, 1, 1, 1, 1]);
+            assert_eq!(buddy.free_blocks()[5], 0);
+        });
+    }
+
+    #[test_case]
+    fn buddy_is_free(_boot_info: &BootInfo) {
+        with_buddy(|buddy, start| {
+            let pfn = buddy.alloc(2).unwrap();
+            assert!(!buddy.is_free(pfn) && !buddy.is_free(pfn + 1));
+            assert!(buddy.is_free(pfn + 2) && buddy.is_free(start + ARENA_PAGES - 1));
+
+            let mut free = 0;
+            buddy.for_each_free_block(|block, order| {
+                assert!(block >= pfn + 2);
+                free += 1 << order;
+            });
+            assert_eq!(free, ARENA_PAGES - 2);
+        });
+    }
+
+    #[test_case]
+    fn buddy_order_for(_boot_info: &BootInfo) {
+        assert_eq!(order_for(0), None);
+        assert_eq!(order_for(1), Some(0));
+        assert_eq!(order_for(3), Some(2));
+        assert_eq!(order_for(1 << MAX_ORDER), Some(MAX_ORDER));
+        assert_eq!(order_for((1 << MAX_ORDER) + 1), None);
+    }
+}
@@ -0,0 +1,115 @@
+use core::fmt;
+
+use kernel_boot_interface::hhdm::BootHhdm;
+use teensy_std::addr::{PhysFrame, PAGE_SIZE};
+
+use super::buddy::LINK_BYTES;
+
+/// Byte free pages are filled with in debug mode.
+pub const POISON: u8 = 0x6b;
+const POISON_WORD: u64 = u64::from_ne_bytes([POISON; 8]);
+
+/// A misuse of palloc caught in debug mode, or a pool that was locked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PallocError {
+    /// Someone holds the pool lock, see `try_get_page`.
+    Locked,
+    /// The page is already free.
+    DoubleFree(PhysFrame),
+    /// The page was never handed to the pool, or is reserved.
+    NotOwned(PhysFrame),
+    /// The page is free and was never allocated.
+    NeverAllocated(PhysFrame),
+    /// The page is free but no longer holds the poison pattern, so it was
+    /// written after being freed.
+    UseAfterFree {
+        frame: PhysFrame,
+        offset: usize,
+        value: u64,
+    },
+}
+
+impl fmt::Display for PallocError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PallocError::Locked => write!(f, "the page pool is locked"),
+            PallocError::DoubleFree(frame) => {
+                write!(f, "double free of {:#x}", frame.start().as_usize())
+            }
+            PallocError::NotOwned(frame) => write!(
+                f,
+                "free of {:#x}, which palloc does not own",
+                frame.start().as_usize()
+            ),
+            PallocError::NeverAllocated(frame) => write!(
+                f,
+                "free of {:#x}, which palloc never handed out",
+                frame.start().as_usize()
+            ),
+            PallocError::UseAfterFree {
+                frame,
+                offset,
+                value,
+            } => write!(
+                f,
+                "use after free of {:#x}: {:#018x} at offset {:#x}",
+                frame.start().as_usize(),
+                value,
+                offset
+            ),
+        }
+    }
+}
+
+/// Fills FRAME with the poison pattern.
+pub(super) fn poison(hhdm: &BootHhdm, frame: PhysFrame) {
+    fill(hhdm, frame, 0);
+}
+
+/// Fills the free block of 2^ORDER pages at FRAME with the poison pattern,
+/// except for the links the buddy allocator keeps at its start.
+pub(super) fn poison_free_block(hhdm: &BootHhdm, frame: PhysFrame, order: usize) {
+    fill(hhdm, frame, LINK_BYTES);
+    (1..(1 << order)).for_each(|page| fill(hhdm, frame + page, 0));
+}
+
+/// Checks that FRAME, which is off the free lists, still holds the poison
+/// pattern. In debug mode the buddy allocator poisons the links of a block
+/// it takes off a list, so all of the page is checked.
+pub(super) fn check_poison(hhdm: &BootHhdm, frame: PhysFrame) -> Result<(), PallocError> {
+    check(hhdm, frame, 0)
+}
+
+/// Checks that the free block of 2^ORDER pages at FRAME still holds the
+/// poison pattern, except for its links.
+pub(super) fn check_free_block(
+    hhdm: &BootHhdm,
+    frame: PhysFrame,
+    order: usize,
+) -> Result<(), PallocError> {
+    check(hhdm, frame, LINK_BYTES)?;
+    (1..(1 << order)).try_for_each(|page| check(hhdm, frame + page, 0))
+}
+
+fn fill(hhdm: &BootHhdm, frame: PhysFrame, from: usize) {
+    unsafe {
+        hhdm.phys_to_virt(frame.start() + from)
+            .as_mut_ptr::<u8>()
+            .write_bytes(POISON, PAGE_SIZE - from);
+    }
+}
+
+fn check(hhdm: &BootHhdm, frame: PhysFrame, from: usize) -> Result<(), PallocError> {
+    let words = hhdm.phys_to_virt(frame.start()).as_ptr::<u64>();
+    for idx in (from / 8)..(PAGE_SIZE / 8) {
+        let value = unsafe { words.add(idx).read_volatile() };
+        if value != POISON_WORD {
+            return Err(PallocError::UseAfterFree {
+                frame,
+                offset: idx * 8,
+                value,
+            });
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,388 @@
+pub mod bitmap_alloc;
+mod buddy;
+pub mod debug;
+mod stats;
+mod zone;
+
+use spin;
+
+use kernel_boot_interface::{
+    hhdm::BootHhdm,
+    memmap::{BootMemType, Memmap},
+};
+use metamorphoses::bitmap::{self, Bitmap};
+use teensy_std::addr::PhysFrame;
+
+use kernel_paging;
+
+use kernel_log::kprintln;
+
+use crate::memory::{memmap, memtest};
+use crate::synch::Mutex;
+
+pub use buddy::{BuddyAllocator, MAX_ORDER};
+pub use debug::PallocError;
+pub use stats::PallocStats;
+pub use zone::{Zone, ZoneStats};
+
+use zone::Zones;
+
+// NOTE: Not a fan of using Once to make this safe
+static PAGE_POOL: spin::Once<Mutex<PagePool>> = spin::Once::new();
+
+/// Hands out runs of physical page frames, addressed by page frame number.
+pub trait PageAllocator {
+    /// Allocates NUM_PAGES contiguous pages and returns the first one's number.
+    fn alloc(&mut self, num_pages: usize) -> Option<usize>;
+    /// Gives back NUM_PAGES contiguous pages starting at PFN.
+    fn free(&mut self, pfn: usize, num_pages: usize);
+}
+
+struct PagePool {
+    pages: Zones<'static>,
+    /// One bit per page frame, set while the page is allocated.
+    allocated: Bitmap<'static>,
+    /// One bit per page frame, set once the page has been allocated.
+    handed_out: Bitmap<'static>,
+    /// Whether frees are checked and free pages are poisoned.
+    debug: bool,
+    hhdm: BootHhdm,
+    /// Pages each zone has been given, whether free or allocated.
+    managed_pages: [usize; Zone::ALL.len()],
+    /// Pages of every `BootMemType` in the boot memory map.
+    memmap_pages: [usize; BootMemType::ALL.len()],
+    /// RAM pages the memtest kept out of the pool.
+    bad_pages: usize,
+}
+
+/// Sets up the pool. Later calls do nothing: building the pool again would
+/// free pages that are in use.
+pub fn init(hhdm: &BootHhdm, memmap: &Memmap) {
+    PAGE_POOL.call_once(|| Mutex::new(init_memory_pool(hhdm, memmap)));
+}
+
+/// Allocates a page, from `Zone::Normal` if it has any left.
+#[track_caller]
+pub fn get_page() -> Option<PhysFrame> {
+    get_page_in(Zone::Normal)
+}
+
+/// Allocates a page from ZONE or a zone below it.
+#[track_caller]
+pub fn get_page_in(zone: Zone) -> Option<PhysFrame> {
+    get_pages_in(zone, 1)
+}
+
+/// Like `get_page`, but fails with `PallocError::Locked` instead of waiting
+/// for the pool lock, for callers that may have interrupted its holder.
+#[track_caller]
+pub fn try_get_page() -> Result<Option<PhysFrame>, PallocError> {
+    let mut pool = PAGE_POOL.wait().try_lock().ok_or(PallocError::Locked)?;
+    Ok(pool.get_aligned(Zone::Normal, 1, 1))
+}
+
+/// Panics in debug mode if the page is not allocated.
+#[track_caller]
+pub fn free_page(frame: PhysFrame) {
+    free_pages(frame, 1);
+}
+
+/// Allocates NUM_PAGES contiguous pages, at most 2^MAX_ORDER of them, from
+/// `Zone::Normal` if it has them.
+#[track_caller]
+pub fn get_pages(num_pages: usize) -> Option<PhysFrame> {
+    get_pages_in(Zone::Normal, num_pages)
+}
+
+/// Allocates NUM_PAGES contiguous pages from ZONE or, when it has not got
+/// them, from the zones below it. The pages lie wholly within one zone.
+#[track_caller]
+pub fn get_pages_in(zone: Zone, num_pages: usize) -> Option<PhysFrame> {
+    PAGE_POOL.wait().lock().get_aligned(zone, num_pages, 1)
+}
+
+/// Panics if any of the pages lies past the pool and, in debug mode, if any
+/// is not allocated.
+#[track_caller]
+pub fn free_pages(frame: PhysFrame, num_pages: usize) {
+    if let Err(err) = try_free_pages(frame, num_pages) {
+        panic!("palloc: {}", err);
+    }
+}
+
+/// Like `free_pages`, but returns the misuses it catches instead of
+/// panicking. Nothing is freed if an error is returned.
+pub fn try_free_pages(frame: PhysFrame, num_pages: usize) -> Result<(), PallocError> {
+    PAGE_POOL.wait().lock().free_multiple(frame, num_pages)
+}
+
+/// Turns on debug mode for good: every free page is poisoned, the poison is
+/// checked when a page is allocated and frees are checked against double
+/// frees and pages palloc does not own. Misuses panic with the address and
+/// the caller.
+pub fn enable_debug() {
+    PAGE_POOL.wait().lock().enable_debug();
+}
+
+/// In debug mode, checks that every free page still holds the poison.
+pub fn check_free_pages() -> Result<(), PallocError> {
+    PAGE_POOL.wait().lock().check_free_pages()
+}
+
+/// Allocates NUM_PAGES contiguous pages starting on an ALIGN byte boundary.
+/// ALIGN must be a power of two and at least `kernel_paging::PAGE_SIZE_MIN`.
+/// Free them with `free_pages`.
+#[track_caller]
+pub fn get_pages_aligned(num_pages: usize, align: usize) -> Option<PhysFrame> {
+    get_pages_aligned_in(Zone::Normal, num_pages, align)
+}
+
+/// Like `get_pages_aligned`, with the pages from ZONE or a zone below it.
+#[track_caller]
+pub fn get_pages_aligned_in(zone: Zone, num_pages: usize, align: usize) -> Option<PhysFrame> {
+    assert!(align.is_power_of_two() && align >= kernel_paging::PAGE_SIZE_MIN);
+    PAGE_POOL
+        .wait()
+        .lock()
+        .get_aligned(zone, num_pages, kernel_paging::page_min_no(align))
+}
+
+/// Allocates a naturally aligned frame of PAGE_SIZE bytes, which must be one
+/// of `kernel_paging::PAGE_SIZES`.
+#[track_caller]
+pub fn get_sized_frame(page_size: usize) -> Option<PhysFrame> {
+    assert!(kernel_paging::PAGE_SIZES.contains(&page_size));
+    get_pages_aligned(kernel_paging::page_min_no(page_size), page_size)
+}
+
+/// Gives back a frame from `get_sized_frame`.
+#[track_caller]
+pub fn free_sized_frame(frame: PhysFrame, page_size: usize) {
+    assert!(kernel_paging::PAGE_SIZES.contains(&page_size));
+    debug_assert!(frame.start().is_aligned(page_size));
+    free_pages(frame, kernel_paging::page_min_no(page_size));
+}
+
+/// Number of pages currently free in the pool.
+pub fn free_page_count() -> usize {
+    PAGE_POOL.wait().lock().pages.free_pages()
+}
+
+/// Takes a snapshot of the pool. Walks every page frame.
+pub fn stats() -> PallocStats {
+    PAGE_POOL.wait().lock().stats()
+}
+
+/// Writes the pool statistics to the serial log.
+pub fn dump_stats() {
+    kprintln!("{}", stats());
+}
+
+/// Hands the frames [START, END), which the pool did not own until now,
+/// over to it.
+pub fn add_region(start: PhysFrame, end: PhysFrame) {
+    PAGE_POOL.wait().lock().mark_free(start, end);
+}
+
+/// Takes the metadata out of the memblock, then hands the pool every page
+/// of RAM that is still free and retires the memblock.
+fn init_memory_pool(hhdm: &BootHhdm, memmap: &Memmap) -> PagePool {
+    memmap::init(memmap);
+    let mut memblock = memmap::memblock();
+    let memory_pages = kernel_paging::page_min_no(memblock.end().as_usize());
+
+    let buddy_words = Zones::metadata_words(memory_pages);
+    let bitmap_words = memory_pages.div_ceil(bitmap::WORD_SIZE_BITS);
+    let metadata_size = buddy_words + 2 * bitmap_words;
+    let metadata_base = memblock
+        .alloc(
+            metadata_size * core::mem::size_of::<u64>(),
+            kernel_paging::PAGE_SIZE_MIN,
+        )
+        .expect("No memory for the palloc metadata");
+
+    let metadata: &'static mut [u64] = unsafe {
+        core::slice::from_raw_parts_mut(
+            hhdm.phys_to_virt(metadata_base).as_mut_ptr(),
+            metadata_size,
+        )
+    };
+    let (buddy_metadata, bitmaps) = metadata.split_at_mut(buddy_words);
+    let (allocated, handed_out) = bitmaps.split_at_mut(bitmap_words);
+    let pages = Zones::new(buddy_metadata, memory_pages, hhdm);
+    let allocated = Bitmap::new(allocated, memory_pages);
+    let handed_out = Bitmap::new(handed_out, memory_pages);
+
+    let mut page_pool = PagePool::new(pages, allocated, handed_out, *hhdm, memmap);
+    page_pool.bad_pages = memblock.bad_pages() + memtest::unlisted_bad_pages();
+    memblock.for_each_free(|free| {
+        memtest::for_each_good_run(free, |start, end| page_pool.mark_free(start, end))
+    });
+    memblock.hand_off();
+    page_pool
+}
+
+impl PagePool {
+    /// Returns an empty pool.
+    fn new(
+        pages: Zones<'static>,
+        allocated: Bitmap<'static>,
+        handed_out: Bitmap<'static>,
+        hhdm: BootHhdm,
+        memmap: &Memmap,
+    ) -> Self {
+        let mut memmap_pages = [0; BootMemType::ALL.len()];
+        for entry in memmap.iter() {
+            memmap_pages[entry.typ as usize] += entry.len.div_ceil(kernel_paging::PAGE_SIZE_MIN);
+        }
+
+        Self {
+            pages,
+            allocated,
+            handed_out,
+            debug: false,
+            hhdm,
+            managed_pages: [0; Zone::ALL.len()],
+            memmap_pages,
+            bad_pages: 0,
+        }
+    }
+
+    fn mark_free(&mut self, start: PhysFrame, end: PhysFrame) {
+        if start < end {
+            if self.debug {
+                (start.number()..end.number())
+                    .for_each(|pfn| debug::poison(&self.hhdm, PhysFrame::from_number(pfn)));
+            }
+            let added = self.pages.add_free_range(start.number(), end.number());
+            for (managed, added) in self.managed_pages.iter_mut().zip(added) {
+                *managed += added;
+            }
+        }
+    }
+
+    fn stats(&self) -> PallocStats {
+        let free_pages = self.pages.free_pages();
+        let managed_pages = self.managed_pages.iter().sum();
+        let zones = Zone::ALL.map(|zone| ZoneStats {
+            managed_pages: self.managed_pages[zone as usize],
+            free_pages: self.pages.zone(zone).map_or(0, |zone| zone.free_pages()),
+        });
+        PallocStats {
+            memmap_pages: self.memmap_pages,
+            managed_pages,
+            free_pages,
+            used_pages: managed_pages - free_pages,
+            bad_pages: self.bad_pages,
+            largest_free_run: self.pages.largest_free_run(),
+            free_blocks: self.pages.free_blocks(),
+            zones,
+        }
+    }
+
+    #[track_caller]
+    fn get_aligned(
+        &mut self,
+        zone: Zone,
+        num_pages: usize,
+        align_pages: usize,
+    ) -> Option<PhysFrame> {
+        let pfn = self.pages.alloc_aligned(zone, num_pages, align_pages)?;
+        Some(self.track_alloc(pfn, num_pages))
+    }
+
+    #[track_caller]
+    fn track_alloc(&mut self, pfn: usize, num_pages: usize) -> PhysFrame {
+        for pfn in pfn..(pfn + num_pages) {
+            self.allocated.set(pfn);
+            self.handed_out.set(pfn);
+            if self.debug {
+                if let Err(err) = debug::check_poison(&self.hhdm, PhysFrame::from_number(pfn)) {
+                    panic!("palloc: {} found on allocation", err);
+                }
+            }
+        }
+        PhysFrame::from_number(pfn)
+    }
+
+    fn free_multiple(&mut self, frame: PhysFrame, num_pages: usize) -> Result<(), PallocError> {
+        let pfns = frame.number()..(frame.number() + num_pages);
+        if pfns.end > self.allocated.len() {
+            let first_outside = pfns.start.max(self.allocated.len());
+            return Err(PallocError::NotOwned(PhysFrame::from_number(first_outside)));
+        }
+        if self.debug {
+            for pfn in pfns.clone() {
+                let frame = PhysFrame::from_number(pfn);
+                if !self.allocated.get(pfn) {
+                    return Err(if !self.pages.is_free(pfn) {
+                        PallocError::NotOwned(frame)
+                    } else if self.handed_out.get(pfn) {
+                        PallocError::DoubleFree(frame)
+                    } else {
+                        PallocError::NeverAllocated(frame)
+                    });
+                }
+            }
+        }
+
+        for pfn in pfns {
+            self.allocated.clear(pfn);
+            if self.debug {
+                debug::poison(&self.hhdm, PhysFrame::from_number(pfn));
+            }
+        }
+        self.pages.free(frame.number(), num_pages);
+        Ok(())
+    }
+
+    fn enable_debug(&mut self) {
+        if self.debug {
+            return;
+        }
+        self.debug = true;
+        self.pages.poison_links();
+        let hhdm = self.hhdm;
+        self.pages.for_each_free_block(|pfn, order| {
+            debug::poison_free_block(&hhdm, PhysFrame::from_number(pfn), order);
+        });
+    }
+
+    fn check_free_pages(&self) -> Result<(), PallocError> {
+        if !self.debug {
+            return Ok(());
+        }
+        let mut res = Ok(());
+        self.pages.for_each_free_block(|pfn, order| {
+            if res.is_ok() {
+                res = debug::check_free_block(&self.hhdm, PhysFrame::from_number(pfn), order);
+            }
+        });
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use kernel_boot_interface::BootInfo;
+    use teensy_std::addr::PhysAddr;
+
+    #[test_case]
+    fn largest_usable_entry(boot_info: &BootInfo) {
+        let entry = memmap::get_largest_memmap_entry(&boot_info.hhdm, &boot_info.memmap);
+        let hhdm_size = boot_info.hhdm.max_len();
+        assert!(entry.base < PhysAddr::new(hhdm_size));
+        assert!(entry.typ == BootMemType::Usable);
+    }
+
+    #[test_case]
+    fn check_memory_pages(boot_info: &BootInfo) {
+        assert!(memmap::get_num_memory_pages(&boot_info.memmap) > 0);
+    }
+
+    fn test_initialise() {
+        //init(&boot_info.hhdm, &boot_info.memmap);
+    }
+}
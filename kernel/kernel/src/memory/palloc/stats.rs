@@ -0,0 +1,83 @@
+use core::fmt;
+
+use kernel_boot_interface::memmap::BootMemType;
+
+use super::{Zone, ZoneStats, MAX_ORDER};
+
+/// Snapshot of the page pool. All counts are in pages.
+#[derive(Debug, Clone, Copy)]
+pub struct PallocStats {
+    /// Pages of every `BootMemType` in the boot memory map, indexed by
+    /// `typ as usize`.
+    pub memmap_pages: [usize; BootMemType::ALL.len()],
+    /// Pages handed to the pool, free or not.
+    pub managed_pages: usize,
+    pub free_pages: usize,
+    pub used_pages: usize,
+    /// Pages of RAM found faulty, by the firmware or the memtest, that were
+    /// never handed to the pool.
+    pub bad_pages: usize,
+    /// Longest run of contiguous free pages.
+    pub largest_free_run: usize,
+    /// Number of free blocks of 2^order pages, by order.
+    pub free_blocks: [usize; MAX_ORDER + 1],
+    /// Pages of every zone, indexed by `zone as usize`.
+    pub zones: [ZoneStats; Zone::ALL.len()],
+}
+
+impl PallocStats {
+    pub fn memmap_pages_of(&self, typ: BootMemType) -> usize {
+        self.memmap_pages[typ as usize]
+    }
+
+    pub fn zone(&self, zone: Zone) -> ZoneStats {
+        self.zones[zone as usize]
+    }
+
+    /// How much of the free memory lies outside the largest free run, from
+    /// 0 (one contiguous run) to 100 percent.
+    pub fn fragmentation_percent(&self) -> usize {
+        (self.largest_free_run * 100)
+            .checked_div(self.free_pages)
+            .map_or(0, |contiguous| 100 - contiguous)
+    }
+}
+
+impl fmt::Display for PallocStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "palloc: {} pages managed, {} free, {} used",
+            self.managed_pages, self.free_pages, self.used_pages
+        )?;
+        write!(f, "  memmap:")?;
+        for typ in BootMemType::ALL {
+            write!(f, " {:?} {}", typ, self.memmap_pages_of(typ))?;
+        }
+        writeln!(f)?;
+        if self.bad_pages > 0 {
+            writeln!(f, "  bad: {} pages", self.bad_pages)?;
+        }
+        write!(f, "  zones:")?;
+        for zone in Zone::ALL {
+            let stats = self.zone(zone);
+            write!(
+                f,
+                " {} {}/{} free",
+                zone, stats.free_pages, stats.managed_pages
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "  largest free run: {} pages, fragmentation {}%",
+            self.largest_free_run,
+            self.fragmentation_percent()
+        )?;
+        write!(f, "  free blocks by order:")?;
+        for (order, count) in self.free_blocks.iter().enumerate() {
+            write!(f, " {}:{}", order, count)?;
+        }
+        Ok(())
+    }
+}
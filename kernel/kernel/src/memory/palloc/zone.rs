@@ -0,0 +1,199 @@
+use core::fmt;
+
+use kernel_boot_interface::hhdm::BootHhdm;
+
+use super::{BuddyAllocator, PageAllocator, MAX_ORDER};
+
+const NUM_ORDERS: usize = MAX_ORDER + 1;
+
+/// Physical memory split by which devices can reach it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Zone {
+    /// Below 16MiB, for ISA DMA.
+    Dma,
+    /// Below 4GiB, for devices with 32-bit DMA addresses.
+    Dma32,
+    /// Everything else.
+    Normal,
+}
+
+/// Pages of a zone, in the stats.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ZoneStats {
+    pub managed_pages: usize,
+    pub free_pages: usize,
+}
+
+/// The page pool split into one buddy allocator per zone. Zones with no
+/// memory behind them have no allocator.
+pub(super) struct Zones<'a> {
+    zones: [Option<BuddyAllocator<'a>>; Zone::ALL.len()],
+}
+
+impl Zone {
+    /// Every zone from the lowest up, so `zone as usize` indexes it.
+    pub const ALL: [Zone; 3] = [Zone::Dma, Zone::Dma32, Zone::Normal];
+
+    pub const fn start_pfn(self) -> usize {
+        match self {
+            Zone::Dma => 0,
+            Zone::Dma32 => Zone::Dma.end_pfn(),
+            Zone::Normal => Zone::Dma32.end_pfn(),
+        }
+    }
+
+    /// First page frame past the zone.
+    pub const fn end_pfn(self) -> usize {
+        match self {
+            Zone::Dma => (16 << 20) / kernel_paging::PAGE_SIZE_MIN,
+            Zone::Dma32 => (4 << 30) / kernel_paging::PAGE_SIZE_MIN,
+            Zone::Normal => usize::MAX,
+        }
+    }
+
+    /// The zone the page frame PFN is in.
+    pub fn of(pfn: usize) -> Zone {
+        Zone::ALL
+            .into_iter()
+            .find(|zone| pfn < zone.end_pfn())
+            .unwrap()
+    }
+
+    /// Zones an allocation for this zone is served from, in the order they
+    /// are tried: the zone itself, then the ones below it, as memory a device
+    /// can reach is fine for everyone else too.
+    pub fn fallbacks(self) -> &'static [Zone] {
+        match self {
+            Zone::Dma => &[Zone::Dma],
+            Zone::Dma32 => &[Zone::Dma32, Zone::Dma],
+            Zone::Normal => &[Zone::Normal, Zone::Dma32, Zone::Dma],
+        }
+    }
+}
+
+impl fmt::Display for Zone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Zone::Dma => "DMA",
+            Zone::Dma32 => "DMA32",
+            Zone::Normal => "Normal",
+        };
+        f.write_str(name)
+    }
+}
+
+impl<'a> Zones<'a> {
+    /// The part of ZONE that lies in [0, END_PFN).
+    fn span(zone: Zone, end_pfn: usize) -> Option<(usize, usize)> {
+        let start = zone.start_pfn();
+        let end = zone.end_pfn().min(end_pfn);
+        (start < end).then_some((start, end))
+    }
+
+    /// Number of words of metadata needed for the zones in [0, END_PFN).
+    pub fn metadata_words(end_pfn: usize) -> usize {
+        Zone::ALL
+            .into_iter()
+            .filter_map(|zone| Self::span(zone, end_pfn))
+            .map(|(start, end)| BuddyAllocator::metadata_words(start, end))
+            .sum()
+    }
+
+    /// Splits the page frames [0, END_PFN) into zones, none of which has
+    /// free pages yet.
+    pub fn new(mut metadata: &'a mut [u64], end_pfn: usize, hhdm: &BootHhdm) -> Self {
+        let zones = Zone::ALL.map(|zone| {
+            let (start, end) = Self::span(zone, end_pfn)?;
+            let (words, rest) = core::mem::take(&mut metadata)
+                .split_at_mut(BuddyAllocator::metadata_words(start, end));
+            metadata = rest;
+            Some(BuddyAllocator::new(words, start, end, hhdm))
+        });
+        Self { zones }
+    }
+
+    pub fn zone(&self, zone: Zone) -> Option<&BuddyAllocator<'a>> {
+        self.zones[zone as usize].as_ref()
+    }
+
+    /// Allocates from the first zone ZONE falls back to that has the pages.
+    pub fn alloc_aligned(
+        &mut self,
+        zone: Zone,
+        num_pages: usize,
+        align_pages: usize,
+    ) -> Option<usize> {
+        zone.fallbacks().iter().find_map(|&zone| {
+            self.zones[zone as usize]
+                .as_mut()?
+                .alloc_aligned(num_pages, align_pages)
+        })
+    }
+
+    /// The pages must have come from the same zone.
+    pub fn free(&mut self, pfn: usize, num_pages: usize) {
+        self.zones[Zone::of(pfn) as usize]
+            .as_mut()
+            .expect("Freed pages are in a zone")
+            .free(pfn, num_pages);
+    }
+
+    /// Hands the pages [START_PFN, END_PFN) to the zones they lie in and
+    /// returns how many each got.
+    pub fn add_free_range(&mut self, start_pfn: usize, end_pfn: usize) -> [usize; Zone::ALL.len()] {
+        let mut added = [0; Zone::ALL.len()];
+        for (zone, pages) in self.zones.iter_mut().zip(added.iter_mut()) {
+            if let Some(zone) = zone {
+                let before = zone.free_pages();
+                zone.add_free_range(start_pfn, end_pfn);
+                *pages = zone.free_pages() - before;
+            }
+        }
+        added
+    }
+
+    pub fn free_pages(&self) -> usize {
+        self.iter().map(|zone| zone.free_pages()).sum()
+    }
+
+    pub fn poison_links(&mut self) {
+        self.zones
+            .iter_mut()
+            .flatten()
+            .for_each(|zone| zone.poison_links());
+    }
+
+    pub fn is_free(&self, pfn: usize) -> bool {
+        self.zones[Zone::of(pfn) as usize]
+            .as_ref()
+            .is_some_and(|zone| zone.is_free(pfn))
+    }
+
+    pub fn for_each_free_block(&self, mut f: impl FnMut(usize, usize)) {
+        self.iter()
+            .for_each(|zone| zone.for_each_free_block(&mut f));
+    }
+
+    /// Number of free blocks of every order over all zones.
+    pub fn free_blocks(&self) -> [usize; NUM_ORDERS] {
+        let mut blocks = [0; NUM_ORDERS];
+        for zone in self.iter() {
+            for (total, count) in blocks.iter_mut().zip(zone.free_blocks()) {
+                *total += count;
+            }
+        }
+        blocks
+    }
+
+    /// Longest free run within one zone.
+    pub fn largest_free_run(&self) -> usize {
+        self.iter()
+            .map(|zone| zone.largest_free_run())
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &BuddyAllocator<'a>> {
+        self.zones.iter().flatten()
+    }
+}
@@ -0,0 +1,98 @@
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use kernel_boot_interface::{
+    memmap::{BootMemType, MemmapEntry},
+    BootInfo,
+};
+use kernel_paging::AddressSpace;
+use teensy_std::addr::{PhysAddr, PhysFrame};
+
+use crate::{acpi, memory::palloc};
+
+static BOOTLOADER_RECLAIMED: AtomicBool = AtomicBool::new(false);
+static ACPI_RECLAIMED: AtomicBool = AtomicBool::new(false);
+
+/// Frees every bootloader reclaimable page the kernel does not still depend
+/// on and returns how many there were. palloc and the heap must be up.
+///
+/// Limine's page tables, GDT and stack all live in bootloader reclaimable
+/// memory, so the tables reachable from CR3 and the GDT are kept, as is the
+/// whole region holding the stack if we are still running on limine's.
+pub fn reclaim_bootloader_memory() -> usize {
+    if BOOTLOADER_RECLAIMED.swap(true, Ordering::SeqCst) {
+        return 0;
+    }
+    let boot_info = kernel_boot::release_bootloader();
+    let space = AddressSpace::current(&boot_info.hhdm);
+
+    let mut in_use = Vec::new();
+    space.for_each_table(|table| in_use.push(PhysFrame::containing(PhysAddr::new(table))));
+    let (gdt_base, gdt_len) = kernel_cpu::gdt_region();
+    for virt in (gdt_base..(gdt_base + gdt_len)).step_by(kernel_paging::PAGE_SIZE_MIN) {
+        in_use.extend(
+            space
+                .translate(virt)
+                .map(|phys| PhysFrame::containing(PhysAddr::new(phys))),
+        );
+    }
+    in_use.sort_unstable();
+    in_use.dedup();
+
+    // Only matches limine's stack, never one from `kstack`.
+    let stack = space
+        .translate(kernel_cpu::stack_pointer())
+        .map(PhysAddr::new)
+        .expect("The stack is mapped");
+    reclaim(
+        boot_info,
+        BootMemType::BootloaderReclaimable,
+        |entry| entry.contains(stack),
+        &in_use,
+    )
+}
+
+/// Frees the ACPI reclaimable regions and returns how many pages they held.
+/// Only to be called once every ACPI table the kernel wants has been parsed;
+/// `acpi` finds no tables afterwards.
+pub fn reclaim_acpi_memory(boot_info: &BootInfo) -> usize {
+    if ACPI_RECLAIMED.swap(true, Ordering::SeqCst) {
+        return 0;
+    }
+    acpi::forget_tables();
+    reclaim(boot_info, BootMemType::AcpiReclaimable, |_| false, &[])
+}
+
+/// Frees the pages of the regions of TYP, apart from the regions KEEP_REGION
+/// picks and the frames in the sorted IN_USE.
+fn reclaim(
+    boot_info: &BootInfo,
+    typ: BootMemType,
+    keep_region: impl Fn(&MemmapEntry) -> bool,
+    in_use: &[PhysFrame],
+) -> usize {
+    let mut reclaimed = 0;
+    for entry in boot_info
+        .memmap
+        .regions(typ)
+        .filter(|entry| !keep_region(entry))
+    {
+        let start = entry.first_frame();
+        let end = entry.end_frame();
+
+        // Free the runs of pages between the ones still in use.
+        let mut run_start = start;
+        for frame in (start.number()..end.number()).map(PhysFrame::from_number) {
+            if in_use.binary_search(&frame).is_ok() {
+                reclaimed += frame - run_start;
+                palloc::add_region(run_start, frame);
+                run_start = frame + 1;
+            }
+        }
+        if run_start < end {
+            reclaimed += end - run_start;
+            palloc::add_region(run_start, end);
+        }
+    }
+    reclaimed
+}
@@ -0,0 +1,192 @@
+use kernel_boot_interface::{
+    memmap::{BootMemType, Memmap},
+    BootInfo,
+};
+use kernel_paging::{AddressSpace, CacheMode, MapError, MapFlags, PageSize};
+use teensy_std::addr::{PhysAddr, VirtAddr, PAGE_SIZE};
+
+use crate::memory::paging::PallocFrameAllocator;
+
+/// Limine maps the first 4GiB of physical memory into the HHDM whatever the
+/// memory map says, so the kernel does too.
+const HHDM_LOW_MEMORY: usize = 4 << 30;
+/// 1GiB pages are not supported by every CPU, QEMU's default one included.
+const HHDM_PAGE: PageSize = PageSize::Size2MiB;
+
+extern "C" {
+    static __text_start: u8;
+    static __text_end: u8;
+    static __rodata_start: u8;
+    static __rodata_end: u8;
+    static __data_start: u8;
+    static __data_end: u8;
+}
+
+/// A page aligned part of the kernel image mapped with one set of flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KernelSection {
+    pub name: &'static str,
+    pub start: VirtAddr,
+    pub end: VirtAddr,
+    pub flags: MapFlags,
+}
+
+/// The sections of the running kernel, as laid out by the linker script:
+/// text is read only and executable, rodata read only and data, which holds
+/// the bss too, writable. Neither of the latter is executable.
+pub fn kernel_sections() -> [KernelSection; 3] {
+    let section = |name, start: *const u8, end: *const u8, flags| KernelSection {
+        name,
+        start: VirtAddr::from_ptr(start),
+        end: VirtAddr::from_ptr(end),
+        flags,
+    };
+    [
+        section(
+            "text",
+            core::ptr::addr_of!(__text_start),
+            core::ptr::addr_of!(__text_end),
+            MapFlags::new(),
+        ),
+        section(
+            "rodata",
+            core::ptr::addr_of!(__rodata_start),
+            core::ptr::addr_of!(__rodata_end),
+            MapFlags::new().no_execute(),
+        ),
+        section(
+            "data",
+            core::ptr::addr_of!(__data_start),
+            core::ptr::addr_of!(__data_end),
+            MapFlags::kernel_data(),
+        ),
+    ]
+}
+
+/// Builds page tables mapping the kernel image section by section and the
+/// HHDM as writable but not executable. The framebuffer is write combining
+/// in the HHDM as it is when ioremapped, since two mappings of one page with
+/// different memory types are undefined. The bootloader's GDT is kept mapped
+/// where it is. palloc must be up. The new space is not loaded.
+pub fn build_kernel_space(boot_info: &BootInfo) -> AddressSpace {
+    let hhdm = &boot_info.hhdm;
+    let mut space = AddressSpace::empty(hhdm, &mut PallocFrameAllocator)
+        .expect("Out of memory for the kernel page tables");
+
+    map_hhdm(&mut space, boot_info, 0, HHDM_LOW_MEMORY);
+    for entry in boot_info.memmap.iter() {
+        map_hhdm(
+            &mut space,
+            boot_info,
+            entry.base.as_usize(),
+            entry.end().as_usize(),
+        );
+    }
+
+    let kernel = &boot_info.kernel_addr;
+    for section in kernel_sections() {
+        let mut virt = section.start;
+        while virt < section.end {
+            let phys = kernel.phys_base + (virt - kernel.virt_base);
+            map_page(&mut space, virt, phys, PageSize::Size4KiB, section.flags);
+            virt += PAGE_SIZE;
+        }
+    }
+
+    let current = AddressSpace::current(hhdm);
+    let (gdt_base, gdt_len) = kernel_cpu::gdt_region();
+    let gdt = VirtAddr::new(gdt_base).align_down(PAGE_SIZE);
+    let mut virt = gdt;
+    while virt < VirtAddr::new(gdt_base + gdt_len) {
+        if space.lookup(virt.as_usize()).is_none() {
+            let phys = current
+                .translate(virt.as_usize())
+                .expect("The GDT is mapped");
+            map_page(
+                &mut space,
+                virt,
+                PhysAddr::new(phys),
+                PageSize::Size4KiB,
+                MapFlags::kernel_data(),
+            );
+        }
+        virt += PAGE_SIZE;
+    }
+    space
+}
+
+/// Maps the physical memory [START, END) into the HHDM, rounded out to whole
+/// pages. Pages another range already mapped are skipped. Large pages that
+/// hold some of the framebuffer are split, so only the framebuffer is write
+/// combining.
+fn map_hhdm(space: &mut AddressSpace, boot_info: &BootInfo, start: usize, end: usize) {
+    let hhdm = &boot_info.hhdm;
+    let size = if hhdm.base.is_multiple_of(HHDM_PAGE.bytes()) {
+        HHDM_PAGE
+    } else {
+        PageSize::Size4KiB
+    };
+    let mut phys = PhysAddr::new(start).align_down(size.bytes());
+    while phys < PhysAddr::new(end) {
+        if size == PageSize::Size4KiB || !overlaps_framebuffer(&boot_info.memmap, phys, size) {
+            map_page(
+                space,
+                hhdm.phys_to_virt(phys),
+                phys,
+                size,
+                hhdm_flags(&boot_info.memmap, phys),
+            );
+        } else {
+            let mut page = phys;
+            while page < phys + size.bytes() {
+                map_page(
+                    space,
+                    hhdm.phys_to_virt(page),
+                    page,
+                    PageSize::Size4KiB,
+                    hhdm_flags(&boot_info.memmap, page),
+                );
+                page += PAGE_SIZE;
+            }
+        }
+        phys += size.bytes();
+    }
+}
+
+/// Whether any of the SIZE bytes at PHYS is framebuffer memory.
+fn overlaps_framebuffer(memmap: &Memmap, phys: PhysAddr, size: PageSize) -> bool {
+    memmap
+        .regions(BootMemType::Framebuffer)
+        .any(|entry| entry.base < phys + size.bytes() && phys < entry.end())
+}
+
+/// The HHDM flags of the page at PHYS.
+fn hhdm_flags(memmap: &Memmap, phys: PhysAddr) -> MapFlags {
+    let in_framebuffer = memmap
+        .find(phys)
+        .is_some_and(|entry| entry.typ == BootMemType::Framebuffer);
+    if in_framebuffer {
+        MapFlags::kernel_data().cache(CacheMode::WriteCombining)
+    } else {
+        MapFlags::kernel_data()
+    }
+}
+
+fn map_page(
+    space: &mut AddressSpace,
+    virt: VirtAddr,
+    phys: PhysAddr,
+    size: PageSize,
+    flags: MapFlags,
+) {
+    match space.map(
+        virt.as_usize(),
+        phys.as_usize(),
+        size,
+        flags,
+        &mut PallocFrameAllocator,
+    ) {
+        Ok(()) | Err(MapError::AlreadyMapped) => {}
+        Err(err) => panic!("Could not map {:?} to {:?}: {:?}", virt, phys, err),
+    }
+}
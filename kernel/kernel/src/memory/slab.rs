@@ -0,0 +1,352 @@
+use core::{
+    fmt,
+    marker::PhantomData,
+    mem::{self, MaybeUninit},
+    ptr::{self, NonNull},
+};
+
+use kernel_boot_interface::hhdm::BootHhdm;
+use teensy_std::addr::{PhysFrame, VirtAddr};
+
+use crate::memory::palloc;
+use crate::synch::Mutex;
+
+/// A slab grows until it holds at least this many objects...
+const MIN_OBJS_PER_SLAB: usize = 8;
+/// ...or reaches this many pages. Must be a power of two.
+const MAX_SLAB_PAGES: usize = 16;
+/// Empty slabs kept around per cache before they go back to palloc.
+const MAX_EMPTY_SLABS: usize = 1;
+
+static HHDM: spin::Once<BootHhdm> = spin::Once::new();
+
+/// Lets caches pull pages out of palloc. palloc must be initialised first.
+pub fn init(hhdm: &BootHhdm) {
+    HHDM.call_once(|| *hhdm);
+}
+
+/// A cache of objects of type T carved out of slabs of palloc pages.
+///
+/// Every slab is a naturally aligned run of pages starting with a
+/// `SlabHeader`, so the slab of an object is found by rounding its address
+/// down. Slabs move between the empty, partial and full lists as objects are
+/// allocated and freed.
+///
+/// The constructor of a cache runs once per object, when its slab is carved.
+/// Objects must be freed in their constructed state, so the free list links
+/// are kept after the object rather than in it.
+pub struct SlabCache<T> {
+    raw: Mutex<RawCache>,
+    ctor: Option<fn(&mut MaybeUninit<T>)>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CacheStats {
+    pub name: &'static str,
+    pub obj_size: usize,
+    pub objs_per_slab: usize,
+    pub slab_pages: usize,
+    pub slabs: usize,
+    pub empty_slabs: usize,
+    pub active_objs: usize,
+    pub allocs: usize,
+    pub frees: usize,
+    pub slabs_released: usize,
+}
+
+#[repr(C)]
+struct SlabHeader {
+    next: *mut SlabHeader,
+    prev: *mut SlabHeader,
+    free: *mut FreeObj,
+    in_use: usize,
+}
+
+struct FreeObj {
+    next: *mut FreeObj,
+}
+
+struct RawCache {
+    first_obj: usize,
+    obj_size: usize,
+    /// Where the free list link lies in a free object.
+    link_offset: usize,
+    objs_per_slab: usize,
+    slab_pages: usize,
+    partial: *mut SlabHeader,
+    full: *mut SlabHeader,
+    empty: *mut SlabHeader,
+    stats: CacheStats,
+}
+
+// Safety: the slabs are only reached through the cache's mutex.
+unsafe impl Send for RawCache {}
+
+impl<T> SlabCache<T> {
+    /// CTOR, if any, runs on every object once, before it is first handed
+    /// out.
+    pub const fn new(name: &'static str, ctor: Option<fn(&mut MaybeUninit<T>)>) -> Self {
+        Self {
+            raw: Mutex::new(RawCache::new(
+                name,
+                mem::size_of::<T>(),
+                mem::align_of::<T>(),
+                ctor.is_some(),
+            )),
+            ctor,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns an uninitialised object, unless the cache has a constructor.
+    pub fn alloc(&self) -> Option<NonNull<T>> {
+        let construct = |obj: NonNull<u8>| {
+            if let Some(ctor) = self.ctor {
+                ctor(unsafe { &mut *obj.as_ptr().cast::<MaybeUninit<T>>() });
+            }
+        };
+        Some(self.raw.lock().alloc(construct)?.cast::<T>())
+    }
+
+    /// Gives OBJ back to its slab. The object is not dropped.
+    ///
+    /// # Safety
+    /// OBJ must come from this cache and must not be used afterwards. If the
+    /// cache has a constructor, OBJ must be as the constructor left it.
+    pub unsafe fn free(&self, obj: NonNull<T>) {
+        self.raw.lock().free(obj.cast::<u8>());
+    }
+
+    /// Returns every empty slab to palloc and how many there were.
+    pub fn shrink(&self) -> usize {
+        self.raw.lock().shrink()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.raw.lock().stats
+    }
+}
+
+impl RawCache {
+    /// Objects that are KEPT_CONSTRUCTED while free get their free list link
+    /// past their end.
+    const fn new(name: &'static str, size: usize, align: usize, kept_constructed: bool) -> Self {
+        assert!(align <= kernel_paging::PAGE_SIZE_MIN);
+        let align = if align < mem::align_of::<FreeObj>() {
+            mem::align_of::<FreeObj>()
+        } else {
+            align
+        };
+        let link_offset = if kept_constructed {
+            align_up(size, mem::align_of::<FreeObj>())
+        } else {
+            0
+        };
+        let size = if size < link_offset + mem::size_of::<FreeObj>() {
+            link_offset + mem::size_of::<FreeObj>()
+        } else {
+            size
+        };
+        let obj_size = align_up(size, align);
+        let first_obj = align_up(mem::size_of::<SlabHeader>(), align);
+
+        let mut slab_pages = 1;
+        while objs_in(slab_pages, first_obj, obj_size) < MIN_OBJS_PER_SLAB
+            && slab_pages < MAX_SLAB_PAGES
+        {
+            slab_pages *= 2;
+        }
+        while objs_in(slab_pages, first_obj, obj_size) == 0 {
+            slab_pages *= 2;
+        }
+        let objs_per_slab = objs_in(slab_pages, first_obj, obj_size);
+
+        Self {
+            first_obj,
+            obj_size,
+            link_offset,
+            objs_per_slab,
+            slab_pages,
+            partial: ptr::null_mut(),
+            full: ptr::null_mut(),
+            empty: ptr::null_mut(),
+            stats: CacheStats {
+                name,
+                obj_size,
+                objs_per_slab,
+                slab_pages,
+                slabs: 0,
+                empty_slabs: 0,
+                active_objs: 0,
+                allocs: 0,
+                frees: 0,
+                slabs_released: 0,
+            },
+        }
+    }
+
+    /// CONSTRUCT runs on the objects of any slab carved for the allocation.
+    fn alloc(&mut self, construct: impl FnMut(NonNull<u8>)) -> Option<NonNull<u8>> {
+        unsafe {
+            if self.partial.is_null() {
+                if self.empty.is_null() {
+                    self.grow(construct)?;
+                }
+                let slab = self.empty;
+                unlink(&mut self.empty, slab);
+                push(&mut self.partial, slab);
+                self.stats.empty_slabs -= 1;
+            }
+
+            let slab = self.partial;
+            let link = (*slab).free;
+            (*slab).free = (*link).next;
+            (*slab).in_use += 1;
+            if (*slab).in_use == self.objs_per_slab {
+                unlink(&mut self.partial, slab);
+                push(&mut self.full, slab);
+            }
+
+            self.stats.allocs += 1;
+            self.stats.active_objs += 1;
+            NonNull::new(link.cast::<u8>().sub(self.link_offset))
+        }
+    }
+
+    unsafe fn free(&mut self, obj: NonNull<u8>) {
+        let obj = obj.as_ptr();
+        let slab = (obj as usize & !(self.slab_bytes() - 1)) as *mut SlabHeader;
+        debug_assert!((obj as usize - slab as usize) >= self.first_obj);
+        debug_assert!((obj as usize - slab as usize - self.first_obj).is_multiple_of(self.obj_size));
+
+        let was_full = (*slab).in_use == self.objs_per_slab;
+        let link = obj.add(self.link_offset).cast::<FreeObj>();
+        (*link).next = (*slab).free;
+        (*slab).free = link;
+        (*slab).in_use -= 1;
+
+        if was_full {
+            unlink(&mut self.full, slab);
+            push(&mut self.partial, slab);
+        }
+        if (*slab).in_use == 0 {
+            unlink(&mut self.partial, slab);
+            push(&mut self.empty, slab);
+            self.stats.empty_slabs += 1;
+            if self.stats.empty_slabs > MAX_EMPTY_SLABS {
+                self.release(slab);
+            }
+        }
+
+        self.stats.frees += 1;
+        self.stats.active_objs -= 1;
+    }
+
+    fn shrink(&mut self) -> usize {
+        let mut released = 0;
+        while !self.empty.is_null() {
+            unsafe { self.release(self.empty) };
+            released += 1;
+        }
+        released
+    }
+
+    /// Takes a new slab from palloc, runs CONSTRUCT on each of its objects
+    /// and puts it on the empty list.
+    unsafe fn grow(&mut self, mut construct: impl FnMut(NonNull<u8>)) -> Option<()> {
+        let hhdm = HHDM.get().expect("slab::init has not run");
+        let frame = palloc::get_pages(self.slab_pages)?;
+        let slab = hhdm.phys_to_virt(frame.start()).as_mut_ptr::<SlabHeader>();
+        debug_assert!((slab as usize).is_multiple_of(self.slab_bytes()));
+
+        let mut free = ptr::null_mut();
+        for i in (0..self.objs_per_slab).rev() {
+            let obj = (slab as usize + self.first_obj + i * self.obj_size) as *mut u8;
+            construct(NonNull::new_unchecked(obj));
+            let link = obj.add(self.link_offset).cast::<FreeObj>();
+            (*link).next = free;
+            free = link;
+        }
+        slab.write(SlabHeader {
+            next: ptr::null_mut(),
+            prev: ptr::null_mut(),
+            free,
+            in_use: 0,
+        });
+
+        push(&mut self.empty, slab);
+        self.stats.slabs += 1;
+        self.stats.empty_slabs += 1;
+        Some(())
+    }
+
+    /// Hands an empty slab back to palloc.
+    unsafe fn release(&mut self, slab: *mut SlabHeader) {
+        debug_assert!((*slab).in_use == 0);
+        unlink(&mut self.empty, slab);
+        let phys = HHDM
+            .wait()
+            .virt_to_phys(VirtAddr::from_ptr(slab))
+            .expect("Slabs live in the HHDM");
+        palloc::free_pages(PhysFrame::containing(phys), self.slab_pages);
+
+        self.stats.slabs -= 1;
+        self.stats.empty_slabs -= 1;
+        self.stats.slabs_released += 1;
+    }
+
+    const fn slab_bytes(&self) -> usize {
+        self.slab_pages * kernel_paging::PAGE_SIZE_MIN
+    }
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} objects of {} bytes active, {} slabs ({} empty) of {} pages holding {} objects, \
+             {} allocs, {} frees, {} slabs released",
+            self.name,
+            self.active_objs,
+            self.obj_size,
+            self.slabs,
+            self.empty_slabs,
+            self.slab_pages,
+            self.objs_per_slab,
+            self.allocs,
+            self.frees,
+            self.slabs_released
+        )
+    }
+}
+
+unsafe fn push(list: &mut *mut SlabHeader, slab: *mut SlabHeader) {
+    (*slab).prev = ptr::null_mut();
+    (*slab).next = *list;
+    if !(*list).is_null() {
+        (**list).prev = slab;
+    }
+    *list = slab;
+}
+
+unsafe fn unlink(list: &mut *mut SlabHeader, slab: *mut SlabHeader) {
+    let (next, prev) = ((*slab).next, (*slab).prev);
+    if prev.is_null() {
+        *list = next;
+    } else {
+        (*prev).next = next;
+    }
+    if !next.is_null() {
+        (*next).prev = prev;
+    }
+}
+
+/// Objects of OBJ_SIZE fitting in a slab of PAGES after its header.
+const fn objs_in(pages: usize, first_obj: usize, obj_size: usize) -> usize {
+    (pages * kernel_paging::PAGE_SIZE_MIN - first_obj) / obj_size
+}
+
+const fn align_up(addr: usize, align: usize) -> usize {
+    (addr + align - 1) & !(align - 1)
+}
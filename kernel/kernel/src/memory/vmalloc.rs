@@ -0,0 +1,155 @@
+use alloc::{vec, vec::Vec};
+
+use kernel_paging::{MapFlags, PageSize};
+use teensy_std::addr::{PhysAddr, PhysFrame, VirtAddr, PAGE_SIZE};
+
+use crate::memory::{
+    paging, palloc,
+    vmregion::{self, VmKind, VmRegion},
+};
+use crate::synch::Mutex;
+
+/// Part of the higher half handed out by `vmalloc` and `ioremap`.
+const VMALLOC_START: usize = 0xffff_e000_0000_0000;
+const VMALLOC_END: usize = 0xffff_e010_0000_0000;
+/// Unmapped pages left after every allocation so overruns fault.
+const GUARD_PAGES: usize = 1;
+
+static SPACE: spin::Once<Mutex<VirtRanges>> = spin::Once::new();
+
+/// The free parts of the vmalloc area as sorted, disjoint [start, end) ranges.
+struct VirtRanges {
+    free: Vec<(usize, usize)>,
+}
+
+/// Returns SIZE bytes of virtually contiguous, uninitialised kernel memory
+/// backed by pages which need not be physically contiguous. The heap, paging
+/// and palloc must be up.
+pub fn vmalloc(size: usize) -> Option<VirtAddr> {
+    let pages = size.div_ceil(PAGE_SIZE);
+    if pages == 0 {
+        return None;
+    }
+    let start = reserve_range(pages)?;
+
+    for page in 0..pages {
+        if !map_new_page(start + page * PAGE_SIZE) {
+            unmap_pages(start, page);
+            release_range(start, pages);
+            return None;
+        }
+    }
+
+    let region = VmRegion {
+        start: VirtAddr::new(start),
+        end: VirtAddr::new(start + pages * PAGE_SIZE),
+        flags: MapFlags::kernel_data(),
+        kind: VmKind::Fixed,
+        name: "vmalloc",
+    };
+    vmregion::register(region).expect("vmalloc ranges never overlap");
+    Some(region.start)
+}
+
+/// Unmaps the allocation at ADDR and gives its pages and virtual range back.
+///
+/// # Safety
+/// ADDR must come from `vmalloc` and nothing may use the memory afterwards.
+pub unsafe fn vfree(addr: VirtAddr) {
+    assert!(
+        is_vmalloc_addr(addr),
+        "vfree of {:?}, which is not a vmalloc address",
+        addr
+    );
+    assert!(
+        vmregion::find(addr)
+            .is_some_and(|region| region.start == addr && region.kind == VmKind::Fixed),
+        "vfree of an address vmalloc never returned"
+    );
+    let region = vmregion::unregister(addr).unwrap();
+    let pages = (region.end - region.start) / PAGE_SIZE;
+    unmap_pages(addr.as_usize(), pages);
+    release_range(addr.as_usize(), pages);
+}
+
+/// Whether ADDR lies in the area vmalloc hands out.
+pub fn is_vmalloc_addr(addr: VirtAddr) -> bool {
+    (VMALLOC_START..VMALLOC_END).contains(&addr.as_usize())
+}
+
+/// Reserves PAGES pages of the vmalloc area, followed by unmapped guard
+/// pages, and returns the address of the first one.
+pub(crate) fn reserve_range(pages: usize) -> Option<usize> {
+    space().lock().reserve(pages + GUARD_PAGES)
+}
+
+/// Gives back a range of PAGES pages from `reserve_range`.
+pub(crate) fn release_range(start: usize, pages: usize) {
+    space().lock().release(start, pages + GUARD_PAGES);
+}
+
+fn space() -> &'static Mutex<VirtRanges> {
+    SPACE.call_once(|| {
+        Mutex::new(VirtRanges {
+            free: vec![(VMALLOC_START, VMALLOC_END)],
+        })
+    })
+}
+
+fn map_new_page(virt: usize) -> bool {
+    let Some(frame) = palloc::get_page() else {
+        return false;
+    };
+    let mapped = paging::map(
+        virt,
+        frame.start().as_usize(),
+        PageSize::Size4KiB,
+        MapFlags::kernel_data(),
+    );
+    if mapped.is_err() {
+        palloc::free_page(frame);
+    }
+    mapped.is_ok()
+}
+
+fn unmap_pages(start: usize, pages: usize) {
+    for page in 0..pages {
+        let mapping = paging::unmap(start + page * PAGE_SIZE).expect("vmalloc pages are mapped");
+        palloc::free_page(PhysFrame::containing(PhysAddr::new(mapping.phys)));
+    }
+}
+
+impl VirtRanges {
+    /// First fit.
+    fn reserve(&mut self, pages: usize) -> Option<usize> {
+        let len = pages * PAGE_SIZE;
+        let idx = self
+            .free
+            .iter()
+            .position(|&(start, end)| end - start >= len)?;
+        let (start, end) = self.free[idx];
+        if end - start == len {
+            self.free.remove(idx);
+        } else {
+            self.free[idx].0 += len;
+        }
+        Some(start)
+    }
+
+    /// Gives back a range from `reserve`, merging it with its neighbours.
+    fn release(&mut self, start: usize, pages: usize) {
+        let end = start + pages * PAGE_SIZE;
+        let idx = self.free.partition_point(|&(other, _)| other < start);
+        let merges_prev = idx > 0 && self.free[idx - 1].1 == start;
+        let merges_next = idx < self.free.len() && self.free[idx].0 == end;
+        match (merges_prev, merges_next) {
+            (true, true) => {
+                self.free[idx - 1].1 = self.free[idx].1;
+                self.free.remove(idx);
+            }
+            (true, false) => self.free[idx - 1].1 = end,
+            (false, true) => self.free[idx].0 = start,
+            (false, false) => self.free.insert(idx, (start, end)),
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use alloc::vec::Vec;
+
+use kernel_paging::MapFlags;
+use teensy_std::addr::{PhysAddr, PhysFrame, VirtAddr, PAGE_SIZE};
+
+use crate::memory::{paging, palloc};
+use crate::synch::Mutex;
+
+/// Kernel virtual memory regions, sorted by start address and never
+/// overlapping. The page fault handler looks faulting addresses up here.
+static REGIONS: Mutex<Vec<VmRegion>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmKind {
+    /// Whoever registered the region maps its pages.
+    Fixed,
+    /// Pages are backed by zeroed palloc frames on first access.
+    DemandZero,
+    /// Maps device memory, which palloc does not own.
+    Mmio,
+}
+
+/// A page aligned range [start, end) of kernel virtual memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VmRegion {
+    pub start: VirtAddr,
+    pub end: VirtAddr,
+    pub flags: MapFlags,
+    pub kind: VmKind,
+    pub name: &'static str,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmError {
+    /// The region is empty or its bounds are not page aligned.
+    BadRange,
+    /// The region overlaps one that is already registered.
+    Overlaps,
+    /// No region starts at the given address.
+    NotRegistered,
+    /// Someone holds the registry lock, see `try_find`.
+    Locked,
+}
+
+impl VmRegion {
+    pub fn contains(&self, addr: VirtAddr) -> bool {
+        self.start <= addr && addr < self.end
+    }
+}
+
+/// Adds REGION to the registry. The heap must be up.
+pub fn register(region: VmRegion) -> Result<(), VmError> {
+    if region.start >= region.end
+        || !region.start.is_aligned(PAGE_SIZE)
+        || !region.end.is_aligned(PAGE_SIZE)
+    {
+        return Err(VmError::BadRange);
+    }
+
+    let mut regions = REGIONS.lock();
+    let idx = regions.partition_point(|other| other.start < region.start);
+    let overlaps_prev = idx > 0 && regions[idx - 1].end > region.start;
+    let overlaps_next = idx < regions.len() && regions[idx].start < region.end;
+    if overlaps_prev || overlaps_next {
+        return Err(VmError::Overlaps);
+    }
+    regions.insert(idx, region);
+    Ok(())
+}
+
+/// Removes the region starting at START. The frames faulted into a demand
+/// zero region are unmapped and freed, the mappings of other regions are left
+/// to their owner.
+pub fn unregister(start: VirtAddr) -> Result<VmRegion, VmError> {
+    let region = {
+        let mut regions = REGIONS.lock();
+        let idx = regions
+            .binary_search_by_key(&start, |region| region.start)
+            .map_err(|_| VmError::NotRegistered)?;
+        regions.remove(idx)
+    };
+
+    if region.kind == VmKind::DemandZero {
+        let mut virt = region.start;
+        while virt < region.end {
+            if let Ok(mapping) = paging::unmap(virt.as_usize()) {
+                palloc::free_page(PhysFrame::containing(PhysAddr::new(mapping.phys)));
+            }
+            virt += PAGE_SIZE;
+        }
+    }
+    Ok(region)
+}
+
+/// Returns the region holding ADDR.
+pub fn find(addr: VirtAddr) -> Option<VmRegion> {
+    lookup(&REGIONS.lock(), addr)
+}
+
+/// Like `find`, but fails with `VmError::Locked` instead of waiting for the
+/// registry lock, for callers that may have interrupted its holder.
+pub fn try_find(addr: VirtAddr) -> Result<Option<VmRegion>, VmError> {
+    let regions = REGIONS.try_lock().ok_or(VmError::Locked)?;
+    Ok(lookup(&regions, addr))
+}
+
+fn lookup(regions: &[VmRegion], addr: VirtAddr) -> Option<VmRegion> {
+    let idx = regions.partition_point(|region| region.start <= addr);
+    idx.checked_sub(1)
+        .map(|idx| regions[idx])
+        .filter(|region| region.contains(addr))
+}
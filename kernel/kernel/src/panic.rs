@@ -1,16 +1,29 @@
+use core::panic::PanicInfo;
+
 use kernel_cpu;
-use kernel_log::kprintln;
+
+static PANIC_HOOK: spin::Once<fn(&PanicInfo)> = spin::Once::new();
+
+/// HOOK is called with every panic once it was logged, before the kernel
+/// shuts down, so that a test can check it panicked the way it meant to.
+/// Only the first hook set is kept.
+pub fn set_panic_hook(hook: fn(&PanicInfo)) {
+    PANIC_HOOK.call_once(|| hook);
+}
 
 #[cfg(not(test))]
 #[panic_handler]
-fn rust_panic(info: &core::panic::PanicInfo) -> ! {
-    kprintln!("{:?}", info);
+fn rust_panic(info: &PanicInfo) -> ! {
+    kernel_log::kprintln_forced!("{:?}", info);
+    if let Some(hook) = PANIC_HOOK.get() {
+        hook(info);
+    }
     kernel_shutdown::shutdown(kernel_shutdown::ShutdownExitCode::Failed);
     kernel_cpu::hcf();
 }
 
 #[cfg(test)]
 #[panic_handler]
-fn rust_panic(info: &core::panic::PanicInfo) -> ! {
+fn rust_panic(info: &PanicInfo) -> ! {
     kernel_test::panic(info);
 }
@@ -0,0 +1,188 @@
+//! Keeping time. At boot the TSC and the local APIC timer are calibrated
+//! against the HPET, or the PIT where there is none. The monotonic clock
+//! then counts on the TSC if it is invariant and on the HPET otherwise.
+
+pub mod tick;
+
+use core::hint::spin_loop;
+
+use kernel_log::kprintln;
+use kernel_paging::CacheMode;
+use kernel_timer::{Hpet, HpetTable, HPET_MMIO_SIZE};
+
+use crate::{acpi, memory::ioremap::ioremap};
+
+pub const NSEC_PER_SEC: u64 = 1_000_000_000;
+pub const NSEC_PER_USEC: u64 = 1_000;
+/// How long each calibration run waits on the reference timer.
+const CALIBRATION_US: u64 = 10_000;
+/// Calibration runs. A run can only be stretched by the time it takes to
+/// notice the reference ran out, so the lowest rate measured is kept.
+const CALIBRATION_RUNS: usize = 3;
+
+static CLOCK: spin::Once<Clock> = spin::Once::new();
+
+/// What the monotonic clock reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockSource {
+    Tsc,
+    Hpet,
+}
+
+struct Clock {
+    source: ClockSource,
+    hpet: Option<Hpet>,
+    tsc_hz: u64,
+    /// Reading of the clock source when the clock started.
+    start: u64,
+}
+
+/// COUNT ticks at HZ, in nanoseconds.
+fn ticks_to_ns(count: u64, hz: u64) -> u64 {
+    (count as u128 * NSEC_PER_SEC as u128 / hz as u128) as u64
+}
+
+/// The rate of something that counted COUNT in NS nanoseconds.
+fn rate_hz(count: u64, ns: u64) -> u64 {
+    (count as u128 * NSEC_PER_SEC as u128 / ns as u128) as u64
+}
+
+/// Finds the HPET, calibrates the TSC and the local APIC timer, starts the
+/// monotonic clock and sets up the tick source. ACPI, paging and the
+/// interrupt controllers must be up. Later calls do nothing.
+pub fn init() {
+    if CLOCK.is_completed() {
+        return;
+    }
+    let hpet = find_hpet();
+    let (tsc_hz, lapic_timer_hz) = kernel_cpu::without_interrupts(|| calibrate(hpet.as_ref()));
+
+    // A 32-bit HPET wraps around in minutes, so a TSC that drifts with the
+    // power state is still the better clock then.
+    let source = match hpet {
+        Some(hpet) if hpet.has_64bit_counter() && !kernel_timer::has_invariant_tsc() => {
+            ClockSource::Hpet
+        }
+        _ => ClockSource::Tsc,
+    };
+    let start = match source {
+        ClockSource::Tsc => kernel_cpu::rdtsc(),
+        ClockSource::Hpet => hpet.as_ref().unwrap().counter(),
+    };
+    CLOCK.call_once(|| Clock {
+        source,
+        hpet,
+        tsc_hz,
+        start,
+    });
+    kprintln!(
+        "time: TSC at {} kHz, local APIC timer at {} kHz, clock source {:?}",
+        tsc_hz / 1000,
+        lapic_timer_hz / 1000,
+        source
+    );
+    tick::init(lapic_timer_hz);
+}
+
+/// The HPET the ACPI tables list, mapped and running.
+fn find_hpet() -> Option<Hpet> {
+    let table = HpetTable::parse(acpi::find_table(b"HPET")?)
+        .map_err(|err| {
+            kprintln!("time: ignoring the HPET table: {:?}", err);
+        })
+        .ok()?;
+    let Some(base) = ioremap(table.address, HPET_MMIO_SIZE, CacheMode::Uncached) else {
+        kprintln!("time: ignoring the HPET, it cannot be mapped");
+        return None;
+    };
+    unsafe { Hpet::new(base) }
+        .map_err(|err| {
+            kprintln!("time: ignoring the HPET: {:?}", err);
+        })
+        .ok()
+}
+
+/// Measures the rates of the TSC and of the local APIC timer against HPET,
+/// or the PIT without one.
+fn calibrate(hpet: Option<&Hpet>) -> (u64, u64) {
+    let lapic = kernel_interrupts::local_apic().expect("The local APIC is up");
+    let mut tsc_hz = u64::MAX;
+    let mut lapic_timer_hz = u64::MAX;
+    for _ in 0..CALIBRATION_RUNS {
+        lapic.start_timer(kernel_interrupts::TimerMode::OneShot, u32::MAX, None);
+        let tsc_start = kernel_cpu::rdtsc();
+        let elapsed_ns = reference_delay(hpet, CALIBRATION_US);
+        let tsc_end = kernel_cpu::rdtsc();
+        let lapic_ticks = u32::MAX - lapic.timer_count();
+        lapic.stop_timer();
+
+        tsc_hz = tsc_hz.min(rate_hz(tsc_end - tsc_start, elapsed_ns));
+        lapic_timer_hz = lapic_timer_hz.min(rate_hz(lapic_ticks as u64, elapsed_ns));
+    }
+    (tsc_hz, lapic_timer_hz)
+}
+
+/// Busy waits about US microseconds on HPET, or the PIT without one, and
+/// returns how many nanoseconds the wait took as far as it can tell.
+fn reference_delay(hpet: Option<&Hpet>, us: u64) -> u64 {
+    match hpet {
+        Some(hpet) => {
+            let ticks = hpet.ns_to_ticks(us.saturating_mul(NSEC_PER_USEC));
+            let start = hpet.counter();
+            let mut elapsed = 0;
+            while elapsed < ticks {
+                spin_loop();
+                elapsed = hpet.ticks_between(start, hpet.counter());
+            }
+            hpet.ticks_to_ns(elapsed)
+        }
+        None => {
+            kernel_timer::pit_delay_us(us);
+            us.saturating_mul(NSEC_PER_USEC)
+        }
+    }
+}
+
+/// Nanoseconds since `init`, never going backwards. Zero before it.
+pub fn now_ns() -> u64 {
+    let Some(clock) = CLOCK.get() else {
+        return 0;
+    };
+    match (clock.source, &clock.hpet) {
+        (ClockSource::Hpet, Some(hpet)) => {
+            hpet.ticks_to_ns(hpet.ticks_between(clock.start, hpet.counter()))
+        }
+        _ => ticks_to_ns(kernel_cpu::rdtsc() - clock.start, clock.tsc_hz),
+    }
+}
+
+/// Busy waits at least US microseconds. Waits on the PIT before `init`.
+pub fn udelay(us: u64) {
+    if !CLOCK.is_completed() {
+        kernel_timer::pit_delay_us(us);
+        return;
+    }
+    let end = now_ns().saturating_add(us.saturating_mul(NSEC_PER_USEC));
+    while now_ns() < end {
+        spin_loop();
+    }
+}
+
+/// Whether the timers were calibrated against an HPET, not the PIT.
+pub fn has_hpet() -> bool {
+    CLOCK.get().is_some_and(|clock| clock.hpet.is_some())
+}
+
+pub fn clock_source() -> Option<ClockSource> {
+    CLOCK.get().map(|clock| clock.source)
+}
+
+/// The calibrated rate of the TSC.
+pub fn tsc_frequency() -> Option<u64> {
+    CLOCK.get().map(|clock| clock.tsc_hz)
+}
+
+/// The calibrated rate of the local APIC timer, with its divider applied.
+pub fn lapic_timer_frequency() -> Option<u64> {
+    tick::lapic_timer_hz()
+}
@@ -0,0 +1,150 @@
+//! The tick source: the local APIC timer of this CPU, calling a handler once
+//! after a delay or periodically. There is one timer, so the tick serves one
+//! client at a time and `start_tick` fails with `TimeError::Busy` while
+//! another client's tick runs.
+
+use core::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use kernel_interrupts::TimerMode;
+
+use super::NSEC_PER_SEC;
+use crate::{
+    interrupts::{self, IrqReturn},
+    synch::Mutex,
+};
+
+static TICK: Mutex<Tick> = Mutex::new(Tick {
+    vector: None,
+    lapic_timer_hz: 0,
+    client: None,
+});
+/// Times the tick fired.
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// Called with the cookie it was started with, in interrupt context and
+/// with interrupts disabled. It may start the tick again.
+pub type TickHandler = fn(cookie: usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TickMode {
+    OneShot,
+    Periodic,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeError {
+    /// `time::init` has not run.
+    NotInitialised,
+    /// The tick is already running.
+    Busy,
+    /// The timer cannot count this many nanoseconds.
+    BadPeriod(u64),
+}
+
+struct Tick {
+    vector: Option<u8>,
+    lapic_timer_hz: u64,
+    client: Option<TickClient>,
+}
+
+#[derive(Clone, Copy)]
+struct TickClient {
+    mode: TickMode,
+    handler: TickHandler,
+    cookie: usize,
+}
+
+impl fmt::Display for TimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimeError::NotInitialised => write!(f, "timers not set up"),
+            TimeError::Busy => write!(f, "the tick is already running"),
+            TimeError::BadPeriod(ns) => write!(f, "cannot tick every {} ns", ns),
+        }
+    }
+}
+
+pub(super) fn init(lapic_timer_hz: u64) {
+    let handle = interrupts::request_vector("lapic-timer", tick_interrupt, 0)
+        .unwrap_or_else(|err| panic!("time: no vector for the tick: {}", err));
+    // The handle is never freed, so the tick keeps its vector for good.
+    let mut tick = TICK.lock();
+    tick.vector = Some(handle.vector());
+    tick.lapic_timer_hz = lapic_timer_hz;
+}
+
+pub(super) fn lapic_timer_hz() -> Option<u64> {
+    let tick = kernel_cpu::without_interrupts(|| TICK.lock().lapic_timer_hz);
+    (tick != 0).then_some(tick)
+}
+
+/// Calls HANDLER with COOKIE PERIOD_NS nanoseconds from now, and every
+/// PERIOD_NS nanoseconds after that if MODE is periodic, until `stop_tick`.
+pub fn start_tick(
+    mode: TickMode,
+    period_ns: u64,
+    handler: TickHandler,
+    cookie: usize,
+) -> Result<(), TimeError> {
+    kernel_cpu::without_interrupts(|| {
+        let mut tick = TICK.lock();
+        let vector = tick.vector.ok_or(TimeError::NotInitialised)?;
+        if tick.client.is_some() {
+            return Err(TimeError::Busy);
+        }
+        let count = (period_ns as u128 * tick.lapic_timer_hz as u128 / NSEC_PER_SEC as u128)
+            .try_into()
+            .ok()
+            .filter(|&count: &u32| count > 0)
+            .ok_or(TimeError::BadPeriod(period_ns))?;
+        tick.client = Some(TickClient {
+            mode,
+            handler,
+            cookie,
+        });
+        let timer_mode = match mode {
+            TickMode::OneShot => TimerMode::OneShot,
+            TickMode::Periodic => TimerMode::Periodic,
+        };
+        lapic().start_timer(timer_mode, count, Some(vector));
+        Ok(())
+    })
+}
+
+/// Stops the tick, if it is running. A tick already raised may still call
+/// the handler.
+pub fn stop_tick() {
+    kernel_cpu::without_interrupts(|| {
+        let mut tick = TICK.lock();
+        if tick.client.take().is_some() {
+            lapic().stop_timer();
+        }
+    });
+}
+
+/// Times the tick fired since boot.
+pub fn tick_count() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+fn lapic() -> &'static kernel_interrupts::LocalApic {
+    kernel_interrupts::local_apic().expect("The local APIC is up")
+}
+
+fn tick_interrupt(_cookie: usize) -> IrqReturn {
+    TICKS.fetch_add(1, Ordering::Relaxed);
+    let client = {
+        let mut tick = TICK.lock();
+        match tick.client {
+            Some(client) if client.mode == TickMode::OneShot => tick.client.take(),
+            client => client,
+        }
+    };
+    if let Some(client) = client {
+        (client.handler)(client.cookie);
+    }
+    IrqReturn::Handled
+}
@@ -0,0 +1,87 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel_test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use kernel_boot_interface::{hhdm::BootHhdm, BootInfo};
+use teensy_std::addr::{Page, PhysAddr, PhysFrame, VirtAddr, PAGE_SIZE};
+
+#[no_mangle]
+pub extern "C" fn _kernel_start() -> ! {
+    test_main();
+    kernel_cpu::hcf();
+}
+
+#[test_case]
+fn address_arithmetic(_boot_info: &BootInfo) {
+    let addr = PhysAddr::new(0x1234);
+    assert_eq!(addr.align_down(PAGE_SIZE), PhysAddr::new(0x1000));
+    assert_eq!(addr.align_up(PAGE_SIZE), PhysAddr::new(0x2000));
+    assert!(!addr.is_aligned(PAGE_SIZE));
+    assert_eq!(addr + 0x10, PhysAddr::new(0x1244));
+    assert_eq!(addr - PhysAddr::new(0x1000), 0x234);
+    assert_eq!(addr.checked_sub(0x1235), None);
+    assert_eq!(PhysAddr::new(usize::MAX).checked_add(1), None);
+    assert_eq!(
+        addr.checked_align_up(PAGE_SIZE),
+        Some(PhysAddr::new(0x2000))
+    );
+    assert_eq!(
+        PhysAddr::new(usize::MAX - 0x10).checked_align_up(PAGE_SIZE),
+        None
+    );
+    assert_eq!(
+        VirtAddr::new(0x0000_7fff_ffff_f001).checked_align_up(PAGE_SIZE),
+        None
+    );
+
+    let frame = PhysFrame::containing(addr);
+    assert_eq!(frame.start(), PhysAddr::new(0x1000));
+    assert_eq!((frame + 2).number(), 3);
+    assert_eq!(PhysFrame::from_start(addr), None);
+}
+
+#[test_case]
+fn virtual_addresses_are_canonical(_boot_info: &BootInfo) {
+    assert!(VirtAddr::try_new(0x0000_7fff_ffff_ffff).is_some());
+    assert!(VirtAddr::try_new(0xffff_8000_0000_0000).is_some());
+    assert!(VirtAddr::try_new(0x0000_8000_0000_0000).is_none());
+    assert!(VirtAddr::try_new(0xffff_7fff_ffff_ffff).is_none());
+    assert!(VirtAddr::try_new(0x0001_0000_0000_0000).is_none());
+
+    let last_low = VirtAddr::new(0x0000_7fff_ffff_f000);
+    assert_eq!(last_low.checked_add(PAGE_SIZE), None);
+    assert_eq!(
+        Page::containing(VirtAddr::new(0xffff_8000_0000_0123)).start(),
+        VirtAddr::new(0xffff_8000_0000_0000)
+    );
+}
+
+#[test_case]
+fn hhdm_conversions_stay_in_the_window(boot_info: &BootInfo) {
+    let hhdm = BootHhdm {
+        base: 0xffff_8000_0000_0000,
+        len: 0x1_0000_0000,
+    };
+    let phys = PhysAddr::new(0x1234_5000);
+    let virt = hhdm.phys_to_virt(phys);
+    assert_eq!(virt, VirtAddr::new(0xffff_8000_1234_5000));
+    assert_eq!(hhdm.virt_to_phys(virt), Some(phys));
+    assert_eq!(
+        hhdm.virt_to_phys(VirtAddr::new(0xffff_7fff_ffff_f000)),
+        None
+    );
+    assert_eq!(
+        hhdm.virt_to_phys(VirtAddr::new(0xffff_8001_0000_0000)),
+        None
+    );
+
+    let kernel = boot_info.kernel_addr.virt_base;
+    assert_eq!(boot_info.hhdm.virt_to_phys(kernel), None);
+}
+
+#[panic_handler]
+pub fn test_panic(info: &core::panic::PanicInfo) -> ! {
+    kernel_test::panic(info);
+}
@@ -0,0 +1,100 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel_test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use kernel_boot_interface::BootInfo;
+use kernel_paging::MapFlags;
+use odysseos::memory::{
+    fault, heap, paging, palloc,
+    vmregion::{self, VmError, VmKind, VmRegion},
+};
+use teensy_std::addr::{VirtAddr, PAGE_SIZE};
+
+/// Nothing lives this far up the higher half on boot.
+const REGION_START: VirtAddr = VirtAddr::new(0xffff_c800_0000_0000);
+const REGION_PAGES: usize = 4;
+
+#[no_mangle]
+pub extern "C" fn _kernel_start() -> ! {
+    test_main();
+    kernel_cpu::hcf();
+}
+
+fn init(boot_info: &BootInfo) {
+    palloc::init(&boot_info.hhdm, &boot_info.memmap);
+    paging::init(boot_info);
+    heap::init(&boot_info.hhdm);
+    fault::init(&boot_info.hhdm);
+}
+
+fn region(start: VirtAddr, pages: usize) -> VmRegion {
+    VmRegion {
+        start,
+        end: start + pages * PAGE_SIZE,
+        flags: MapFlags::kernel_data(),
+        kind: VmKind::DemandZero,
+        name: "test",
+    }
+}
+
+fn table_count() -> usize {
+    let mut count = 0;
+    paging::kernel_space().for_each_table(|_| count += 1);
+    count
+}
+
+#[test_case]
+fn demand_zero_faults_in_pages(boot_info: &BootInfo) {
+    init(boot_info);
+    vmregion::register(region(REGION_START, REGION_PAGES)).unwrap();
+    let free_before = palloc::free_page_count();
+    let tables_before = table_count();
+
+    let second = REGION_START + PAGE_SIZE;
+    assert_eq!(paging::translate(second.as_usize()), None);
+    let ptr = (second + 8).as_mut_ptr::<u64>();
+    assert_eq!(unsafe { ptr.read_volatile() }, 0);
+    assert!(paging::translate(second.as_usize()).is_some());
+    // Only the page touched is backed.
+    assert_eq!(paging::translate(REGION_START.as_usize()), None);
+
+    unsafe { ptr.write_volatile(0xdead_beef) };
+    assert_eq!(unsafe { ptr.read_volatile() }, 0xdead_beef);
+
+    vmregion::unregister(REGION_START).unwrap();
+    assert_eq!(paging::translate(second.as_usize()), None);
+    // The frame goes back, the page tables pulled in by the fault stay.
+    let new_tables = table_count() - tables_before;
+    assert!(new_tables <= 3);
+    assert_eq!(palloc::free_page_count() + new_tables, free_before);
+}
+
+#[test_case]
+fn vmregion_lookup_and_overlap(boot_info: &BootInfo) {
+    init(boot_info);
+    let start = REGION_START + 64 * PAGE_SIZE;
+    vmregion::register(region(start, REGION_PAGES)).unwrap();
+
+    assert_eq!(
+        vmregion::register(region(start + PAGE_SIZE, 1)),
+        Err(VmError::Overlaps)
+    );
+    assert_eq!(
+        vmregion::register(region(start - PAGE_SIZE, 2)),
+        Err(VmError::Overlaps)
+    );
+    assert_eq!(
+        vmregion::register(region(start + 1, 1)),
+        Err(VmError::BadRange)
+    );
+
+    assert_eq!(vmregion::find(start + 5).map(|r| r.start), Some(start));
+    assert_eq!(vmregion::find(start + REGION_PAGES * PAGE_SIZE), None);
+    assert_eq!(vmregion::find(start - 1), None);
+
+    vmregion::unregister(start).unwrap();
+    assert_eq!(vmregion::find(start), None);
+    assert_eq!(vmregion::unregister(start), Err(VmError::NotRegistered));
+}
@@ -0,0 +1,29 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel_test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::arch::asm;
+
+use kernel_boot_interface::BootInfo;
+
+#[no_mangle]
+pub extern "C" fn _kernel_start() -> ! {
+    test_main();
+    kernel_cpu::hcf();
+}
+
+/// An exception nothing handles panics with its name. The test passes by
+/// panicking, so it is the only one in this binary.
+#[test_case]
+fn invalid_opcode_panics(_boot_info: &BootInfo) {
+    kernel_cpu::init_idt();
+    unsafe { asm!("ud2") };
+    panic!("Execution carried on after ud2");
+}
+
+#[panic_handler]
+pub fn test_panic(info: &core::panic::PanicInfo) -> ! {
+    kernel_test::expect_panic(info, "CPU exception Invalid Opcode (#6)");
+}
@@ -0,0 +1,67 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel_test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::arch::asm;
+
+use kernel_boot_interface::BootInfo;
+
+#[no_mangle]
+pub extern "C" fn _kernel_start() -> ! {
+    test_main();
+    kernel_cpu::hcf();
+}
+
+fn init() {
+    kernel_cpu::init_gdt();
+    kernel_cpu::init_idt();
+}
+
+#[test_case]
+fn exception_names(_boot_info: &BootInfo) {
+    assert_eq!(kernel_cpu::exception_name(0), "Divide Error");
+    assert_eq!(kernel_cpu::exception_name(8), "Double Fault");
+    assert_eq!(kernel_cpu::exception_name(13), "General Protection");
+    assert_eq!(
+        kernel_cpu::exception_name(kernel_cpu::PAGE_FAULT_VECTOR),
+        "Page Fault"
+    );
+    assert_eq!(kernel_cpu::exception_name(31), "Reserved");
+    assert_eq!(kernel_cpu::exception_name(32), "Unknown");
+}
+
+/// A breakpoint is reported and execution carries on after the `int3` with
+/// every register as it was.
+#[test_case]
+fn breakpoint_resumes(_boot_info: &BootInfo) {
+    init();
+    let (r12, r15): (u64, u64);
+    unsafe {
+        asm!(
+            "mov r12, 0x1234",
+            "mov r15, 0x5678",
+            "int3",
+            out("r12") r12,
+            out("r15") r15,
+        );
+    }
+    assert_eq!(r12, 0x1234);
+    assert_eq!(r15, 0x5678);
+}
+
+#[test_case]
+fn probe_still_recovers(_boot_info: &BootInfo) {
+    init();
+    // Limine only identity maps the low 4GiB of the lower half.
+    let addr = 0x7fff_0000_0000;
+    let fault = unsafe { kernel_cpu::probe_write_u8(addr, 0) }.unwrap_err();
+    assert_eq!(fault.addr, addr);
+    assert!(fault.error.write());
+}
+
+#[panic_handler]
+pub fn test_panic(info: &core::panic::PanicInfo) -> ! {
+    kernel_test::panic(info);
+}
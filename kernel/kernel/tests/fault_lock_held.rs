@@ -0,0 +1,55 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel_test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use kernel_boot_interface::BootInfo;
+use kernel_paging::MapFlags;
+use odysseos::{
+    memory::{
+        fault, heap, paging, palloc,
+        vmregion::{self, VmKind, VmRegion},
+    },
+    panic,
+};
+use teensy_std::addr::{VirtAddr, PAGE_SIZE};
+
+/// Nothing lives this far up the higher half on boot.
+const REGION_START: VirtAddr = VirtAddr::new(0xffff_c800_0000_0000);
+
+#[no_mangle]
+pub extern "C" fn _kernel_start() -> ! {
+    test_main();
+    kernel_cpu::hcf();
+}
+
+fn init(boot_info: &BootInfo) {
+    palloc::init(&boot_info.hhdm, &boot_info.memmap);
+    paging::init(boot_info);
+    heap::init(&boot_info.hhdm);
+    fault::init(&boot_info.hhdm);
+}
+
+/// A fault taken while the kernel address space is locked would spin on that
+/// lock for ever if the handler waited for it. The test passes by panicking,
+/// so it is the only one in this binary.
+#[test_case]
+fn fault_with_the_address_space_locked_panics(boot_info: &BootInfo) {
+    init(boot_info);
+    vmregion::register(VmRegion {
+        start: REGION_START,
+        end: REGION_START + PAGE_SIZE,
+        flags: MapFlags::kernel_data(),
+        kind: VmKind::DemandZero,
+        name: "test",
+    })
+    .unwrap();
+    panic::set_panic_hook(|info| {
+        kernel_test::expect_panic(info, "the kernel address space lock is held")
+    });
+
+    let _space = paging::kernel_space();
+    unsafe { REGION_START.as_mut_ptr::<u64>().write_volatile(1) };
+    panic!("The fault was handled with the address space locked");
+}
@@ -0,0 +1,56 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel_test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use kernel_boot_interface::BootInfo;
+use kernel_cpu::IstStack;
+
+#[no_mangle]
+pub extern "C" fn _kernel_start() -> ! {
+    test_main();
+    kernel_cpu::hcf();
+}
+
+#[test_case]
+fn segments_are_reloaded(_boot_info: &BootInfo) {
+    kernel_cpu::init_gdt();
+    assert_eq!(kernel_cpu::code_segment(), kernel_cpu::KERNEL_CODE_SELECTOR);
+    assert_eq!(
+        kernel_cpu::stack_segment(),
+        kernel_cpu::KERNEL_DATA_SELECTOR
+    );
+    assert_eq!(kernel_cpu::task_register(), kernel_cpu::TSS_SELECTOR);
+
+    let (_, len) = kernel_cpu::gdt_region();
+    assert_eq!(len, 7 * 8);
+}
+
+#[test_case]
+fn gdt_reloads(_boot_info: &BootInfo) {
+    kernel_cpu::init_gdt();
+    let region = kernel_cpu::gdt_region();
+    kernel_cpu::init_gdt();
+    assert_eq!(kernel_cpu::gdt_region(), region);
+    assert_eq!(kernel_cpu::task_register(), kernel_cpu::TSS_SELECTOR);
+}
+
+#[test_case]
+fn ist_stacks_are_separate(_boot_info: &BootInfo) {
+    kernel_cpu::init_gdt();
+    let tops = IstStack::ALL.map(kernel_cpu::ist_stack_top);
+    for (idx, &top) in tops.iter().enumerate() {
+        assert!(top != 0 && top % 16 == 0);
+        assert!(tops[idx + 1..]
+            .iter()
+            .all(|&other| other.abs_diff(top) >= 4096));
+    }
+    let rsp = kernel_cpu::stack_pointer();
+    assert!(tops.iter().all(|&top| rsp.abs_diff(top) >= 4096));
+}
+
+#[panic_handler]
+pub fn test_panic(info: &core::panic::PanicInfo) -> ! {
+    kernel_test::panic(info);
+}
@@ -0,0 +1,79 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel_test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::{boxed::Box, collections::BTreeMap, vec::Vec};
+
+use kernel_boot_interface::BootInfo;
+use odysseos::memory::{heap, palloc};
+
+#[no_mangle]
+pub extern "C" fn _kernel_start() -> ! {
+    test_main();
+    kernel_cpu::hcf();
+}
+
+fn init(boot_info: &BootInfo) {
+    palloc::init(&boot_info.hhdm, &boot_info.memmap);
+    heap::init(&boot_info.hhdm);
+}
+
+#[test_case]
+fn heap_box(boot_info: &BootInfo) {
+    init(boot_info);
+    let a = Box::new(41u64);
+    let b = Box::new(1u64);
+    assert_eq!(*a + *b, 42);
+}
+
+#[test_case]
+fn heap_vec_grows_past_a_page(boot_info: &BootInfo) {
+    init(boot_info);
+    let len = 4 * kernel_paging::PAGE_SIZE_MIN;
+    let v: Vec<usize> = (0..len).collect();
+    assert!(heap::heap_pages() * kernel_paging::PAGE_SIZE_MIN >= len * core::mem::size_of::<usize>());
+    for (i, x) in v.iter().enumerate() {
+        assert_eq!(i, *x);
+    }
+}
+
+#[test_case]
+fn heap_btree_map(boot_info: &BootInfo) {
+    init(boot_info);
+    let mut map = BTreeMap::new();
+    for i in 0..1000u64 {
+        map.insert(i, i * i);
+    }
+    for i in 0..1000u64 {
+        assert_eq!(map[&i], i * i);
+    }
+}
+
+#[test_case]
+fn heap_aligned(boot_info: &BootInfo) {
+    #[repr(align(4096))]
+    struct PageAligned([u8; 4096]);
+
+    init(boot_info);
+    let _unaligned = Box::new(1u8);
+    let page = Box::new(PageAligned([0xCC; 4096]));
+    assert_eq!(&*page as *const PageAligned as usize % 4096, 0);
+    assert!(page.0.iter().all(|b| *b == 0xCC));
+}
+
+#[test_case]
+fn heap_reuses_freed_memory(boot_info: &BootInfo) {
+    init(boot_info);
+    // Warm the heap up so the loop below never needs to grow it.
+    drop(Vec::<u8>::with_capacity(64 * 1024));
+    let pages = heap::heap_pages();
+    for _ in 0..1000 {
+        let v: Vec<u8> = Vec::with_capacity(64 * 1024);
+        drop(v);
+    }
+    assert_eq!(heap::heap_pages(), pages);
+}
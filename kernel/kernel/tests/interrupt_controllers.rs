@@ -0,0 +1,131 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel_test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use kernel_boot_interface::BootInfo;
+use kernel_interrupts::{Madt, MadtCpu, MadtError, Polarity, Trigger, MAX_LAPIC_NMIS};
+use kernel_test::fixtures::fake_acpi_table;
+use odysseos::{
+    acpi, interrupts,
+    memory::{fault, heap, paging, palloc},
+};
+use teensy_std::addr::PhysAddr;
+
+#[no_mangle]
+pub extern "C" fn _kernel_start() -> ! {
+    test_main();
+    kernel_cpu::hcf();
+}
+
+fn init(boot_info: &BootInfo) {
+    palloc::init(&boot_info.hhdm, &boot_info.memmap);
+    paging::init(boot_info);
+    heap::init(&boot_info.hhdm);
+    fault::init(&boot_info.hhdm);
+    acpi::init(boot_info).unwrap();
+    interrupts::init();
+}
+
+/// A MADT as QEMU's looks: one CPU, one IOAPIC, the timer moved to GSI 2,
+/// the ACPI SCI level triggered and LINT1 wired to NMI.
+fn fake_madt() -> [u8; 90] {
+    let mut madt = fake_acpi_table(b"APIC");
+    madt[36..40].copy_from_slice(&0xfee0_0000u32.to_le_bytes());
+    madt[40..44].copy_from_slice(&1u32.to_le_bytes());
+    let entries: [&[u8]; 5] = [
+        &[0, 8, 0, 0, 1, 0, 0, 0],
+        &[1, 12, 0, 0, 0x00, 0x00, 0xc0, 0xfe, 0, 0, 0, 0],
+        &[2, 10, 0, 0, 2, 0, 0, 0, 0, 0],
+        &[2, 10, 0, 9, 9, 0, 0, 0, 0x0f, 0],
+        &[4, 6, 0xff, 0, 0, 1],
+    ];
+    let mut offset = 44;
+    for entry in entries {
+        madt[offset..offset + entry.len()].copy_from_slice(entry);
+        offset += entry.len();
+    }
+    assert_eq!(offset, madt.len());
+    madt
+}
+
+#[test_case]
+fn madt_parses(_boot_info: &BootInfo) {
+    let table = fake_madt();
+    let madt = Madt::parse(&table).unwrap();
+    assert_eq!(madt.lapic_address, PhysAddr::new(0xfee0_0000));
+    assert!(madt.has_legacy_pics);
+    assert_eq!(
+        madt.cpus(),
+        &[MadtCpu {
+            processor: 0,
+            apic_id: 0
+        }]
+    );
+    assert_eq!(madt.ioapics().len(), 1);
+    assert_eq!(madt.ioapics()[0].address, PhysAddr::new(0xfec0_0000));
+
+    let overrides = madt.overrides();
+    assert_eq!((overrides[0].irq, overrides[0].gsi), (0, 2));
+    assert_eq!(overrides[0].trigger, Trigger::Edge);
+    assert_eq!(overrides[1].polarity, Polarity::ActiveLow);
+    assert_eq!(overrides[1].trigger, Trigger::Level);
+
+    let nmi = madt.lapic_nmis()[0];
+    assert_eq!((nmi.processor, nmi.lint), (None, 1));
+}
+
+/// One LAPIC NMI entry more than the parser keeps is dropped, not an error.
+#[test_case]
+fn madt_keeps_the_first_entries(_boot_info: &BootInfo) {
+    let mut table = fake_acpi_table::<{ 44 + (MAX_LAPIC_NMIS + 1) * 6 }>(b"APIC");
+    for (idx, entry) in table[44..].chunks_exact_mut(6).enumerate() {
+        entry.copy_from_slice(&[4, 6, idx as u8, 0, 0, 1]);
+    }
+    let madt = Madt::parse(&table).unwrap();
+    assert_eq!(madt.lapic_nmis().len(), MAX_LAPIC_NMIS);
+    let last = madt.lapic_nmis()[MAX_LAPIC_NMIS - 1];
+    assert_eq!(last.processor, Some(MAX_LAPIC_NMIS as u32 - 1));
+}
+
+#[test_case]
+fn madt_rejects_garbage(_boot_info: &BootInfo) {
+    let mut table = fake_madt();
+    assert_eq!(Madt::parse(&table[..60]).err(), Some(MadtError::Truncated));
+    table[0] = b'X';
+    assert_eq!(Madt::parse(&table).err(), Some(MadtError::BadSignature));
+}
+
+#[test_case]
+fn controllers_come_up(boot_info: &BootInfo) {
+    init(boot_info);
+    assert!(acpi::find_table(b"APIC").is_some());
+    assert!(acpi::signatures().any(|signature| signature == *b"FACP"));
+
+    assert_eq!(kernel_interrupts::legacy_pic_masks(), 0xffff);
+    let lapic = kernel_interrupts::local_apic().unwrap();
+    let madt = kernel_interrupts::madt().unwrap();
+    assert!(madt.cpus().iter().any(|cpu| cpu.apic_id == lapic.id()));
+    assert!(!kernel_cpu::interrupts_enabled());
+}
+
+#[test_case]
+fn isa_irq_routing(boot_info: &BootInfo) {
+    init(boot_info);
+    let keyboard = kernel_interrupts::isa_irq(1);
+    assert_eq!(keyboard.trigger, Trigger::Edge);
+
+    kernel_interrupts::route(keyboard, 0x41).unwrap();
+    let redirection = kernel_interrupts::redirection(keyboard.gsi).unwrap();
+    assert_eq!(redirection.vector, 0x41);
+    assert!(redirection.masked);
+
+    kernel_interrupts::unmask(keyboard.gsi).unwrap();
+    assert!(!kernel_interrupts::redirection(keyboard.gsi).unwrap().masked);
+    kernel_interrupts::mask(keyboard.gsi).unwrap();
+    assert!(kernel_interrupts::redirection(keyboard.gsi).unwrap().masked);
+
+    assert!(kernel_interrupts::route(keyboard, 14).is_err());
+    assert!(kernel_interrupts::mask(10_000).is_err());
+}
@@ -0,0 +1,128 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel_test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use kernel_boot_interface::{memmap::BootMemType, BootInfo};
+use kernel_paging::CacheMode;
+use odysseos::memory::{
+    fault, heap,
+    ioremap::{ioremap, iounmap},
+    paging, palloc,
+};
+use teensy_std::addr::PhysAddr;
+
+#[no_mangle]
+pub extern "C" fn _kernel_start() -> ! {
+    test_main();
+    kernel_cpu::hcf();
+}
+
+fn init(boot_info: &BootInfo) {
+    palloc::init(&boot_info.hhdm, &boot_info.memmap);
+    paging::init(boot_info);
+    heap::init(&boot_info.hhdm);
+    fault::init(&boot_info.hhdm);
+}
+
+#[test_case]
+fn pat_has_write_combining(boot_info: &BootInfo) {
+    init(boot_info);
+    // Entry 5 is write combining, the first four keep their reset values.
+    let pat = kernel_paging::read_pat();
+    assert_eq!((pat >> 40) & 0xff, 0x01);
+    assert_eq!(pat & 0xffff_ffff, 0x0007_0406);
+}
+
+#[test_case]
+fn ioremap_framebuffer_write_combining(boot_info: &BootInfo) {
+    init(boot_info);
+    let fb = &boot_info.frame_buffer;
+    let size = (fb.pitch * fb.height) as usize;
+    let pixels = ioremap(fb.phys_address, size, CacheMode::WriteCombining).unwrap();
+
+    let mapping = paging::kernel_space().lookup(pixels.as_usize()).unwrap();
+    assert_eq!(mapping.flags.cache, CacheMode::WriteCombining);
+    assert_eq!(mapping.phys, fb.phys_address.align_down(4096).as_usize());
+    assert_eq!(
+        paging::translate((pixels + size - 1).as_usize()),
+        Some((fb.phys_address + size - 1).as_usize())
+    );
+
+    unsafe {
+        pixels.as_mut_ptr::<u32>().write_volatile(0x00ff_00ff);
+        assert_eq!(pixels.as_ptr::<u32>().read_volatile(), 0x00ff_00ff);
+        iounmap(pixels);
+    }
+    assert_eq!(paging::translate(pixels.as_usize()), None);
+}
+
+/// The HHDM alias of the framebuffer must have the memory type of its
+/// ioremap mapping, while the memory around it stays write back.
+#[test_case]
+fn hhdm_framebuffer_write_combining(boot_info: &BootInfo) {
+    init(boot_info);
+    let hhdm = &boot_info.hhdm;
+    let fb = boot_info
+        .memmap
+        .find(boot_info.frame_buffer.phys_address)
+        .unwrap();
+    let cache = |phys: PhysAddr| {
+        paging::kernel_space()
+            .lookup(hhdm.phys_to_virt(phys).as_usize())
+            .unwrap()
+            .flags
+            .cache
+    };
+    assert_eq!(cache(fb.base), CacheMode::WriteCombining);
+    assert_eq!(cache(fb.end() - 1), CacheMode::WriteCombining);
+
+    let usable = boot_info
+        .memmap
+        .regions(BootMemType::Usable)
+        .next()
+        .unwrap();
+    assert_eq!(cache(usable.base), CacheMode::WriteBack);
+}
+
+#[test_case]
+fn ioremap_keeps_page_offset(boot_info: &BootInfo) {
+    init(boot_info);
+    let phys = boot_info.frame_buffer.phys_address + 0x123;
+    let regs = ioremap(phys, 8, CacheMode::Uncached).unwrap();
+    assert_eq!(regs.as_usize() % 4096, 0x123);
+    assert_eq!(paging::translate(regs.as_usize()), Some(phys.as_usize()));
+    assert_eq!(
+        paging::kernel_space()
+            .lookup(regs.as_usize())
+            .unwrap()
+            .flags
+            .cache,
+        CacheMode::Uncached
+    );
+    unsafe { iounmap(regs) };
+}
+
+#[test_case]
+fn ioremap_rejects_wrapping_ranges(boot_info: &BootInfo) {
+    init(boot_info);
+    let phys = PhysAddr::new(usize::MAX - 0xfff);
+    assert_eq!(ioremap(phys, 0x1000, CacheMode::Uncached), None);
+    assert_eq!(ioremap(phys + 0x10, 0x10, CacheMode::Uncached), None);
+}
+
+#[test_case]
+fn ioremap_rejects_ram(boot_info: &BootInfo) {
+    init(boot_info);
+    for typ in [
+        BootMemType::Usable,
+        BootMemType::BootloaderReclaimable,
+        BootMemType::KernelAndModules,
+    ] {
+        let entry = boot_info.memmap.regions(typ).next().unwrap();
+        assert_eq!(ioremap(entry.base, 8, CacheMode::Uncached), None);
+        // A range that only starts in the entry touches it all the same.
+        assert_eq!(ioremap(entry.end() - 0x10, 0x20, CacheMode::Uncached), None);
+    }
+}
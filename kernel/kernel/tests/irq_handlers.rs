@@ -0,0 +1,172 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel_test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use kernel_boot_interface::BootInfo;
+use kernel_interrupts::Trigger;
+use odysseos::{
+    acpi,
+    interrupts::{self, IrqError, IrqReturn},
+    memory::{fault, heap, paging, palloc},
+};
+
+static FIRST_CALLS: AtomicUsize = AtomicUsize::new(0);
+static SECOND_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[no_mangle]
+pub extern "C" fn _kernel_start() -> ! {
+    test_main();
+    kernel_cpu::hcf();
+}
+
+fn init(boot_info: &BootInfo) {
+    palloc::init(&boot_info.hhdm, &boot_info.memmap);
+    paging::init(boot_info);
+    heap::init(&boot_info.hhdm);
+    fault::init(&boot_info.hhdm);
+    kernel_cpu::init_idt();
+    acpi::init(boot_info).unwrap();
+    interrupts::init();
+}
+
+fn cookie(calls: &'static AtomicUsize) -> usize {
+    calls as *const AtomicUsize as usize
+}
+
+fn calls(cookie: usize) -> &'static AtomicUsize {
+    unsafe { &*(cookie as *const AtomicUsize) }
+}
+
+fn claim(cookie: usize) -> IrqReturn {
+    calls(cookie).fetch_add(1, Ordering::SeqCst);
+    IrqReturn::Handled
+}
+
+/// Reads the statistics, which take the lock of the handler table.
+fn read_stats(cookie: usize) -> IrqReturn {
+    interrupts::unhandled_count();
+    claim(cookie)
+}
+
+fn decline(cookie: usize) -> IrqReturn {
+    calls(cookie).fetch_add(1, Ordering::SeqCst);
+    IrqReturn::NotHandled
+}
+
+/// Raises VECTOR through the local APIC and waits until it was dispatched.
+fn raise(vector: u8) {
+    let before = interrupts::irq_count(vector);
+    kernel_interrupts::local_apic()
+        .unwrap()
+        .send_self_ipi(vector);
+    kernel_cpu::enable_interrupts();
+    while interrupts::irq_count(vector) == before {
+        core::hint::spin_loop();
+    }
+    kernel_cpu::disable_interrupts();
+}
+
+#[test_case]
+fn msi_handler_gets_its_cookie(boot_info: &BootInfo) {
+    init(boot_info);
+    let before = FIRST_CALLS.load(Ordering::SeqCst);
+    let handle = interrupts::request_msi("test-msi", claim, cookie(&FIRST_CALLS)).unwrap();
+    assert!(handle.vector() >= interrupts::FIRST_DEVICE_VECTOR);
+    assert_eq!(handle.msi_message().data, handle.vector() as u32);
+
+    raise(handle.vector());
+    raise(handle.vector());
+    assert_eq!(FIRST_CALLS.load(Ordering::SeqCst), before + 2);
+    assert_eq!(interrupts::irq_count(handle.vector()), 2);
+    assert_eq!(interrupts::handled_count(&handle), 2);
+    interrupts::free_irq(handle);
+}
+
+#[test_case]
+fn shared_line_asks_every_handler(boot_info: &BootInfo) {
+    init(boot_info);
+    let line = kernel_interrupts::isa_irq(1);
+    let first = FIRST_CALLS.load(Ordering::SeqCst);
+    let second = SECOND_CALLS.load(Ordering::SeqCst);
+    let declining =
+        interrupts::request_irq(line, "decline", decline, cookie(&FIRST_CALLS)).unwrap();
+    let claiming = interrupts::request_irq(line, "claim", claim, cookie(&SECOND_CALLS)).unwrap();
+    assert_eq!(declining.vector(), claiming.vector());
+    assert!(!kernel_interrupts::redirection(line.gsi).unwrap().masked);
+
+    raise(claiming.vector());
+    assert_eq!(FIRST_CALLS.load(Ordering::SeqCst), first + 1);
+    assert_eq!(SECOND_CALLS.load(Ordering::SeqCst), second + 1);
+    assert_eq!(interrupts::handled_count(&declining), 0);
+    assert_eq!(interrupts::handled_count(&claiming), 1);
+    interrupts::dump_stats();
+
+    interrupts::free_irq(declining);
+    assert!(!kernel_interrupts::redirection(line.gsi).unwrap().masked);
+    interrupts::free_irq(claiming);
+    assert!(kernel_interrupts::redirection(line.gsi).unwrap().masked);
+}
+
+/// A claim does not stop the handlers after it, whose devices may have
+/// raised the interrupt too.
+#[test_case]
+fn shared_line_calls_handlers_after_a_claim(boot_info: &BootInfo) {
+    init(boot_info);
+    let line = kernel_interrupts::isa_irq(1);
+    let first = FIRST_CALLS.load(Ordering::SeqCst);
+    let second = SECOND_CALLS.load(Ordering::SeqCst);
+    let claiming = interrupts::request_irq(line, "claim", claim, cookie(&FIRST_CALLS)).unwrap();
+    let also_claiming =
+        interrupts::request_irq(line, "claim too", claim, cookie(&SECOND_CALLS)).unwrap();
+
+    raise(claiming.vector());
+    assert_eq!(FIRST_CALLS.load(Ordering::SeqCst), first + 1);
+    assert_eq!(SECOND_CALLS.load(Ordering::SeqCst), second + 1);
+    assert_eq!(interrupts::handled_count(&claiming), 1);
+    assert_eq!(interrupts::handled_count(&also_claiming), 1);
+
+    interrupts::free_irq(claiming);
+    interrupts::free_irq(also_claiming);
+}
+
+#[test_case]
+fn shared_line_must_match(boot_info: &BootInfo) {
+    init(boot_info);
+    let line = kernel_interrupts::isa_irq(1);
+    let handle = interrupts::request_irq(line, "claim", claim, cookie(&FIRST_CALLS)).unwrap();
+    let level = kernel_interrupts::IrqLine {
+        trigger: Trigger::Level,
+        ..line
+    };
+    assert_eq!(
+        interrupts::request_irq(level, "level", claim, cookie(&FIRST_CALLS)),
+        Err(IrqError::LineMismatch(line.gsi))
+    );
+    interrupts::free_irq(handle);
+}
+
+#[test_case]
+fn unclaimed_interrupts_are_counted(boot_info: &BootInfo) {
+    init(boot_info);
+    let handle = interrupts::request_msi("decline", decline, cookie(&SECOND_CALLS)).unwrap();
+    let unhandled = interrupts::unhandled_count();
+    raise(handle.vector());
+    assert_eq!(interrupts::unhandled_count(), unhandled + 1);
+    assert_eq!(interrupts::handled_count(&handle), 0);
+    interrupts::free_irq(handle);
+}
+
+#[test_case]
+fn handler_can_read_stats(boot_info: &BootInfo) {
+    init(boot_info);
+    let before = FIRST_CALLS.load(Ordering::SeqCst);
+    let handle = interrupts::request_msi("stats", read_stats, cookie(&FIRST_CALLS)).unwrap();
+    raise(handle.vector());
+    assert_eq!(FIRST_CALLS.load(Ordering::SeqCst), before + 1);
+    assert_eq!(interrupts::handled_count(&handle), 1);
+    interrupts::free_irq(handle);
+}
@@ -0,0 +1,69 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel_test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use kernel_boot_interface::BootInfo;
+use kernel_log::kprintln;
+use kernel_paging::AddressSpace;
+use teensy_std::addr::VirtAddr;
+
+extern "C" {
+    static __kernel_image_start: u8;
+    static __kernel_image_end: u8;
+}
+
+#[no_mangle]
+pub extern "C" fn _kernel_start() -> ! {
+    test_main();
+    kernel_cpu::hcf();
+}
+
+#[test_case]
+fn kernel_reports_load_address(boot_info: &BootInfo) {
+    let kernel = &boot_info.kernel_addr;
+    let start = VirtAddr::from_ptr(core::ptr::addr_of!(__kernel_image_start));
+    let end = VirtAddr::from_ptr(core::ptr::addr_of!(__kernel_image_end));
+    assert_eq!(kernel.virt_base, start);
+    assert_eq!(kernel.link_base + kernel.slide(), start);
+
+    let entry = VirtAddr::new(_kernel_start as extern "C" fn() -> ! as usize);
+    assert!(start <= entry && entry < end);
+
+    let space = AddressSpace::current(&boot_info.hhdm);
+    assert_eq!(
+        space.translate(start.as_usize()),
+        Some(kernel.phys_base.as_usize())
+    );
+}
+
+#[test_case]
+fn kernel_is_relocated(boot_info: &BootInfo) {
+    static TARGET: u8 = 0;
+    static POINTER: &u8 = &TARGET;
+    assert!(kernel_boot::is_relocated());
+    let pointer = unsafe { core::ptr::read_volatile(&POINTER) };
+    assert!(core::ptr::eq(pointer, &TARGET));
+    assert!(boot_info
+        .kernel_addr
+        .virt_base
+        .is_aligned(kernel_paging::PAGE_SIZE_MIN));
+}
+
+/// limine.cfg boots with KASLR, so the kernel is placed at a new random
+/// address every boot. One boot cannot tell that from a fixed base, so the
+/// runner boots any kernel printing this line twice and fails if both print
+/// the same base.
+#[test_case]
+fn kernel_base_is_randomised(boot_info: &BootInfo) {
+    kprintln!(
+        "kaslr: kernel base {:#x}",
+        boot_info.kernel_addr.virt_base.as_usize()
+    );
+}
+
+#[panic_handler]
+pub fn test_panic(info: &core::panic::PanicInfo) -> ! {
+    kernel_test::panic(info);
+}
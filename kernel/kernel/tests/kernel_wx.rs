@@ -0,0 +1,103 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel_test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use kernel_boot_interface::BootInfo;
+use odysseos::memory::{
+    fault, heap, paging, palloc,
+    remap::{self, KernelSection},
+};
+use teensy_std::addr::{PhysAddr, VirtAddr, PAGE_SIZE};
+
+static mut DATA_BYTE: u8 = 0;
+
+#[no_mangle]
+pub extern "C" fn _kernel_start() -> ! {
+    test_main();
+    kernel_cpu::hcf();
+}
+
+fn init(boot_info: &BootInfo) {
+    palloc::init(&boot_info.hhdm, &boot_info.memmap);
+    paging::init(boot_info);
+    heap::init(&boot_info.hhdm);
+    fault::init(&boot_info.hhdm);
+}
+
+fn section(name: &str) -> KernelSection {
+    remap::kernel_sections()
+        .into_iter()
+        .find(|section| section.name == name)
+        .unwrap()
+}
+
+#[test_case]
+fn sections_are_wx(boot_info: &BootInfo) {
+    init(boot_info);
+    for section in remap::kernel_sections() {
+        assert!(section.start.is_aligned(PAGE_SIZE) && section.end.is_aligned(PAGE_SIZE));
+        assert!(!(section.flags.writable && !section.flags.no_execute));
+
+        let mut virt = section.start;
+        while virt < section.end {
+            let mapping = paging::kernel_space().lookup(virt.as_usize()).unwrap();
+            assert_eq!(mapping.flags.writable, section.flags.writable);
+            assert_eq!(mapping.flags.no_execute, section.flags.no_execute);
+            virt += PAGE_SIZE;
+        }
+    }
+    assert!(!section("text").flags.writable);
+    assert!(section("rodata").flags.no_execute);
+    assert!(section("data").flags.no_execute);
+}
+
+#[test_case]
+fn hhdm_is_not_executable(boot_info: &BootInfo) {
+    init(boot_info);
+    let frame = palloc::get_page().unwrap();
+    let virt = boot_info.hhdm.phys_to_virt(frame.start());
+    let mapping = paging::kernel_space().lookup(virt.as_usize()).unwrap();
+    assert!(mapping.flags.no_execute);
+    assert!(mapping.flags.writable);
+    assert_eq!(
+        paging::translate(virt.as_usize()),
+        Some(frame.start().as_usize())
+    );
+    palloc::free_page(frame);
+
+    let low = boot_info.hhdm.phys_to_virt(PhysAddr::new(0x1000));
+    assert!(paging::translate(low.as_usize()).is_some());
+}
+
+#[test_case]
+fn writing_text_faults(boot_info: &BootInfo) {
+    init(boot_info);
+    let text = VirtAddr::new(init as fn(&BootInfo) as usize);
+    let byte = unsafe { text.as_ptr::<u8>().read_volatile() };
+
+    let fault = unsafe { kernel_cpu::probe_write_u8(text.as_usize(), byte) }.unwrap_err();
+    assert_eq!(fault.addr, text.as_usize());
+    assert!(fault.error.present());
+    assert!(fault.error.write());
+    assert!(!fault.error.user());
+}
+
+#[test_case]
+fn writing_rodata_faults(boot_info: &BootInfo) {
+    init(boot_info);
+    let rodata = section("rodata").start;
+    let byte = unsafe { rodata.as_ptr::<u8>().read_volatile() };
+
+    let fault = unsafe { kernel_cpu::probe_write_u8(rodata.as_usize(), byte) }.unwrap_err();
+    assert!(fault.error.present() && fault.error.write());
+}
+
+#[test_case]
+fn writing_data_works(boot_info: &BootInfo) {
+    init(boot_info);
+    let data = core::ptr::addr_of_mut!(DATA_BYTE);
+    assert!(unsafe { kernel_cpu::probe_write_u8(data as usize, 0x5a) }.is_ok());
+    assert_eq!(unsafe { data.read_volatile() }, 0x5a);
+}
@@ -0,0 +1,73 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel_test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use kernel_boot_interface::BootInfo;
+use odysseos::memory::{
+    kstack::{self, KernelStack, KERNEL_STACK_PAGES},
+    paging, palloc,
+};
+use teensy_std::addr::PAGE_SIZE;
+
+#[no_mangle]
+pub extern "C" fn _kernel_start() -> ! {
+    test_main();
+    kernel_cpu::hcf();
+}
+
+fn init(boot_info: &BootInfo) {
+    palloc::init(&boot_info.hhdm, &boot_info.memmap);
+    paging::init(boot_info);
+}
+
+#[test_case]
+fn kstack_layout(boot_info: &BootInfo) {
+    init(boot_info);
+    let stack = KernelStack::new().unwrap();
+
+    assert_eq!(stack.top() - stack.bottom(), KERNEL_STACK_PAGES * PAGE_SIZE);
+    assert!(stack.top().is_aligned(16));
+    assert_eq!(stack.guard_page().start() + PAGE_SIZE, stack.bottom());
+
+    let guard = stack.guard_page().start();
+    assert_eq!(paging::translate(guard.as_usize()), None);
+    assert!(kstack::is_guard_page(guard + 8));
+    assert!(!kstack::is_guard_page(stack.bottom()));
+    assert!(!kstack::is_guard_page(stack.top() - 8));
+
+    let words = (stack.top() - stack.bottom()) / 8;
+    let base = stack.bottom().as_mut_ptr::<u64>();
+    for i in 0..words {
+        unsafe { base.add(i).write_volatile(i as u64) };
+    }
+    for i in 0..words {
+        assert_eq!(unsafe { base.add(i).read_volatile() }, i as u64);
+    }
+}
+
+#[test_case]
+fn kstack_drop_gives_back(boot_info: &BootInfo) {
+    init(boot_info);
+    // The first stack may pull in page tables that stay around.
+    drop(KernelStack::new().unwrap());
+    let free_before = palloc::free_page_count();
+
+    let stack = KernelStack::new().unwrap();
+    let bottom = stack.bottom().as_usize();
+    assert_eq!(palloc::free_page_count(), free_before - KERNEL_STACK_PAGES);
+    drop(stack);
+
+    assert_eq!(palloc::free_page_count(), free_before);
+    assert_eq!(paging::translate(bottom), None);
+}
+
+#[test_case]
+fn kstack_slots_distinct(boot_info: &BootInfo) {
+    init(boot_info);
+    let a = KernelStack::new().unwrap();
+    let b = KernelStack::new().unwrap();
+    assert_ne!(a.guard_page(), b.guard_page());
+    assert!(a.top() <= b.guard_page().start() || b.top() <= a.guard_page().start());
+}
@@ -0,0 +1,52 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel_test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::hint::black_box;
+
+use kernel_boot_interface::BootInfo;
+use odysseos::{
+    memory::{fault, kstack::KernelStack, paging, palloc},
+    panic,
+};
+
+#[no_mangle]
+pub extern "C" fn _kernel_start() -> ! {
+    test_main();
+    kernel_cpu::hcf();
+}
+
+fn init(boot_info: &BootInfo) {
+    palloc::init(&boot_info.hhdm, &boot_info.memmap);
+    paging::init(boot_info);
+    fault::init(&boot_info.hhdm);
+}
+
+/// Uses a few hundred bytes of stack per call until DEPTH runs out, which it
+/// never does.
+#[inline(never)]
+fn recurse(depth: usize) -> usize {
+    let frame = black_box([depth; 32]);
+    if depth == 0 {
+        return frame[0];
+    }
+    recurse(black_box(depth - 1)) + frame[31]
+}
+
+extern "C" fn overflow() -> ! {
+    recurse(usize::MAX);
+    panic!("The recursion came back");
+}
+
+/// Running off the bottom of a kernel stack ends in a panic naming the
+/// overflow, not in a bare double fault. The test passes by panicking, so it
+/// is the only one in this binary.
+#[test_case]
+fn overflow_is_reported(boot_info: &BootInfo) {
+    init(boot_info);
+    panic::set_panic_hook(|info| kernel_test::expect_panic(info, "kernel stack overflow"));
+    let stack = KernelStack::new().unwrap();
+    unsafe { kernel_cpu::switch_stack(stack.top().as_usize(), overflow) };
+}
@@ -0,0 +1,95 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel_test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use kernel_boot_interface::{memmap::BootMemType, BootInfo};
+use kernel_test::fixtures::fake_memmap;
+use odysseos::memory::{
+    memmap::{self, Memblock, PhysRange},
+    palloc,
+};
+use teensy_std::addr::{PhysAddr, PAGE_SIZE};
+
+#[no_mangle]
+pub extern "C" fn _kernel_start() -> ! {
+    test_main();
+    kernel_cpu::hcf();
+}
+
+fn range(start: usize, end: usize) -> PhysRange {
+    PhysRange::new(PhysAddr::new(start), PhysAddr::new(end))
+}
+
+#[test_case]
+fn memblock_sanitises_memmap(_boot_info: &BootInfo) {
+    let memmap = fake_memmap(&[
+        (0x10_0000, 0x10_0000, BootMemType::Usable),
+        (0x0, 0x9_f000, BootMemType::Usable),
+        // Overlaps and touches the first entry.
+        (0x18_0000, 0x10_0000, BootMemType::Usable),
+        // Punches a hole, not page aligned.
+        (0x15_0800, 0x800, BootMemType::Reserved),
+        (0x30_0000, 0x1000, BootMemType::BootloaderReclaimable),
+        // Not page aligned at either end.
+        (0x40_0010, 0x2000, BootMemType::Usable),
+    ]);
+    let memblock = Memblock::from_memmap(&memmap);
+
+    assert_eq!(
+        memblock.memory(),
+        &[
+            range(0x0, 0x9_f000),
+            range(0x10_0000, 0x15_0000),
+            range(0x15_1000, 0x28_0000),
+            range(0x30_0000, 0x30_1000),
+            range(0x40_1000, 0x40_2000),
+        ]
+    );
+    assert_eq!(memblock.end(), PhysAddr::new(0x40_2000));
+    assert!(memblock.is_reserved(PhysAddr::zero()));
+    assert!(memblock.is_reserved(PhysAddr::new(0x15_0000)));
+    assert!(memblock.is_reserved(PhysAddr::new(0x30_0000)));
+    assert!(!memblock.is_reserved(PhysAddr::new(0x10_0000)));
+}
+
+#[test_case]
+fn memblock_reserve_and_alloc(_boot_info: &BootInfo) {
+    let memmap = fake_memmap(&[
+        (0x0, 0x9_f000, BootMemType::Usable),
+        (0x10_0000, 0x10_0000, BootMemType::Usable),
+    ]);
+    let mut memblock = Memblock::from_memmap(&memmap);
+    memblock.reserve(PhysAddr::new(0x1f_f800), 0x10);
+
+    let addr = memblock.alloc(3 * PAGE_SIZE, 0x1_0000).unwrap();
+    assert_eq!(addr, PhysAddr::new(0x1f_0000));
+    assert!(memblock.is_reserved(addr + 2 * PAGE_SIZE));
+
+    let mut free = [range(0, 0); 3];
+    let mut count = 0;
+    memblock.for_each_free(|range| {
+        free[count] = range;
+        count += 1;
+    });
+    assert_eq!(
+        &free[..count],
+        &[
+            range(0x1000, 0x9_f000),
+            range(0x10_0000, 0x1f_0000),
+            range(0x1f_3000, 0x1f_f000),
+        ]
+    );
+    assert!(memblock.alloc(0x20_0000, PAGE_SIZE).is_none());
+}
+
+#[test_case]
+fn palloc_never_hands_out_reserved_memory(boot_info: &BootInfo) {
+    palloc::init(&boot_info.hhdm, &boot_info.memmap);
+    let memblock = memmap::memblock();
+    for _ in 0..64 {
+        let frame = palloc::get_page().unwrap();
+        assert!(!memblock.is_reserved(frame.start()));
+    }
+}
@@ -0,0 +1,93 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel_test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::format;
+
+use kernel_boot_interface::{memmap::BootMemType, BootInfo};
+use kernel_test::fixtures::fake_memmap;
+use odysseos::memory::{
+    heap,
+    memmap::{Memblock, PhysRange},
+    palloc,
+};
+use teensy_std::addr::PhysAddr;
+
+#[no_mangle]
+pub extern "C" fn _kernel_start() -> ! {
+    test_main();
+    kernel_cpu::hcf();
+}
+
+#[test_case]
+fn memmap_queries(_boot_info: &BootInfo) {
+    let memmap = fake_memmap(&[
+        (0x0, 0x9_f000, BootMemType::Usable),
+        (0x9_f000, 0x1000, BootMemType::Reserved),
+        (0x10_0000, 0x10_0000, BootMemType::Usable),
+        (0x20_0000, 0x1000, BootMemType::BadMemory),
+        (0x20_1000, 0x5_0000, BootMemType::KernelAndModules),
+    ]);
+
+    assert_eq!(memmap.regions(BootMemType::Usable).count(), 2);
+    assert_eq!(memmap.regions(BootMemType::Framebuffer).count(), 0);
+    assert_eq!(memmap.total_bytes(BootMemType::Usable), 0x19_f000);
+    assert_eq!(memmap.total_bytes(BootMemType::AcpiNvs), 0);
+
+    let entry = memmap.find(PhysAddr::new(0x20_0fff)).unwrap();
+    assert_eq!(entry.typ, BootMemType::BadMemory);
+    assert_eq!(
+        memmap.find(PhysAddr::new(0x25_1000 - 1)).unwrap().typ,
+        BootMemType::KernelAndModules
+    );
+    assert!(memmap.find(PhysAddr::new(0x25_1000)).is_none());
+}
+
+#[test_case]
+fn bad_memory_is_never_free(_boot_info: &BootInfo) {
+    let memmap = fake_memmap(&[
+        (0x0, 0x10_0000, BootMemType::Usable),
+        (0x8_0000, 0x2000, BootMemType::BadMemory),
+    ]);
+    let memblock = Memblock::from_memmap(&memmap);
+    assert_eq!(
+        memblock.bad(),
+        &[PhysRange::new(
+            PhysAddr::new(0x8_0000),
+            PhysAddr::new(0x8_2000)
+        )]
+    );
+    assert_eq!(memblock.bad_pages(), 2);
+    assert!(memblock.is_reserved(PhysAddr::new(0x8_1000)));
+}
+
+#[test_case]
+fn boot_memmap_keeps_types(boot_info: &BootInfo) {
+    let memmap = &boot_info.memmap;
+    let kernel = memmap.find(boot_info.kernel_addr.phys_base).unwrap();
+    assert_eq!(kernel.typ, BootMemType::KernelAndModules);
+    let fb = memmap.find(boot_info.frame_buffer.phys_address).unwrap();
+    assert_eq!(fb.typ, BootMemType::Framebuffer);
+    assert!(memmap.total_bytes(BootMemType::Usable) > 0);
+}
+
+#[test_case]
+fn empty_entries_print(boot_info: &BootInfo) {
+    palloc::init(&boot_info.hhdm, &boot_info.memmap);
+    heap::init(&boot_info.hhdm);
+    let memmap = fake_memmap(&[
+        (0x0, 0x0, BootMemType::Reserved),
+        (0x0, 0x1000, BootMemType::Usable),
+    ]);
+    assert_eq!(
+        format!("{}", memmap),
+        "memmap: 2 entries\n\
+         \x20 [0x0000000000000000-0x0000000000000000]          0 KiB reserved\n\
+         \x20 [0x0000000000000000-0x0000000000000fff]          4 KiB usable\n\
+         \x20 total: usable 4 KiB"
+    );
+}
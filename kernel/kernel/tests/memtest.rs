@@ -0,0 +1,121 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel_test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use kernel_boot_interface::{cmdline::BootCmdline, memmap::BootMemType, BootInfo};
+use kernel_test::fixtures::fake_memmap;
+use odysseos::memory::{
+    memmap::{self, Memblock, PhysRange, MAX_MEMBLOCK_RANGES},
+    memtest::{self, Failure},
+    palloc,
+};
+use teensy_std::addr::{PhysAddr, PhysFrame, PAGE_SIZE};
+
+/// Pages `seeded_bad_pages_stay_out_of_palloc` fails, more than the memblock
+/// has ranges for if none of them are adjacent.
+const SEEDED_BAD_PAGES: usize = 2 * MAX_MEMBLOCK_RANGES;
+
+#[no_mangle]
+pub extern "C" fn _kernel_start() -> ! {
+    test_main();
+    kernel_cpu::hcf();
+}
+
+fn range(start: usize, end: usize) -> PhysRange {
+    PhysRange::new(PhysAddr::new(start), PhysAddr::new(end))
+}
+
+#[test_case]
+fn cmdline_options(_boot_info: &BootInfo) {
+    let cmdline = BootCmdline::new("  quiet memtest=3 log=serial memtest=2 ");
+    assert!(cmdline.has("quiet"));
+    assert!(cmdline.has("memtest"));
+    assert!(!cmdline.has("mem"));
+    assert_eq!(cmdline.value("log"), Some("serial"));
+    assert_eq!(cmdline.value("quiet"), None);
+    assert_eq!(cmdline.value("memtest"), Some("2"));
+    assert_eq!(cmdline.options().count(), 4);
+
+    let long = [b'a'; 300];
+    let long = BootCmdline::new(core::str::from_utf8(&long).unwrap());
+    assert_eq!(long.as_str().len(), 256);
+}
+
+#[test_case]
+fn memtest_passes(_boot_info: &BootInfo) {
+    let passes = |cmdline| memtest::passes_requested(&BootCmdline::new(cmdline));
+    assert_eq!(passes(""), None);
+    assert_eq!(passes("quiet"), None);
+    assert_eq!(passes("memtest"), Some(1));
+    assert_eq!(passes("memtest=4"), Some(4));
+    assert_eq!(passes("memtest=0"), None);
+    assert_eq!(passes("memtest=lots"), None);
+}
+
+/// Fails every other page of the free RAM, so that no two bad pages share a
+/// memblock range, and checks palloc keeps all of them out. Runs before any
+/// other test brings palloc up.
+#[test_case]
+fn seeded_bad_pages_stay_out_of_palloc(boot_info: &BootInfo) {
+    memmap::init(&boot_info.memmap);
+    let bad_before = memmap::memblock().bad_pages();
+    let mut left = SEEDED_BAD_PAGES;
+    let report = memtest::run_with(&boot_info.hhdm, 1, |range, fail| {
+        let start = PhysFrame::containing(range.start).number();
+        let end = PhysFrame::containing(range.end).number();
+        for pfn in (start..end).step_by(2).take(left) {
+            let frame = PhysFrame::from_number(pfn);
+            let failure = Failure {
+                test: "seeded",
+                addr: frame.start(),
+                expected: 0,
+                found: !0,
+            };
+            fail(frame, failure);
+            left -= 1;
+        }
+    });
+    assert_eq!(left, 0);
+    assert_eq!(report.bad_pages, SEEDED_BAD_PAGES);
+
+    palloc::init(&boot_info.hhdm, &boot_info.memmap);
+    assert_eq!(palloc::stats().bad_pages, bad_before + SEEDED_BAD_PAGES);
+}
+
+#[test_case]
+fn good_memory_passes(boot_info: &BootInfo) {
+    palloc::init(&boot_info.hhdm, &boot_info.memmap);
+    let frame = palloc::get_pages(4).unwrap();
+    let pages = PhysRange::new(frame.start(), frame.start() + 4 * PAGE_SIZE);
+
+    let mut failures = 0;
+    memtest::test_range(&boot_info.hhdm, pages, |_, _| failures += 1);
+    assert_eq!(failures, 0);
+    palloc::free_pages(frame, 4);
+}
+
+#[test_case]
+fn bad_pages_are_reserved(_boot_info: &BootInfo) {
+    let memmap = fake_memmap(&[(0x0, 0x10_0000, BootMemType::Usable)]);
+    let mut memblock = Memblock::from_memmap(&memmap);
+    memblock.mark_bad(PhysAddr::new(0x5_0008), 8);
+    memblock.mark_bad(PhysAddr::new(0x5_1000), 8);
+
+    assert_eq!(memblock.bad(), &[range(0x5_0000, 0x5_2000)]);
+    assert_eq!(memblock.bad_pages(), 2);
+    assert!(memblock.is_reserved(PhysAddr::new(0x5_1ff8)));
+    assert!(!memblock.is_reserved(PhysAddr::new(0x5_2000)));
+
+    let mut free = [range(0, 0); 2];
+    let mut num_free = 0;
+    memblock.for_each_free(|range| {
+        free[num_free] = range;
+        num_free += 1;
+    });
+    assert_eq!(
+        &free[..num_free],
+        &[range(0x1000, 0x5_0000), range(0x5_2000, 0x10_0000)]
+    );
+}
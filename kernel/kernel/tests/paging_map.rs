@@ -0,0 +1,160 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel_test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use kernel_boot_interface::BootInfo;
+use kernel_paging::{AddressSpace, FrameAllocator, MapError, MapFlags, PageSize};
+use odysseos::memory::{
+    paging::{self, PallocFrameAllocator},
+    palloc,
+};
+use teensy_std::addr::{PhysAddr, PhysFrame};
+
+/// Nothing lives this far up the higher half on boot.
+const TEST_VIRT: usize = 0xffff_c000_1234_5000;
+
+#[no_mangle]
+pub extern "C" fn _kernel_start() -> ! {
+    test_main();
+    kernel_cpu::hcf();
+}
+
+fn init(boot_info: &BootInfo) {
+    palloc::init(&boot_info.hhdm, &boot_info.memmap);
+    paging::init(boot_info);
+}
+
+fn free(frame: usize) {
+    palloc::free_page(PhysFrame::containing(PhysAddr::new(frame)));
+}
+
+/// Hands out at most `left` frames from palloc and counts the ones that come
+/// back.
+struct LimitedFrames {
+    left: usize,
+    freed: usize,
+}
+
+impl FrameAllocator for LimitedFrames {
+    fn allocate_frame(&mut self) -> Option<usize> {
+        self.left = self.left.checked_sub(1)?;
+        PallocFrameAllocator.allocate_frame()
+    }
+
+    fn free_frame(&mut self, frame: usize) {
+        self.freed += 1;
+        PallocFrameAllocator.free_frame(frame);
+    }
+}
+
+#[test_case]
+fn map_and_read_back(boot_info: &BootInfo) {
+    init(boot_info);
+    let frame = palloc::get_page().unwrap().start().as_usize();
+
+    paging::map(TEST_VIRT, frame, PageSize::Size4KiB, MapFlags::kernel_data()).unwrap();
+    assert_eq!(paging::translate(TEST_VIRT + 0x123), Some(frame + 0x123));
+
+    let virt = TEST_VIRT as *mut u8;
+    for i in 0..kernel_paging::PAGE_SIZE_MIN {
+        unsafe { virt.add(i).write_volatile(i as u8) };
+    }
+
+    // The same frame seen through the HHDM must hold what was written.
+    let hhdm = (frame + boot_info.hhdm.base) as *const u8;
+    for i in 0..kernel_paging::PAGE_SIZE_MIN {
+        unsafe { assert_eq!(hhdm.add(i).read_volatile(), i as u8) };
+    }
+
+    let mapping = paging::unmap(TEST_VIRT).unwrap();
+    assert_eq!(mapping.phys, frame);
+    assert_eq!(mapping.size, PageSize::Size4KiB);
+    assert_eq!(mapping.flags, MapFlags::kernel_data());
+    assert_eq!(paging::translate(TEST_VIRT), None);
+    free(frame);
+}
+
+#[test_case]
+fn remap_changes_frame_and_flags(boot_info: &BootInfo) {
+    init(boot_info);
+    let first = palloc::get_page().unwrap().start().as_usize();
+    let second = palloc::get_page().unwrap().start().as_usize();
+
+    paging::map(TEST_VIRT, first, PageSize::Size4KiB, MapFlags::kernel_data()).unwrap();
+    let old = paging::kernel_space()
+        .remap(TEST_VIRT, second, MapFlags::new().no_execute())
+        .unwrap();
+    assert_eq!(old.phys, first);
+
+    let mapping = paging::kernel_space().lookup(TEST_VIRT).unwrap();
+    assert_eq!(mapping.phys, second);
+    assert!(!mapping.flags.writable);
+    assert!(mapping.flags.no_execute);
+    paging::unmap(TEST_VIRT).unwrap();
+    free(first);
+    free(second);
+}
+
+#[test_case]
+fn map_errors(boot_info: &BootInfo) {
+    init(boot_info);
+    let frame = palloc::get_page().unwrap().start().as_usize();
+    let flags = MapFlags::kernel_data();
+
+    assert_eq!(
+        paging::map(TEST_VIRT + 1, frame, PageSize::Size4KiB, flags),
+        Err(MapError::NotAligned)
+    );
+    assert_eq!(
+        paging::map(0x0000_8000_0000_0000, frame, PageSize::Size4KiB, flags),
+        Err(MapError::NonCanonical)
+    );
+    assert_eq!(paging::unmap(TEST_VIRT), Err(MapError::NotMapped));
+
+    paging::map(TEST_VIRT, frame, PageSize::Size4KiB, flags).unwrap();
+    assert_eq!(
+        paging::map(TEST_VIRT, frame, PageSize::Size4KiB, flags),
+        Err(MapError::AlreadyMapped)
+    );
+    paging::unmap(TEST_VIRT).unwrap();
+    free(frame);
+}
+
+#[test_case]
+fn translate_hhdm(boot_info: &BootInfo) {
+    init(boot_info);
+    let frame = palloc::get_page().unwrap().start().as_usize();
+    assert_eq!(paging::translate(frame + boot_info.hhdm.base), Some(frame));
+    free(frame);
+}
+
+/// Running out of frames half way down gives back the tables allocated so
+/// far and leaves nothing mapped.
+#[test_case]
+fn map_out_of_frames_frees_tables(boot_info: &BootInfo) {
+    init(boot_info);
+    let mut space = AddressSpace::empty(&boot_info.hhdm, &mut PallocFrameAllocator).unwrap();
+    let free_before = palloc::free_page_count();
+
+    // An empty space needs a PDPT, a PD and a PT for a 4KiB page.
+    let mut frames = LimitedFrames { left: 2, freed: 0 };
+    assert_eq!(
+        space.map(
+            TEST_VIRT,
+            0,
+            PageSize::Size4KiB,
+            MapFlags::kernel_data(),
+            &mut frames
+        ),
+        Err(MapError::OutOfFrames)
+    );
+    assert_eq!(frames.freed, 2);
+    assert_eq!(palloc::free_page_count(), free_before);
+    let mut tables = 0;
+    space.for_each_table(|_| tables += 1);
+    assert_eq!(tables, 1);
+
+    free(space.root());
+}
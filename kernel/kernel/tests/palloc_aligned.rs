@@ -0,0 +1,58 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel_test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use kernel_boot_interface::BootInfo;
+use odysseos::memory::palloc;
+
+#[no_mangle]
+pub extern "C" fn _kernel_start() -> ! {
+    test_main();
+    kernel_cpu::hcf();
+}
+
+#[test_case]
+fn palloc_huge_frame(boot_info: &BootInfo) {
+    palloc::init(&boot_info.hhdm, &boot_info.memmap);
+    let huge = kernel_paging::PAGE_SIZES[1];
+    let free_before = palloc::free_page_count();
+
+    let frame = palloc::get_sized_frame(huge).unwrap();
+    assert!(frame.start().is_aligned(huge));
+    assert_eq!(
+        palloc::free_page_count(),
+        free_before - kernel_paging::page_min_no(huge)
+    );
+
+    palloc::free_sized_frame(frame, huge);
+    assert_eq!(palloc::free_page_count(), free_before);
+}
+
+#[test_case]
+fn palloc_aligned_run(boot_info: &BootInfo) {
+    palloc::init(&boot_info.hhdm, &boot_info.memmap);
+    let align = 64 * kernel_paging::PAGE_SIZE_MIN;
+    let free_before = palloc::free_page_count();
+
+    // A run shorter than its alignment only takes the pages asked for.
+    let frame = palloc::get_pages_aligned(3, align).unwrap();
+    assert!(frame.start().is_aligned(align));
+    assert_eq!(palloc::free_page_count(), free_before - 3);
+
+    palloc::free_pages(frame, 3);
+    assert_eq!(palloc::free_page_count(), free_before);
+}
+
+#[test_case]
+fn palloc_zero_pages(boot_info: &BootInfo) {
+    palloc::init(&boot_info.hhdm, &boot_info.memmap);
+    let free_before = palloc::free_page_count();
+    assert_eq!(palloc::get_pages(0), None);
+    assert_eq!(
+        palloc::get_pages_aligned(0, kernel_paging::PAGE_SIZE_MIN),
+        None
+    );
+    assert_eq!(palloc::free_page_count(), free_before);
+}
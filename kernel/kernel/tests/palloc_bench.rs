@@ -0,0 +1,97 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel_test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::vec;
+
+use kernel_boot_interface::BootInfo;
+use kernel_log::kprintln;
+use odysseos::memory::{
+    heap,
+    palloc::{self, bitmap_alloc::BitmapAllocator, BuddyAllocator, PageAllocator},
+};
+
+/// Pages both allocators get to manage, 16MiB worth.
+const BENCH_PAGES: usize = 4 * 1024;
+const ROUNDS: usize = 1000;
+
+#[no_mangle]
+pub extern "C" fn _kernel_start() -> ! {
+    test_main();
+    kernel_cpu::hcf();
+}
+
+/// Leaves every other page of [START, START + BENCH_PAGES) allocated, the
+/// worst case for a first fit scan looking for more than one page.
+fn fragment(pool: &mut impl PageAllocator, start: usize) {
+    for _ in 0..BENCH_PAGES {
+        pool.alloc(1).unwrap();
+    }
+    for pfn in (start..(start + BENCH_PAGES)).step_by(2) {
+        pool.free(pfn, 1);
+    }
+}
+
+/// Average cycles an allocation of NUM_PAGES followed by its free takes.
+fn bench(pool: &mut impl PageAllocator, num_pages: usize) -> u64 {
+    let begin = kernel_cpu::rdtsc();
+    for _ in 0..ROUNDS {
+        let pfn = pool.alloc(num_pages).unwrap();
+        pool.free(pfn, num_pages);
+    }
+    (kernel_cpu::rdtsc() - begin) / ROUNDS as u64
+}
+
+#[test_case]
+fn buddy_vs_bitmap(boot_info: &BootInfo) {
+    palloc::init(&boot_info.hhdm, &boot_info.memmap);
+    heap::init(&boot_info.hhdm);
+
+    // Both allocators manage the same naturally aligned chunk of real memory.
+    let chunk = palloc::get_pages(BENCH_PAGES).unwrap();
+    let start = chunk.number();
+    let end = start + BENCH_PAGES;
+
+    let bitmap_words = BENCH_PAGES.div_ceil(metamorphoses::bitmap::WORD_SIZE_BITS);
+    let mut bitmap_meta = vec![0u64; bitmap_words];
+    let mut bitmap = BitmapAllocator::new(&mut bitmap_meta, start, end);
+    bitmap.add_free_range(start, end);
+
+    let mut buddy_meta = vec![0u64; BuddyAllocator::metadata_words(start, end)];
+    let mut buddy = BuddyAllocator::new(&mut buddy_meta, start, end, &boot_info.hhdm);
+    buddy.add_free_range(start, end);
+
+    for num_pages in [1, 8] {
+        let bitmap_cycles = bench(&mut bitmap, num_pages);
+        let buddy_cycles = bench(&mut buddy, num_pages);
+        kprintln!(
+            "\n  {} page(s), empty pool: bitmap {} cycles, buddy {} cycles",
+            num_pages,
+            bitmap_cycles,
+            buddy_cycles
+        );
+    }
+
+    fragment(&mut bitmap, start);
+    fragment(&mut buddy, start);
+    assert_eq!(buddy.free_pages(), BENCH_PAGES / 2);
+
+    // Fragmented, the bitmap has to scan the whole chunk to find the single
+    // run of two free pages left; the buddy allocator only looks at lists.
+    bitmap.free(end - 1, 1);
+    buddy.free(end - 1, 1);
+    let bitmap_cycles = bench(&mut bitmap, 2);
+    let buddy_cycles = bench(&mut buddy, 2);
+    kprintln!(
+        "  2 pages, fragmented pool: bitmap {} cycles, buddy {} cycles",
+        bitmap_cycles,
+        buddy_cycles
+    );
+    assert!(buddy_cycles < bitmap_cycles);
+
+    palloc::free_pages(chunk, BENCH_PAGES);
+}
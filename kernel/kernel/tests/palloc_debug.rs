@@ -0,0 +1,129 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel_test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use kernel_boot_interface::BootInfo;
+use odysseos::memory::palloc::{self, debug::POISON, PallocError};
+use teensy_std::addr::PhysFrame;
+
+#[no_mangle]
+pub extern "C" fn _kernel_start() -> ! {
+    test_main();
+    kernel_cpu::hcf();
+}
+
+fn init(boot_info: &BootInfo) {
+    palloc::init(&boot_info.hhdm, &boot_info.memmap);
+    palloc::enable_debug();
+}
+
+/// The first test, so that no page was handed out before.
+#[test_case]
+fn palloc_debug_never_allocated(boot_info: &BootInfo) {
+    init(boot_info);
+    // The second page of the two page block goes straight back as excess.
+    let frame = palloc::get_pages_aligned(1, 2 * kernel_paging::PAGE_SIZE_MIN).unwrap();
+    assert_eq!(
+        palloc::try_free_pages(frame + 1, 1),
+        Err(PallocError::NeverAllocated(frame + 1))
+    );
+    palloc::free_page(frame);
+    assert_eq!(
+        palloc::try_free_pages(frame, 1),
+        Err(PallocError::DoubleFree(frame))
+    );
+}
+
+#[test_case]
+fn palloc_debug_poisons_free_pages(boot_info: &BootInfo) {
+    init(boot_info);
+    assert_eq!(palloc::check_free_pages(), Ok(()));
+
+    let frame = palloc::get_page().unwrap();
+    let page = boot_info
+        .hhdm
+        .phys_to_virt(frame.start())
+        .as_mut_ptr::<u8>();
+    unsafe { page.add(0x40).write_volatile(0) };
+    palloc::free_page(frame);
+    assert_eq!(unsafe { page.add(0x40).read_volatile() }, POISON);
+}
+
+#[test_case]
+fn palloc_debug_double_free(boot_info: &BootInfo) {
+    init(boot_info);
+    let frame = palloc::get_pages(2).unwrap();
+    let free_before = palloc::free_page_count();
+
+    assert_eq!(palloc::try_free_pages(frame, 2), Ok(()));
+    assert_eq!(
+        palloc::try_free_pages(frame + 1, 1),
+        Err(PallocError::DoubleFree(frame + 1))
+    );
+    assert_eq!(palloc::free_page_count(), free_before + 2);
+}
+
+#[test_case]
+fn palloc_debug_not_owned(boot_info: &BootInfo) {
+    init(boot_info);
+    // Frame 0 is never handed to the pool.
+    let zero = PhysFrame::from_number(0);
+    assert_eq!(
+        palloc::try_free_pages(zero, 1),
+        Err(PallocError::NotOwned(zero))
+    );
+}
+
+#[test_case]
+fn palloc_debug_use_after_free(boot_info: &BootInfo) {
+    init(boot_info);
+    let frame = palloc::get_page().unwrap();
+    palloc::free_page(frame);
+
+    let page = boot_info
+        .hhdm
+        .phys_to_virt(frame.start())
+        .as_mut_ptr::<u8>();
+    unsafe { page.add(0x100).write_volatile(0) };
+    match palloc::check_free_pages() {
+        Err(PallocError::UseAfterFree {
+            frame: bad, offset, ..
+        }) => {
+            assert_eq!(bad, frame);
+            assert_eq!(offset, 0x100);
+        }
+        res => panic!("use after free not caught: {:?}", res),
+    }
+
+    unsafe { page.add(0x100).write_volatile(POISON) };
+    assert_eq!(palloc::check_free_pages(), Ok(()));
+}
+
+/// Only the first page of a free block holds the free list links, so a write
+/// to the start of any other page is caught.
+#[test_case]
+fn palloc_debug_use_after_free_at_page_start(boot_info: &BootInfo) {
+    init(boot_info);
+    let frame = palloc::get_pages(2).unwrap();
+    palloc::free_pages(frame, 2);
+
+    let second = boot_info
+        .hhdm
+        .phys_to_virt((frame + 1).start())
+        .as_mut_ptr::<u8>();
+    unsafe { second.write_volatile(0) };
+    match palloc::check_free_pages() {
+        Err(PallocError::UseAfterFree {
+            frame: bad, offset, ..
+        }) => {
+            assert_eq!(bad, frame + 1);
+            assert_eq!(offset, 0);
+        }
+        res => panic!("use after free not caught: {:?}", res),
+    }
+
+    unsafe { second.write_volatile(POISON) };
+    assert_eq!(palloc::check_free_pages(), Ok(()));
+}
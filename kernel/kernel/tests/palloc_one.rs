@@ -4,11 +4,9 @@
 #![test_runner(kernel_test::test_runner)]
 #![reexport_test_harness_main = "test_main"]
 
-use core::num::NonZeroUsize;
-
 use kernel_boot_interface::{memmap::BootMemType, BootInfo};
-use odysseos::memory::{self, memmap::get_addr_entry, palloc::free_page};
-use teensy_std::addr::Addr;
+use odysseos::memory::{self, memmap::get_addr_entry, palloc::PallocError};
+use teensy_std::addr::{PhysFrame, VirtAddr};
 
 #[no_mangle]
 pub extern "C" fn _kernel_start() -> ! {
@@ -19,27 +17,39 @@ pub extern "C" fn _kernel_start() -> ! {
 #[test_case]
 fn palloc_one_and_write(boot_info: &BootInfo) {
     memory::palloc::init(&boot_info.hhdm, &boot_info.memmap);
-    let ppage = memory::palloc::get_page();
-    assert!(get_addr_entry(&boot_info.memmap, &ppage).typ == BootMemType::Usable);
+    let ppage = memory::palloc::get_page().unwrap();
+    assert!(get_addr_entry(&boot_info.memmap, ppage.start()).typ == BootMemType::Usable);
 
-    let vpage = ppage
-        .map_addr(|addr| addr.checked_add(boot_info.hhdm.base).unwrap())
-        .as_ptr::<u8>()
-        .unwrap();
+    let vpage = boot_info
+        .hhdm
+        .phys_to_virt(ppage.start())
+        .as_mut_ptr::<u8>();
     for i in 0..(kernel_paging::PAGE_SIZE_MIN as isize) {
         unsafe {
-            *vpage.as_ptr().offset(i) = 0xCC;
+            *vpage.offset(i) = 0xCC;
         }
     }
     for i in 0..(kernel_paging::PAGE_SIZE_MIN as isize) {
         unsafe {
-            assert_eq!(*vpage.as_ptr().offset(i), 0xCC);
+            assert_eq!(*vpage.offset(i), 0xCC);
         }
     }
 
-    let ppage: Addr = Some(vpage).into();
-    memory::palloc::free_page(
-        ppage.map_addr(|addr| NonZeroUsize::new(usize::from(addr) - boot_info.hhdm.base).unwrap()),
+    let ppage = boot_info
+        .hhdm
+        .virt_to_phys(VirtAddr::from_ptr(vpage))
+        .unwrap();
+    memory::palloc::free_page(PhysFrame::from_start(ppage).unwrap());
+}
+
+/// Frees past the end of the pool are refused even outside debug mode.
+#[test_case]
+fn palloc_free_past_the_pool(boot_info: &BootInfo) {
+    memory::palloc::init(&boot_info.hhdm, &boot_info.memmap);
+    let far = PhysFrame::from_number(1 << 40);
+    assert_eq!(
+        memory::palloc::try_free_pages(far, 1),
+        Err(PallocError::NotOwned(far))
     );
 }
 
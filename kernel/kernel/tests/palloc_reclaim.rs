@@ -0,0 +1,70 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel_test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use kernel_boot_interface::{memmap::BootMemType, BootInfo};
+use odysseos::{
+    acpi,
+    memory::{heap, paging, palloc, reclaim},
+};
+
+#[no_mangle]
+pub extern "C" fn _kernel_start() -> ! {
+    test_main();
+    kernel_cpu::hcf();
+}
+
+#[test_case]
+fn reclaim_bootloader_memory(boot_info: &BootInfo) {
+    palloc::init(&boot_info.hhdm, &boot_info.memmap);
+    paging::init(boot_info);
+    heap::init(&boot_info.hhdm);
+
+    // The heap's bookkeeping of the pages in use comes out of palloc too, so
+    // warm it up before taking the count.
+    drop(Vec::<usize>::with_capacity(4096));
+    let before = palloc::free_page_count();
+    let reclaimed = reclaim::reclaim_bootloader_memory();
+    let after = palloc::free_page_count();
+
+    assert!(reclaimed > 0);
+    assert_eq!(after - before, reclaimed);
+    // Reclaiming twice must not hand out the same pages twice.
+    assert_eq!(reclaim::reclaim_bootloader_memory(), 0);
+    assert_eq!(palloc::free_page_count(), after);
+
+    // BootInfo was copied out of limine's responses and is still intact.
+    assert!(boot_info
+        .memmap
+        .iter()
+        .any(|entry| entry.typ == BootMemType::Usable));
+}
+
+#[test_case]
+fn reclaim_acpi_memory(boot_info: &BootInfo) {
+    palloc::init(&boot_info.hhdm, &boot_info.memmap);
+    paging::init(boot_info);
+    heap::init(&boot_info.hhdm);
+    acpi::init(boot_info).unwrap();
+    assert!(acpi::find_table(b"APIC").is_some());
+
+    let expected: usize = boot_info
+        .memmap
+        .regions(BootMemType::AcpiReclaimable)
+        .map(|entry| entry.end_frame() - entry.first_frame())
+        .sum();
+    let before = palloc::free_page_count();
+    assert_eq!(reclaim::reclaim_acpi_memory(boot_info), expected);
+    assert_eq!(palloc::free_page_count() - before, expected);
+    assert_eq!(reclaim::reclaim_acpi_memory(boot_info), 0);
+
+    // The tables may live in the memory just reclaimed.
+    assert!(acpi::find_table(b"APIC").is_none());
+    assert_eq!(acpi::signatures().count(), 0);
+}
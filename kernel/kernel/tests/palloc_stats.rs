@@ -0,0 +1,48 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel_test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use kernel_boot_interface::{memmap::BootMemType, BootInfo};
+use odysseos::memory::palloc;
+
+#[no_mangle]
+pub extern "C" fn _kernel_start() -> ! {
+    test_main();
+    kernel_cpu::hcf();
+}
+
+#[test_case]
+fn palloc_stats_consistent(boot_info: &BootInfo) {
+    palloc::init(&boot_info.hhdm, &boot_info.memmap);
+    let stats = palloc::stats();
+
+    assert_eq!(stats.free_pages, palloc::free_page_count());
+    assert_eq!(stats.used_pages, stats.managed_pages - stats.free_pages);
+    assert!(stats.managed_pages <= stats.memmap_pages_of(BootMemType::Usable));
+    assert!(stats.largest_free_run <= stats.free_pages);
+    let in_blocks: usize = stats
+        .free_blocks
+        .iter()
+        .enumerate()
+        .map(|(order, count)| count << order)
+        .sum();
+    assert_eq!(in_blocks, stats.free_pages);
+    palloc::dump_stats();
+}
+
+#[test_case]
+fn palloc_stats_track_allocations(boot_info: &BootInfo) {
+    palloc::init(&boot_info.hhdm, &boot_info.memmap);
+    let before = palloc::stats();
+
+    let frame = palloc::get_pages(5).unwrap();
+    let during = palloc::stats();
+    assert_eq!(during.used_pages, before.used_pages + 5);
+    assert_eq!(during.free_pages, before.free_pages - 5);
+    assert_eq!(during.managed_pages, before.managed_pages);
+
+    palloc::free_pages(frame, 5);
+    assert_eq!(palloc::stats().used_pages, before.used_pages);
+}
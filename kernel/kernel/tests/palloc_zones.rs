@@ -0,0 +1,105 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel_test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use kernel_boot_interface::BootInfo;
+use odysseos::memory::{
+    heap,
+    palloc::{self, Zone},
+};
+use teensy_std::addr::PhysFrame;
+
+#[no_mangle]
+pub extern "C" fn _kernel_start() -> ! {
+    test_main();
+    kernel_cpu::hcf();
+}
+
+fn init(boot_info: &BootInfo) {
+    palloc::init(&boot_info.hhdm, &boot_info.memmap);
+    heap::init(&boot_info.hhdm);
+}
+
+fn in_zone(frame: PhysFrame, zone: Zone) -> bool {
+    (zone.start_pfn()..zone.end_pfn()).contains(&frame.number())
+}
+
+#[test_case]
+fn zone_bounds(_boot_info: &BootInfo) {
+    assert_eq!(Zone::of(0), Zone::Dma);
+    assert_eq!(Zone::of(Zone::Dma.end_pfn() - 1), Zone::Dma);
+    assert_eq!(Zone::of(Zone::Dma.end_pfn()), Zone::Dma32);
+    assert_eq!(Zone::of(Zone::Dma32.end_pfn()), Zone::Normal);
+    assert_eq!(Zone::Dma.fallbacks(), &[Zone::Dma]);
+    assert_eq!(Zone::Normal.fallbacks()[0], Zone::Normal);
+}
+
+#[test_case]
+fn zone_constrained_allocation(boot_info: &BootInfo) {
+    init(boot_info);
+    let dma = palloc::get_page_in(Zone::Dma).unwrap();
+    assert!(in_zone(dma, Zone::Dma));
+
+    let dma32 = palloc::get_pages_in(Zone::Dma32, 4).unwrap();
+    assert!(in_zone(dma32, Zone::Dma) || in_zone(dma32, Zone::Dma32));
+
+    let aligned = palloc::get_pages_aligned_in(Zone::Dma32, 2, 16 * 4096).unwrap();
+    assert!(aligned.start().is_aligned(16 * 4096));
+    assert!(!in_zone(aligned, Zone::Normal));
+
+    palloc::free_page(dma);
+    palloc::free_pages(dma32, 4);
+    palloc::free_pages(aligned, 2);
+}
+
+#[test_case]
+fn normal_falls_back(boot_info: &BootInfo) {
+    init(boot_info);
+    let stats = palloc::stats();
+    let frame = palloc::get_page().unwrap();
+    if stats.zone(Zone::Normal).free_pages > 0 {
+        assert!(in_zone(frame, Zone::Normal));
+    } else {
+        // Machines with less than 4GiB have no Normal zone at all.
+        assert!(!in_zone(frame, Zone::Normal));
+    }
+    palloc::free_page(frame);
+}
+
+#[test_case]
+fn dma_zone_runs_dry(boot_info: &BootInfo) {
+    init(boot_info);
+    let free = palloc::stats().zone(Zone::Dma).free_pages;
+    let frames: Vec<PhysFrame> = (0..free)
+        .map(|_| palloc::get_page_in(Zone::Dma).unwrap())
+        .collect();
+    assert!(frames.iter().all(|&frame| in_zone(frame, Zone::Dma)));
+    // The DMA zone never borrows from the zones above it.
+    assert_eq!(palloc::get_page_in(Zone::Dma), None);
+    assert_eq!(palloc::stats().zone(Zone::Dma).free_pages, 0);
+
+    frames.into_iter().for_each(palloc::free_page);
+    assert_eq!(palloc::stats().zone(Zone::Dma).free_pages, free);
+}
+
+#[test_case]
+fn zone_stats_add_up(boot_info: &BootInfo) {
+    init(boot_info);
+    let stats = palloc::stats();
+    let managed: usize = Zone::ALL
+        .iter()
+        .map(|&zone| stats.zone(zone).managed_pages)
+        .sum();
+    let free: usize = Zone::ALL
+        .iter()
+        .map(|&zone| stats.zone(zone).free_pages)
+        .sum();
+    assert_eq!(managed, stats.managed_pages);
+    assert_eq!(free, stats.free_pages);
+}
@@ -0,0 +1,140 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel_test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::{
+    mem::MaybeUninit,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use kernel_boot_interface::BootInfo;
+use odysseos::memory::{
+    heap, palloc,
+    slab::{self, SlabCache},
+};
+
+struct Inode {
+    number: u64,
+    links: u32,
+    data: [u8; 100],
+}
+
+fn inode_ctor(inode: &mut MaybeUninit<Inode>) {
+    inode.write(Inode {
+        number: 0,
+        links: 1,
+        data: [0xAB; 100],
+    });
+}
+
+static INODES: SlabCache<Inode> = SlabCache::new("inode", Some(inode_ctor));
+static PAGES: SlabCache<[u8; 3000]> = SlabCache::new("big", None);
+
+static CONSTRUCTED: AtomicUsize = AtomicUsize::new(0);
+
+fn counted_ctor(obj: &mut MaybeUninit<u64>) {
+    obj.write(CONSTRUCTED.fetch_add(1, Ordering::Relaxed) as u64);
+}
+
+static COUNTED: SlabCache<u64> = SlabCache::new("counted", Some(counted_ctor));
+
+#[no_mangle]
+pub extern "C" fn _kernel_start() -> ! {
+    test_main();
+    kernel_cpu::hcf();
+}
+
+fn init(boot_info: &BootInfo) {
+    palloc::init(&boot_info.hhdm, &boot_info.memmap);
+    heap::init(&boot_info.hhdm);
+    slab::init(&boot_info.hhdm);
+}
+
+#[test_case]
+fn slab_alloc_constructs(boot_info: &BootInfo) {
+    init(boot_info);
+    let inode = INODES.alloc().unwrap();
+    unsafe {
+        assert_eq!(inode.as_ref().links, 1);
+        assert!(inode.as_ref().data.iter().all(|b| *b == 0xAB));
+        INODES.free(inode);
+    }
+    assert_eq!(INODES.stats().active_objs, 0);
+}
+
+#[test_case]
+fn slab_objects_are_distinct(boot_info: &BootInfo) {
+    init(boot_info);
+    let per_slab = INODES.stats().objs_per_slab;
+    let count = 3 * per_slab + 1;
+
+    let mut inodes = Vec::new();
+    for i in 0..count {
+        let mut inode = INODES.alloc().unwrap();
+        assert_eq!(inode.as_ptr() as usize % core::mem::align_of::<Inode>(), 0);
+        unsafe { inode.as_mut().number = i as u64 };
+        inodes.push(inode);
+    }
+    for (i, inode) in inodes.iter().enumerate() {
+        assert_eq!(unsafe { inode.as_ref().number }, i as u64);
+    }
+
+    let stats = INODES.stats();
+    assert_eq!(stats.active_objs, count);
+    assert!(stats.slabs >= 4);
+    assert_eq!(stats.allocs - stats.frees, count);
+
+    for mut inode in inodes {
+        unsafe {
+            inode.as_mut().number = 0;
+            INODES.free(inode);
+        }
+    }
+    // All but one empty slab went back to palloc on the way.
+    let stats = INODES.stats();
+    assert_eq!(stats.active_objs, 0);
+    assert_eq!(stats.slabs, stats.empty_slabs);
+    assert!(stats.slabs <= 1);
+}
+
+#[test_case]
+fn slab_constructs_once_per_object(boot_info: &BootInfo) {
+    init(boot_info);
+    let obj = COUNTED.alloc().unwrap();
+    let first = unsafe { *obj.as_ref() };
+    unsafe { COUNTED.free(obj) };
+    let constructed = CONSTRUCTED.load(Ordering::Relaxed);
+    assert_eq!(constructed, COUNTED.stats().objs_per_slab);
+
+    // Freed objects come back as the constructor left them, without
+    // running it again.
+    for _ in 0..constructed {
+        let obj = COUNTED.alloc().unwrap();
+        assert!(unsafe { *obj.as_ref() } < constructed as u64);
+        unsafe { COUNTED.free(obj) };
+    }
+    let obj = COUNTED.alloc().unwrap();
+    assert_eq!(unsafe { *obj.as_ref() }, first);
+    unsafe { COUNTED.free(obj) };
+    assert_eq!(CONSTRUCTED.load(Ordering::Relaxed), constructed);
+}
+
+#[test_case]
+fn slab_shrink(boot_info: &BootInfo) {
+    init(boot_info);
+    let page = PAGES.alloc().unwrap();
+    assert!(PAGES.stats().slab_pages > 1);
+    unsafe { PAGES.free(page) };
+
+    assert_eq!(PAGES.stats().empty_slabs, 1);
+    assert_eq!(PAGES.shrink(), 1);
+    let stats = PAGES.stats();
+    assert_eq!(stats.slabs, 0);
+    assert_eq!(stats.slabs_released, 1);
+    kernel_log::kprintln!("\n  {}", stats);
+}
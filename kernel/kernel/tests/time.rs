@@ -0,0 +1,170 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel_test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use kernel_boot_interface::BootInfo;
+use kernel_log::kprint;
+use kernel_test::fixtures::fake_acpi_table;
+use kernel_timer::{HpetError, HpetTable};
+use odysseos::{
+    acpi, interrupts,
+    memory::{fault, heap, paging, palloc},
+    time::{
+        self,
+        tick::{self, TickMode, TimeError},
+        NSEC_PER_USEC,
+    },
+};
+use teensy_std::addr::PhysAddr;
+
+static FIRED: AtomicUsize = AtomicUsize::new(0);
+
+#[no_mangle]
+pub extern "C" fn _kernel_start() -> ! {
+    test_main();
+    kernel_cpu::hcf();
+}
+
+fn init(boot_info: &BootInfo) {
+    palloc::init(&boot_info.hhdm, &boot_info.memmap);
+    paging::init(boot_info);
+    heap::init(&boot_info.hhdm);
+    fault::init(&boot_info.hhdm);
+    kernel_cpu::init_idt();
+    acpi::init(boot_info).unwrap();
+    interrupts::init();
+    time::init();
+}
+
+fn count_tick(_cookie: usize) {
+    FIRED.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Lets ticks in until FIRED reaches COUNT, for at most a second.
+fn wait_for_ticks(count: usize) {
+    let deadline = time::now_ns() + 1_000_000 * 1_000;
+    kernel_cpu::enable_interrupts();
+    while FIRED.load(Ordering::SeqCst) < count && time::now_ns() < deadline {
+        core::hint::spin_loop();
+    }
+    kernel_cpu::disable_interrupts();
+}
+
+/// An HPET table as QEMU's looks, with the registers at 0xfed0_0000.
+fn fake_hpet_table() -> [u8; 56] {
+    let mut table = fake_acpi_table(b"HPET");
+    table[44..52].copy_from_slice(&0xfed0_0000u64.to_le_bytes());
+    table[53..55].copy_from_slice(&128u16.to_le_bytes());
+    table
+}
+
+#[test_case]
+fn hpet_table_parses(_boot_info: &BootInfo) {
+    let mut table = fake_hpet_table();
+    let hpet = HpetTable::parse(&table).unwrap();
+    assert_eq!(hpet.address, PhysAddr::new(0xfed0_0000));
+    assert_eq!(hpet.number, 0);
+    assert_eq!(hpet.min_tick, 128);
+
+    assert_eq!(HpetTable::parse(&table[..50]), Err(HpetError::Truncated));
+    table[40] = 1;
+    assert_eq!(HpetTable::parse(&table), Err(HpetError::NotMemoryMapped));
+    table[0] = b'X';
+    assert_eq!(HpetTable::parse(&table), Err(HpetError::BadSignature));
+}
+
+#[test_case]
+fn timers_are_calibrated(boot_info: &BootInfo) {
+    init(boot_info);
+    assert!(time::clock_source().is_some());
+    assert!(time::tsc_frequency().unwrap() > 0);
+    assert!(time::lapic_timer_frequency().unwrap() > 0);
+}
+
+#[test_case]
+fn clock_is_monotonic(boot_info: &BootInfo) {
+    init(boot_info);
+    let mut last = time::now_ns();
+    for _ in 0..1000 {
+        let now = time::now_ns();
+        assert!(now >= last);
+        last = now;
+    }
+}
+
+#[test_case]
+fn udelay_waits(boot_info: &BootInfo) {
+    init(boot_info);
+    let start = time::now_ns();
+    time::udelay(2_000);
+    assert!(time::now_ns() - start >= 2_000 * NSEC_PER_USEC);
+}
+
+/// Without an HPET the clock was calibrated against the PIT, so agreeing
+/// with it proves nothing and the test is skipped.
+#[test_case]
+fn clock_agrees_with_the_pit(boot_info: &BootInfo) {
+    init(boot_info);
+    if !time::has_hpet() {
+        kprint!("no HPET, skipped ");
+        return;
+    }
+    let start = time::now_ns();
+    kernel_timer::pit_delay_us(20_000);
+    let elapsed = time::now_ns() - start;
+    // Generous bounds, emulators are not known for their timing.
+    assert!(elapsed >= 18_000 * NSEC_PER_USEC, "{} ns", elapsed);
+    assert!(elapsed < 100_000 * NSEC_PER_USEC, "{} ns", elapsed);
+}
+
+#[test_case]
+fn periodic_tick_repeats(boot_info: &BootInfo) {
+    init(boot_info);
+    let before = FIRED.load(Ordering::SeqCst);
+    let ticks = tick::tick_count();
+    tick::start_tick(TickMode::Periodic, 1_000 * NSEC_PER_USEC, count_tick, 0).unwrap();
+    assert_eq!(
+        tick::start_tick(TickMode::OneShot, 1_000, count_tick, 0),
+        Err(TimeError::Busy)
+    );
+    wait_for_ticks(before + 5);
+    tick::stop_tick();
+    assert!(FIRED.load(Ordering::SeqCst) >= before + 5);
+    assert!(tick::tick_count() >= ticks + 5);
+}
+
+#[test_case]
+fn oneshot_tick_fires_once(boot_info: &BootInfo) {
+    init(boot_info);
+    let before = FIRED.load(Ordering::SeqCst);
+    tick::start_tick(TickMode::OneShot, 2_000 * NSEC_PER_USEC, count_tick, 0).unwrap();
+    wait_for_ticks(before + 1);
+    assert_eq!(FIRED.load(Ordering::SeqCst), before + 1);
+
+    kernel_cpu::enable_interrupts();
+    time::udelay(10_000);
+    kernel_cpu::disable_interrupts();
+    assert_eq!(FIRED.load(Ordering::SeqCst), before + 1);
+
+    // A one-shot tick that fired can be started again.
+    tick::start_tick(TickMode::OneShot, 1_000 * NSEC_PER_USEC, count_tick, 0).unwrap();
+    wait_for_ticks(before + 2);
+    assert_eq!(FIRED.load(Ordering::SeqCst), before + 2);
+}
+
+#[test_case]
+fn bad_periods_are_refused(boot_info: &BootInfo) {
+    init(boot_info);
+    assert_eq!(
+        tick::start_tick(TickMode::OneShot, 0, count_tick, 0),
+        Err(TimeError::BadPeriod(0))
+    );
+    assert_eq!(
+        tick::start_tick(TickMode::Periodic, u64::MAX, count_tick, 0),
+        Err(TimeError::BadPeriod(u64::MAX))
+    );
+}
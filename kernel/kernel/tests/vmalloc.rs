@@ -0,0 +1,79 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(kernel_test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use kernel_boot_interface::BootInfo;
+use odysseos::memory::{
+    fault, heap, paging, palloc,
+    vmalloc::{self, vfree, vmalloc},
+};
+use teensy_std::addr::PAGE_SIZE;
+
+#[no_mangle]
+pub extern "C" fn _kernel_start() -> ! {
+    test_main();
+    kernel_cpu::hcf();
+}
+
+fn init(boot_info: &BootInfo) {
+    palloc::init(&boot_info.hhdm, &boot_info.memmap);
+    paging::init(boot_info);
+    heap::init(&boot_info.hhdm);
+    fault::init(&boot_info.hhdm);
+}
+
+#[test_case]
+fn vmalloc_maps_whole_buffer(boot_info: &BootInfo) {
+    init(boot_info);
+    let size = 3 * PAGE_SIZE + 100;
+    let buf = vmalloc(size).unwrap();
+    assert!(vmalloc::is_vmalloc_addr(buf));
+    assert!(buf.is_aligned(PAGE_SIZE));
+
+    for page in 0..4 {
+        assert!(paging::translate((buf + page * PAGE_SIZE).as_usize()).is_some());
+    }
+    // The guard page after the buffer stays unmapped.
+    assert_eq!(paging::translate((buf + 4 * PAGE_SIZE).as_usize()), None);
+
+    let bytes = buf.as_mut_ptr::<u8>();
+    for i in 0..size {
+        unsafe { bytes.add(i).write_volatile(i as u8) };
+    }
+    for i in 0..size {
+        assert_eq!(unsafe { bytes.add(i).read_volatile() }, i as u8);
+    }
+
+    unsafe { vfree(buf) };
+    assert_eq!(paging::translate(buf.as_usize()), None);
+}
+
+#[test_case]
+fn vmalloc_gives_everything_back(boot_info: &BootInfo) {
+    init(boot_info);
+    // The first allocation may pull in page tables that stay around.
+    unsafe { vfree(vmalloc(PAGE_SIZE).unwrap()) };
+    let free_before = palloc::free_page_count();
+
+    let a = vmalloc(8 * PAGE_SIZE).unwrap();
+    let b = vmalloc(PAGE_SIZE).unwrap();
+    assert!(b >= a + 9 * PAGE_SIZE || a >= b + 2 * PAGE_SIZE);
+    unsafe {
+        vfree(a);
+        vfree(b);
+    }
+    assert_eq!(palloc::free_page_count(), free_before);
+
+    // The virtual range is reused once both ranges merged back together.
+    let c = vmalloc(9 * PAGE_SIZE).unwrap();
+    assert_eq!(c, a.min(b));
+    unsafe { vfree(c) };
+}
+
+#[test_case]
+fn vmalloc_zero_size(boot_info: &BootInfo) {
+    init(boot_info);
+    assert_eq!(vmalloc(0), None);
+}
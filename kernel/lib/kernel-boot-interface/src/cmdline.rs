@@ -0,0 +1,50 @@
+/// Longest command line kept, in bytes. The rest is dropped.
+pub const MAX_CMDLINE_LEN: usize = 256;
+
+/// The kernel command line: whitespace separated options, each either
+/// `name` or `name=value`.
+#[derive(Clone, Copy)]
+pub struct BootCmdline {
+    bytes: [u8; MAX_CMDLINE_LEN],
+    len: usize,
+}
+
+impl BootCmdline {
+    pub fn new(cmdline: &str) -> Self {
+        let mut len = cmdline.len().min(MAX_CMDLINE_LEN);
+        while !cmdline.is_char_boundary(len) {
+            len -= 1;
+        }
+        let mut bytes = [0; MAX_CMDLINE_LEN];
+        bytes[..len].copy_from_slice(&cmdline.as_bytes()[..len]);
+        Self { bytes, len }
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only ever copied from a str and cut at a char boundary.
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap()
+    }
+
+    /// Every option as its name and value, if it has one.
+    pub fn options(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.as_str()
+            .split_whitespace()
+            .map(|option| match option.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (option, None),
+            })
+    }
+
+    /// Whether the option NAME is given, with or without a value.
+    pub fn has(&self, name: &str) -> bool {
+        self.options().any(|(option, _)| option == name)
+    }
+
+    /// The value of the last `NAME=value` option.
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.options()
+            .filter(|&(option, _)| option == name)
+            .filter_map(|(_, value)| value)
+            .last()
+    }
+}
@@ -1,5 +1,7 @@
+use teensy_std::addr::PhysAddr;
+
 pub struct BootFrameBuf {
-    pub phys_address: usize,
+    pub phys_address: PhysAddr,
     pub width: u64,
     pub height: u64,
     pub pitch: u64,
@@ -1,9 +1,27 @@
+use teensy_std::addr::{PhysAddr, VirtAddr};
+
+#[derive(Clone, Copy)]
 pub struct BootHhdm {
     pub base: usize,
+    /// Bytes of physical memory the HHDM maps, from address zero.
+    pub len: usize,
 }
 
 impl BootHhdm {
     pub const fn max_len(&self) -> usize {
-        usize::MAX - self.base
+        self.len
+    }
+
+    /// Where PHYS can be accessed through the HHDM.
+    pub const fn phys_to_virt(&self, phys: PhysAddr) -> VirtAddr {
+        VirtAddr::new(phys.as_usize() + self.base)
+    }
+
+    /// The physical address behind VIRT, or None unless it lies in the HHDM.
+    pub fn virt_to_phys(&self, virt: VirtAddr) -> Option<PhysAddr> {
+        virt.as_usize()
+            .checked_sub(self.base)
+            .filter(|&phys| phys < self.len)
+            .map(PhysAddr::new)
     }
 }
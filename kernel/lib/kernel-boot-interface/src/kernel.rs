@@ -0,0 +1,18 @@
+use teensy_std::addr::{PhysAddr, VirtAddr};
+
+/// Where the bootloader put the kernel image. With KASLR the virtual base
+/// changes from boot to boot.
+#[derive(Clone, Copy)]
+pub struct BootKernelAddr {
+    pub phys_base: PhysAddr,
+    pub virt_base: VirtAddr,
+    /// Where the image is linked to start.
+    pub link_base: VirtAddr,
+}
+
+impl BootKernelAddr {
+    /// How far the image was moved from where it was linked.
+    pub fn slide(&self) -> usize {
+        self.virt_base - self.link_base
+    }
+}
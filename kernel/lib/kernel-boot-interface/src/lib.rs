@@ -1,11 +1,17 @@
 #![no_std]
 
+pub mod cmdline;
 pub mod framebuf;
 pub mod hhdm;
+pub mod kernel;
 pub mod memmap;
 
 pub struct BootInfo {
     pub memmap: memmap::Memmap,
     pub frame_buffer: framebuf::BootFrameBuf,
     pub hhdm: hhdm::BootHhdm,
+    pub kernel_addr: kernel::BootKernelAddr,
+    pub cmdline: cmdline::BootCmdline,
+    /// Physical address of the ACPI RSDP, if the firmware has one.
+    pub rsdp: Option<teensy_std::addr::PhysAddr>,
 }
@@ -1,17 +1,59 @@
+use core::fmt;
+
+use teensy_std::addr::{PhysAddr, PhysFrame, PAGE_SIZE};
+
 pub const MAX_MEM_REGIONS: usize = 256;
 
-#[derive(PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum BootMemType {
     Usable,
     Reserved,
     AcpiReclaimable,
     AcpiNvs,
     BootloaderReclaimable,
+    /// RAM the firmware found faulty.
+    BadMemory,
+    /// The kernel image and the modules loaded with it.
+    KernelAndModules,
+    Framebuffer,
+}
+
+impl BootMemType {
+    /// Every variant, in declaration order, so `typ as usize` indexes it.
+    pub const ALL: [BootMemType; 8] = [
+        BootMemType::Usable,
+        BootMemType::Reserved,
+        BootMemType::AcpiReclaimable,
+        BootMemType::AcpiNvs,
+        BootMemType::BootloaderReclaimable,
+        BootMemType::BadMemory,
+        BootMemType::KernelAndModules,
+        BootMemType::Framebuffer,
+    ];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            BootMemType::Usable => "usable",
+            BootMemType::Reserved => "reserved",
+            BootMemType::AcpiReclaimable => "ACPI reclaimable",
+            BootMemType::AcpiNvs => "ACPI NVS",
+            BootMemType::BootloaderReclaimable => "bootloader reclaimable",
+            BootMemType::BadMemory => "bad memory",
+            BootMemType::KernelAndModules => "kernel and modules",
+            BootMemType::Framebuffer => "framebuffer",
+        }
+    }
+}
+
+impl fmt::Display for BootMemType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
 }
 
 #[derive(Clone, Copy)]
 pub struct MemmapEntry {
-    pub base: usize,
+    pub base: PhysAddr,
     pub len: usize,
     pub typ: BootMemType,
 }
@@ -23,15 +65,77 @@ pub struct Memmap {
 
 impl MemmapEntry {
     /// Returns range exclusive
-    pub fn end(&self) -> usize {
+    pub fn end(&self) -> PhysAddr {
         self.base + self.len
     }
+
+    pub fn contains(&self, addr: PhysAddr) -> bool {
+        self.base <= addr && addr < self.end()
+    }
+
+    /// First frame lying wholly inside the entry.
+    pub fn first_frame(&self) -> PhysFrame {
+        PhysFrame::containing(self.base.align_up(PAGE_SIZE))
+    }
+
+    /// Frame following the last one lying wholly inside the entry.
+    pub fn end_frame(&self) -> PhysFrame {
+        PhysFrame::containing(self.end())
+    }
 }
 
 impl Memmap {
     pub fn iter(&self) -> core::slice::Iter<MemmapEntry> {
         self.entries[0..self.entry_count].iter()
     }
+
+    /// The entries of type TYP, in memory map order.
+    pub fn regions(&self, typ: BootMemType) -> impl Iterator<Item = &MemmapEntry> {
+        self.iter().filter(move |entry| entry.typ == typ)
+    }
+
+    /// Bytes the entries of type TYP cover between them.
+    pub fn total_bytes(&self, typ: BootMemType) -> usize {
+        self.regions(typ).map(|entry| entry.len).sum()
+    }
+
+    /// The entry ADDR lies in, if any.
+    pub fn find(&self, addr: PhysAddr) -> Option<&MemmapEntry> {
+        self.iter().find(|entry| entry.contains(addr))
+    }
+}
+
+/// One line per entry, then the total size of every type present.
+impl fmt::Display for Memmap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "memmap: {} entries", self.entry_count)?;
+        for entry in self.iter() {
+            // An empty entry shows as ending where it starts.
+            let last = entry
+                .end()
+                .as_usize()
+                .saturating_sub(1)
+                .max(entry.base.as_usize());
+            writeln!(
+                f,
+                "  [{:#018x}-{:#018x}] {:>10} KiB {}",
+                entry.base.as_usize(),
+                last,
+                entry.len / 1024,
+                entry.typ
+            )?;
+        }
+        write!(f, "  total:")?;
+        let mut sep = "";
+        for typ in BootMemType::ALL {
+            let bytes = self.total_bytes(typ);
+            if bytes > 0 {
+                write!(f, "{} {} {} KiB", sep, typ, bytes / 1024)?;
+                sep = ",";
+            }
+        }
+        Ok(())
+    }
 }
 
 //impl Default for Entry {
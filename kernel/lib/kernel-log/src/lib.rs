@@ -2,7 +2,7 @@
 #![no_main]
 
 #[cfg(debug_assertions)]
-pub use kernel_serial::serial_println;
+pub use kernel_serial::{serial_println, serial_println_forced};
 
 #[macro_export]
 macro_rules! kprint {
@@ -20,4 +20,20 @@ macro_rules! kprintln {
         concat!($fmt, "\n"), $($arg)*));
 }
 
+/// `kprint!` for fatal paths, which cannot wait for a print they may have
+/// interrupted.
+#[macro_export]
+macro_rules! kprint_forced {
+    ($($arg:tt)*) => {
+        #[cfg(debug_assertions)]
+        kernel_log::serial_println_forced(format_args!($($arg)*));
+    };
+}
 
+#[macro_export]
+macro_rules! kprintln_forced {
+    () => (kernel_log::kprint_forced!("\n"));
+    ($fmt:expr) => (kernel_log::kprint_forced!(concat!($fmt, "\n")));
+    ($fmt:expr, $($arg:tt)*) => (kernel_log::kprint_forced!(
+        concat!($fmt, "\n"), $($arg)*));
+}
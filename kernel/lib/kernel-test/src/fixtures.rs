@@ -0,0 +1,38 @@
+//! Fake boot and firmware data, for tests to feed to the code that parses it.
+
+use kernel_boot_interface::memmap::{BootMemType, Memmap, MemmapEntry, MAX_MEM_REGIONS};
+use teensy_std::addr::PhysAddr;
+
+/// Length of the header every ACPI table but the RSDP starts with.
+const SDT_HEADER_LEN: usize = 36;
+
+/// A memory map of ENTRIES, each a base, a length and a type, in the order
+/// given.
+pub fn fake_memmap(entries: &[(usize, usize, BootMemType)]) -> Memmap {
+    let mut memmap = Memmap {
+        entries: [MemmapEntry {
+            base: PhysAddr::zero(),
+            len: 0,
+            typ: BootMemType::Reserved,
+        }; MAX_MEM_REGIONS],
+        entry_count: entries.len(),
+    };
+    for (slot, &(base, len, typ)) in memmap.entries.iter_mut().zip(entries) {
+        *slot = MemmapEntry {
+            base: PhysAddr::new(base),
+            len,
+            typ,
+        };
+    }
+    memmap
+}
+
+/// An ACPI table with SIGNATURE, all LEN bytes of it, with only the header's
+/// signature and length filled in. The checksum is not set.
+pub fn fake_acpi_table<const LEN: usize>(signature: &[u8; 4]) -> [u8; LEN] {
+    assert!(LEN >= SDT_HEADER_LEN);
+    let mut table = [0; LEN];
+    table[..4].copy_from_slice(signature);
+    table[4..8].copy_from_slice(&(LEN as u32).to_le_bytes());
+    table
+}
@@ -1,7 +1,11 @@
 #![no_std]
 
+pub mod fixtures;
+
+use core::fmt::{self, Write};
+
 use kernel_boot_interface::BootInfo;
-use kernel_log::{kprint, kprintln};
+use kernel_log::{kprint, kprintln, kprintln_forced};
 
 pub trait Testable {
     fn run(&self, boot_info: &BootInfo) -> ();
@@ -28,8 +32,47 @@ pub fn test_runner(tests: &[&dyn Testable]) {
 }
 
 pub fn panic(info: &core::panic::PanicInfo) -> ! {
-    kprintln!("[FAILED]");
-    kprintln!("{:?}", info);
+    kprintln_forced!("[FAILED]");
+    kprintln_forced!("{:?}", info);
     kernel_shutdown::shutdown(kernel_shutdown::ShutdownExitCode::Failed);
     kernel_cpu::hcf();
 }
+
+/// Ends a test that is meant to panic. It passes if the panic message
+/// contains EXPECTED and fails otherwise.
+pub fn expect_panic(info: &core::panic::PanicInfo, expected: &str) -> ! {
+    let mut message = MessageBuffer {
+        bytes: [0; MESSAGE_BUFFER_SIZE],
+        len: 0,
+    };
+    let _ = write!(message, "{}", info.message());
+    let message = &message.bytes[..message.len];
+    let found = expected.is_empty()
+        || message
+            .windows(expected.len())
+            .any(|window| window == expected.as_bytes());
+    if !found {
+        panic(info);
+    }
+    kprintln!("[ok]");
+    kernel_shutdown::shutdown(kernel_shutdown::ShutdownExitCode::Success);
+    kernel_cpu::hcf();
+}
+
+/// Bytes of a panic message `expect_panic` looks at.
+const MESSAGE_BUFFER_SIZE: usize = 512;
+
+/// Keeps the start of formatted output and drops the rest.
+struct MessageBuffer {
+    bytes: [u8; MESSAGE_BUFFER_SIZE],
+    len: usize,
+}
+
+impl Write for MessageBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let count = s.len().min(self.bytes.len() - self.len);
+        self.bytes[self.len..self.len + count].copy_from_slice(&s.as_bytes()[..count]);
+        self.len += count;
+        Ok(())
+    }
+}
@@ -8,11 +8,19 @@ pub struct Bitmap<'a> {
 
 /// Represents the bitmap with a range that can act on it
 /// start is inclusive, end is exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct BitmapRange {
     start: usize,
     end: usize,
 }
 
+/// Iterator over the maximal runs of set or clear bits, see `Bitmap::runs`.
+pub struct Runs<'b, 'a> {
+    bmap: &'b Bitmap<'a>,
+    next: usize,
+    is_set: bool,
+}
+
 impl<'a> Bitmap<'a> {
     /// LEN is passed in bits
     pub fn new(bits: &'a mut [u64], len: usize) -> Self {
@@ -44,54 +52,122 @@ impl<'a> Bitmap<'a> {
         return self.len;
     }
 
-    /// Returns a BitmapRange for the first range that can be flipped
-    pub fn find_first_fit(&self, size: usize, is_set: bool) -> Option<BitmapRange> {
-        debug_assert!(size != 0);
-        let mut start = None;
-        let mut end = 0;
-        for i in 0..(self.len()) {
-            if self.get(i) == is_set {
-                let beginning = if let Some(val) = start {
-                    end += 1;
-                    val
-                } else {
-                    start = Some(i);
-                    end = i + 1;
-                    i
-                };
-                if end - beginning >= size {
-                    return Some(BitmapRange {
-                        start: beginning,
-                        end,
-                    });
-                }
-            } else {
-                start = None
-            }
-        }
-        None
+    /// Sets every bit in RANGE
+    pub fn set_range(&mut self, range: &BitmapRange) {
+        self.update_range(range, |word, mask| *word |= mask);
+    }
+
+    /// Clears every bit in RANGE
+    pub fn clear_range(&mut self, range: &BitmapRange) {
+        self.update_range(range, |word, mask| *word &= !mask);
     }
 
+    /// Inverts every bit in RANGE
     pub fn flip_range(&mut self, range: &BitmapRange) {
-        debug_assert!(range.start < self.len());
+        self.update_range(range, |word, mask| *word ^= mask);
+    }
+
+    /// Whether every bit in RANGE is IS_SET
+    pub fn all(&self, range: &BitmapRange, is_set: bool) -> bool {
         debug_assert!(range.end <= self.len());
-        debug_assert!(self.all_same(range));
+        self.find_next(range.start, !is_set)
+            .is_none_or(|idx| idx >= range.end)
+    }
 
-        let start_idx = range.start / WORD_SIZE_BITS;
-        let end_idx = range.end / WORD_SIZE_BITS;
+    /// Number of set bits
+    pub fn count_ones(&self) -> usize {
+        let full_words = self.len / WORD_SIZE_BITS;
+        let tail_bits = self.len % WORD_SIZE_BITS;
+        let full: usize = self.bits[..full_words]
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum();
+        let tail = if tail_bits == 0 {
+            0
+        } else {
+            (self.bits[full_words] & mask(0, tail_bits)).count_ones() as usize
+        };
+        full + tail
+    }
 
-        let leading_bits = !((1 << (range.start % WORD_SIZE_BITS)) - 1);
-        let trailing_bits = (1 << (range.end % WORD_SIZE_BITS)) - 1;
+    /// Index of the first bit at or after FROM that is IS_SET. Whole words
+    /// without such a bit are skipped.
+    pub fn find_next(&self, from: usize, is_set: bool) -> Option<usize> {
+        if from >= self.len {
+            return None;
+        }
+        let mut word_idx = from / WORD_SIZE_BITS;
+        let mut word = self.word(word_idx, is_set) & (!0u64 << (from % WORD_SIZE_BITS));
+        while word == 0 {
+            word_idx += 1;
+            if word_idx * WORD_SIZE_BITS >= self.len {
+                return None;
+            }
+            word = self.word(word_idx, is_set);
+        }
+        let idx = word_idx * WORD_SIZE_BITS + word.trailing_zeros() as usize;
+        (idx < self.len).then_some(idx)
+    }
 
-        if start_idx == end_idx {
-            self.bits[start_idx] ^= leading_bits & trailing_bits;
-        } else {
-            self.bits[start_idx] ^= leading_bits;
-            for i in (start_idx + 1)..end_idx {
-                self.bits[i] ^= !0u64;
+    /// Index of the last bit that is IS_SET
+    pub fn find_last(&self, is_set: bool) -> Option<usize> {
+        if self.len == 0 {
+            return None;
+        }
+        let mut word_idx = (self.len - 1) / WORD_SIZE_BITS;
+        let mut word = self.word(word_idx, is_set) & mask(0, (self.len - 1) % WORD_SIZE_BITS + 1);
+        while word == 0 {
+            word_idx = word_idx.checked_sub(1)?;
+            word = self.word(word_idx, is_set);
+        }
+        Some(word_idx * WORD_SIZE_BITS + (WORD_SIZE_BITS - 1 - word.leading_zeros() as usize))
+    }
+
+    /// Iterates over the maximal runs of bits which are IS_SET, in order.
+    pub fn runs(&self, is_set: bool) -> Runs<'_, 'a> {
+        Runs {
+            bmap: self,
+            next: 0,
+            is_set,
+        }
+    }
+
+    pub fn set_runs(&self) -> Runs<'_, 'a> {
+        self.runs(true)
+    }
+
+    pub fn clear_runs(&self) -> Runs<'_, 'a> {
+        self.runs(false)
+    }
+
+    /// Returns a BitmapRange for the first range that can be flipped
+    pub fn find_first_fit(&self, size: usize, is_set: bool) -> Option<BitmapRange> {
+        self.find_first_fit_aligned(size, 1, is_set)
+    }
+
+    /// Returns the first range of SIZE bits which are all IS_SET and whose
+    /// start is a multiple of ALIGN, a power of two. There is none of zero
+    /// bits.
+    pub fn find_first_fit_aligned(
+        &self,
+        size: usize,
+        align: usize,
+        is_set: bool,
+    ) -> Option<BitmapRange> {
+        debug_assert!(align.is_power_of_two());
+        if size == 0 {
+            return None;
+        }
+        let mut start = self.find_next(0, is_set)?;
+        loop {
+            start = start.next_multiple_of(align);
+            let end = start.checked_add(size)?;
+            if end > self.len {
+                return None;
             }
-            if end_idx < self.bits.len() {
-                self.bits[end_idx] ^= trailing_bits;
+            match self.find_next(start, !is_set) {
+                Some(mismatch) if mismatch < end => start = self.find_next(mismatch, is_set)?,
+                _ => return Some(BitmapRange { start, end }),
             }
         }
     }
@@ -105,12 +181,35 @@ impl<'a> Bitmap<'a> {
         }
     }
 
-    fn all_same(&self, range: &BitmapRange) -> bool {
-        let mut bits = (range.start..range.end).map(|idx| self.get(idx));
-        if let Some(first) = bits.next() {
-            bits.all(|bit| bit == first)
+    /// Word IDX, inverted when looking for clear bits.
+    fn word(&self, idx: usize, is_set: bool) -> u64 {
+        if is_set {
+            self.bits[idx]
         } else {
-            true
+            !self.bits[idx]
+        }
+    }
+
+    /// Calls OP with every word RANGE touches and the mask of its bits in it.
+    fn update_range(&mut self, range: &BitmapRange, op: impl Fn(&mut u64, u64)) {
+        debug_assert!(range.end <= self.len());
+        if range.start >= range.end {
+            return;
+        }
+        let first = range.start / WORD_SIZE_BITS;
+        let last = (range.end - 1) / WORD_SIZE_BITS;
+        for idx in first..=last {
+            let low = if idx == first {
+                range.start % WORD_SIZE_BITS
+            } else {
+                0
+            };
+            let high = if idx == last {
+                (range.end - 1) % WORD_SIZE_BITS + 1
+            } else {
+                WORD_SIZE_BITS
+            };
+            op(&mut self.bits[idx], mask(low, high));
         }
     }
 }
@@ -119,6 +218,46 @@ impl BitmapRange {
     pub fn new(start: usize, end: usize) -> Self {
         Self { start, end }
     }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start >= self.end
+    }
+}
+
+impl<'b, 'a> Iterator for Runs<'b, 'a> {
+    type Item = BitmapRange;
+
+    fn next(&mut self) -> Option<BitmapRange> {
+        let start = self.bmap.find_next(self.next, self.is_set)?;
+        let end = self
+            .bmap
+            .find_next(start, !self.is_set)
+            .unwrap_or(self.bmap.len());
+        self.next = end;
+        Some(BitmapRange { start, end })
+    }
+}
+
+/// Bits [LOW, HIGH) of a word.
+fn mask(low: usize, high: usize) -> u64 {
+    let below_high = if high == WORD_SIZE_BITS {
+        !0u64
+    } else {
+        (1u64 << high) - 1
+    };
+    below_high & !((1u64 << low) - 1)
 }
 
 #[cfg(test)]
@@ -180,4 +319,125 @@ mod tests {
 
         assert_eq!(bmap.find_and_flip(53, false), None);
     }
+
+    #[test_case]
+    fn alloc_across_words(_boot_info: &BootInfo) {
+        let mut buf = [0u64; 3];
+        let mut bmap = Bitmap::new(&mut buf, 3 * WORD_SIZE_BITS);
+        bmap.set_range(&BitmapRange::new(0, 60));
+        // The run of 8 starts in the first word and ends in the second.
+        assert_eq!(bmap.find_and_flip(8, false), Some(60));
+        assert!(bmap.all(&BitmapRange::new(0, 68), true));
+        assert!(!bmap.get(68));
+
+        // Only the last word is left, apart from the bits 68..128.
+        bmap.set_range(&BitmapRange::new(68, 128));
+        assert_eq!(bmap.find_and_flip(64, false), Some(128));
+        assert_eq!(bmap.find_and_flip(1, false), None);
+    }
+
+    #[test_case]
+    fn alloc_nothing(_boot_info: &BootInfo) {
+        let mut buf = [0u64; 1];
+        let mut bmap = Bitmap::new(&mut buf, WORD_SIZE_BITS);
+        assert_eq!(bmap.find_first_fit_aligned(0, 8, false), None);
+        assert_eq!(bmap.find_and_flip(0, false), None);
+        assert_eq!(bmap.count_ones(), 0);
+    }
+
+    #[test_case]
+    fn alloc_odd_len(_boot_info: &BootInfo) {
+        let mut buf = [0u64; 2];
+        let mut bmap = Bitmap::new(&mut buf, 100);
+        assert_eq!(bmap.find_and_flip(100, false), Some(0));
+        assert_eq!(bmap.count_ones(), 100);
+        // Bits past the end are never handed out, even once filled.
+        bmap.fill(false);
+        assert_eq!(bmap.find_and_flip(101, false), None);
+        bmap.fill(true);
+        assert_eq!(bmap.find_next(0, true), Some(0));
+        assert_eq!(bmap.find_last(true), Some(99));
+        assert_eq!(bmap.count_ones(), 100);
+    }
+
+    #[test_case]
+    fn set_and_clear_range(_boot_info: &BootInfo) {
+        let mut buf = [0u64; 4];
+        let mut bmap = Bitmap::new(&mut buf, 4 * WORD_SIZE_BITS);
+        bmap.set_range(&BitmapRange::new(10, 200));
+        assert_eq!(bmap.count_ones(), 190);
+        bmap.clear_range(&BitmapRange::new(63, 129));
+        assert_eq!(bmap.count_ones(), 190 - 66);
+        assert!(bmap.get(62) && !bmap.get(63) && !bmap.get(128) && bmap.get(129));
+
+        // Ranges need not be uniform any more.
+        bmap.flip_range(&BitmapRange::new(0, 256));
+        assert_eq!(bmap.count_ones(), 256 - (190 - 66));
+        bmap.clear_range(&BitmapRange::new(5, 5));
+        assert!(bmap.get(5));
+    }
+
+    #[test_case]
+    fn find_next_and_last(_boot_info: &BootInfo) {
+        let mut buf = [0u64; 4];
+        let mut bmap = Bitmap::new(&mut buf, 4 * WORD_SIZE_BITS);
+        assert_eq!(bmap.find_next(0, true), None);
+        assert_eq!(bmap.find_last(true), None);
+        assert_eq!(bmap.find_last(false), Some(255));
+
+        bmap.set(3);
+        bmap.set(191);
+        assert_eq!(bmap.find_next(0, true), Some(3));
+        assert_eq!(bmap.find_next(4, true), Some(191));
+        assert_eq!(bmap.find_next(192, true), None);
+        assert_eq!(bmap.find_last(true), Some(191));
+        assert_eq!(bmap.find_next(3, false), Some(4));
+    }
+
+    #[test_case]
+    fn iterate_runs(_boot_info: &BootInfo) {
+        let mut buf = [0u64; 3];
+        let mut bmap = Bitmap::new(&mut buf, 150);
+        bmap.set_range(&BitmapRange::new(0, 2));
+        bmap.set_range(&BitmapRange::new(60, 130));
+        bmap.set(149);
+
+        let mut set = bmap.set_runs();
+        assert_eq!(set.next(), Some(BitmapRange::new(0, 2)));
+        assert_eq!(set.next(), Some(BitmapRange::new(60, 130)));
+        assert_eq!(set.next(), Some(BitmapRange::new(149, 150)));
+        assert_eq!(set.next(), None);
+
+        let mut clear = bmap.clear_runs();
+        assert_eq!(clear.next(), Some(BitmapRange::new(2, 60)));
+        assert_eq!(clear.next(), Some(BitmapRange::new(130, 149)));
+        assert_eq!(clear.next(), None);
+    }
+
+    #[test_case]
+    fn first_fit_aligned(_boot_info: &BootInfo) {
+        let mut buf = [0u64; 4];
+        let mut bmap = Bitmap::new(&mut buf, 4 * WORD_SIZE_BITS);
+        bmap.set(1);
+        assert_eq!(
+            bmap.find_first_fit_aligned(4, 64, false),
+            Some(BitmapRange::new(64, 68))
+        );
+        assert_eq!(
+            bmap.find_first_fit_aligned(2, 2, false),
+            Some(BitmapRange::new(2, 4))
+        );
+
+        bmap.set(130);
+        assert_eq!(
+            bmap.find_first_fit_aligned(64, 64, false),
+            Some(BitmapRange::new(64, 128))
+        );
+        bmap.set(64);
+        assert_eq!(
+            bmap.find_first_fit_aligned(64, 64, false),
+            Some(BitmapRange::new(192, 256))
+        );
+        assert_eq!(bmap.find_first_fit_aligned(65, 64, false), None);
+    }
 }
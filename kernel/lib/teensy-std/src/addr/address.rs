@@ -0,0 +1,165 @@
+use core::{
+    fmt,
+    ops::{Add, AddAssign, Sub, SubAssign},
+};
+
+macro_rules! address_type {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+        #[repr(transparent)]
+        pub struct $name(usize);
+
+        impl $name {
+            /// Panics if ADDR is not a valid address of this kind.
+            pub const fn new(addr: usize) -> Self {
+                match Self::try_new(addr) {
+                    Some(addr) => addr,
+                    None => panic!(concat!("Invalid ", stringify!($name))),
+                }
+            }
+
+            pub const fn try_new(addr: usize) -> Option<Self> {
+                if Self::is_valid(addr) {
+                    Some(Self(addr))
+                } else {
+                    None
+                }
+            }
+
+            pub const fn zero() -> Self {
+                Self(0)
+            }
+
+            pub const fn as_usize(self) -> usize {
+                self.0
+            }
+
+            /// ALIGN must be a power of two.
+            pub const fn is_aligned(self, align: usize) -> bool {
+                self.0 & (align - 1) == 0
+            }
+
+            pub const fn align_down(self, align: usize) -> Self {
+                Self::new(self.0 & !(align - 1))
+            }
+
+            /// Panics if the result does not fit, see `checked_align_up`.
+            pub const fn align_up(self, align: usize) -> Self {
+                match self.checked_align_up(align) {
+                    Some(addr) => addr,
+                    None => panic!(concat!(stringify!($name), " align_up overflow")),
+                }
+            }
+
+            pub const fn checked_align_up(self, align: usize) -> Option<Self> {
+                match self.0.checked_add(align - 1) {
+                    Some(addr) => Self::try_new(addr & !(align - 1)),
+                    None => None,
+                }
+            }
+
+            pub const fn checked_add(self, rhs: usize) -> Option<Self> {
+                match self.0.checked_add(rhs) {
+                    Some(addr) => Self::try_new(addr),
+                    None => None,
+                }
+            }
+
+            pub const fn checked_sub(self, rhs: usize) -> Option<Self> {
+                match self.0.checked_sub(rhs) {
+                    Some(addr) => Self::try_new(addr),
+                    None => None,
+                }
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, concat!(stringify!($name), "({:#x})"), self.0)
+            }
+        }
+
+        impl fmt::LowerHex for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::LowerHex::fmt(&self.0, f)
+            }
+        }
+
+        impl Add<usize> for $name {
+            type Output = Self;
+            fn add(self, rhs: usize) -> Self {
+                Self::new(self.0 + rhs)
+            }
+        }
+
+        impl AddAssign<usize> for $name {
+            fn add_assign(&mut self, rhs: usize) {
+                *self = *self + rhs;
+            }
+        }
+
+        impl Sub<usize> for $name {
+            type Output = Self;
+            fn sub(self, rhs: usize) -> Self {
+                Self::new(self.0 - rhs)
+            }
+        }
+
+        impl SubAssign<usize> for $name {
+            fn sub_assign(&mut self, rhs: usize) {
+                *self = *self - rhs;
+            }
+        }
+
+        /// Distance in bytes between two addresses.
+        impl Sub<$name> for $name {
+            type Output = usize;
+            fn sub(self, rhs: $name) -> usize {
+                self.0 - rhs.0
+            }
+        }
+    };
+}
+
+address_type!(
+    /// An address in physical memory. It can't be dereferenced, it needs to
+    /// go through the HHDM or a mapping first.
+    PhysAddr
+);
+
+address_type!(
+    /// An address in the virtual address space.
+    VirtAddr
+);
+
+/// Bits of a virtual address the page tables translate, with four levels.
+pub const VIRT_ADDR_BITS: u32 = 48;
+
+impl PhysAddr {
+    /// Any physical address can be named, whether memory backs it or not.
+    const fn is_valid(_addr: usize) -> bool {
+        true
+    }
+}
+
+impl VirtAddr {
+    /// ADDR is canonical: the bits above the translated ones copy the
+    /// highest translated bit, or the CPU raises #GP on using it.
+    const fn is_valid(addr: usize) -> bool {
+        let unused_bits = usize::BITS - VIRT_ADDR_BITS;
+        ((addr << unused_bits) as isize >> unused_bits) as usize == addr
+    }
+
+    pub fn from_ptr<T>(ptr: *const T) -> Self {
+        Self::new(ptr as usize)
+    }
+
+    pub const fn as_ptr<T>(self) -> *const T {
+        self.0 as *const T
+    }
+
+    pub const fn as_mut_ptr<T>(self) -> *mut T {
+        self.0 as *mut T
+    }
+}
@@ -0,0 +1,84 @@
+use core::ops::{Add, Sub};
+
+use super::{PhysAddr, VirtAddr};
+
+/// Granularity of physical frames and virtual pages.
+pub const PAGE_SIZE: usize = 4096;
+
+macro_rules! frame_type {
+    ($(#[$meta:meta])* $name:ident, $addr:ident) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub struct $name {
+            start: $addr,
+        }
+
+        impl $name {
+            /// Returns None unless START is aligned to PAGE_SIZE.
+            pub const fn from_start(start: $addr) -> Option<Self> {
+                if start.is_aligned(PAGE_SIZE) {
+                    Some(Self { start })
+                } else {
+                    None
+                }
+            }
+
+            pub const fn containing(addr: $addr) -> Self {
+                Self {
+                    start: addr.align_down(PAGE_SIZE),
+                }
+            }
+
+            pub const fn from_number(number: usize) -> Self {
+                Self {
+                    start: $addr::new(number * PAGE_SIZE),
+                }
+            }
+
+            pub const fn start(self) -> $addr {
+                self.start
+            }
+
+            /// Page number, the start address divided by PAGE_SIZE.
+            pub const fn number(self) -> usize {
+                self.start.as_usize() / PAGE_SIZE
+            }
+        }
+
+        /// Moves forward by a number of pages.
+        impl Add<usize> for $name {
+            type Output = Self;
+            fn add(self, rhs: usize) -> Self {
+                Self::from_number(self.number() + rhs)
+            }
+        }
+
+        /// Moves back by a number of pages.
+        impl Sub<usize> for $name {
+            type Output = Self;
+            fn sub(self, rhs: usize) -> Self {
+                Self::from_number(self.number() - rhs)
+            }
+        }
+
+        /// Number of pages between two pages.
+        impl Sub<$name> for $name {
+            type Output = usize;
+            fn sub(self, rhs: $name) -> usize {
+                self.number() - rhs.number()
+            }
+        }
+    };
+}
+
+frame_type!(
+    /// A PAGE_SIZE chunk of physical memory.
+    PhysFrame,
+    PhysAddr
+);
+
+frame_type!(
+    /// A PAGE_SIZE chunk of the virtual address space.
+    Page,
+    VirtAddr
+);